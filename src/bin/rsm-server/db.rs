@@ -0,0 +1,602 @@
+use std::os::unix::fs::OpenOptionsExt;
+use std::io::prelude::*;
+use std::path::Path;
+use std::fs;
+use sqlite::{self, Connection, Value, State};
+use sha2::{Sha256, Digest};
+use failure::*;
+use json::JsonValue;
+
+// lifetime of access and refresh tokens in seconds
+static ACCESS_TOKEN_LIFETIME: i64 = 60 * 60;
+static REFRESH_TOKEN_LIFETIME: i64 = 30 * 24 * 60 * 60;
+// rounds of SHA-256 applied to salted passwords
+static PASSWORD_ROUNDS: usize = 100000;
+
+// Ordered list of schema migrations, see the client database for details.
+// Every row carries the time the server received it so clients can ask for
+// the changes since their last sync.
+static MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE metadata(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        key VARCHAR(32),
+        value TEXT,
+        UNIQUE(key) ON CONFLICT REPLACE
+    );
+    CREATE TABLE users(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name VARCHAR(64) UNIQUE,
+        salt CHAR(32) NOT NULL,
+        password_hash CHAR(64) NOT NULL,
+        created INTEGER NOT NULL
+    );
+    CREATE TABLE tokens(
+        token CHAR(64) PRIMARY KEY,
+        user_id INTEGER REFERENCES users(id),
+        kind VARCHAR(8) NOT NULL,
+        expires INTEGER NOT NULL
+    );
+    CREATE TABLE snippets(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER REFERENCES users(id),
+        uuid CHAR(36) NOT NULL,
+        name VARCHAR(64),
+        content TEXT,
+        deleted INTEGER DEFAULT 0,
+        last_updated INTEGER NOT NULL,
+        received INTEGER NOT NULL,
+        UNIQUE(user_id, uuid)
+    );
+    CREATE TABLE tags(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER REFERENCES users(id),
+        name VARCHAR(64),
+        deleted INTEGER DEFAULT 0,
+        last_updated INTEGER NOT NULL,
+        received INTEGER NOT NULL,
+        UNIQUE(user_id, name)
+    );
+    CREATE TABLE snippet_tags(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER REFERENCES users(id),
+        snippet_uuid CHAR(36) NOT NULL,
+        tag_name VARCHAR(64),
+        deleted INTEGER DEFAULT 0,
+        last_updated INTEGER NOT NULL,
+        received INTEGER NOT NULL,
+        UNIQUE(user_id, snippet_uuid, tag_name)
+    );
+    CREATE TABLE tombstones(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER REFERENCES users(id),
+        snippet_uuid CHAR(36) NOT NULL,
+        name VARCHAR(64),
+        last_updated INTEGER NOT NULL,
+        received INTEGER NOT NULL,
+        UNIQUE(user_id, snippet_uuid) ON CONFLICT IGNORE
+    );",
+    // 2: description, source and creation time of snippets
+    "ALTER TABLE snippets ADD COLUMN description TEXT;
+    ALTER TABLE snippets ADD COLUMN source TEXT;
+    ALTER TABLE snippets ADD COLUMN created_at INTEGER;
+    UPDATE snippets SET created_at = last_updated;",
+    // 3: revision counter of snippets deciding which upload wins
+    "ALTER TABLE snippets ADD COLUMN version INTEGER NOT NULL DEFAULT 0;",
+    // 4: language of snippets
+    "ALTER TABLE snippets ADD COLUMN language VARCHAR(32);",
+];
+
+pub struct Credentials {
+    pub token: String,
+    pub refresh_token: String,
+    pub expires: i64,
+}
+
+pub enum TokenStatus {
+    Valid(i64),
+    Expired,
+    Unknown,
+}
+
+pub fn connect(db_file: &Path) -> Result<Connection, Error> {
+    if !db_file.exists() {
+        // only the owner may read the password hashes and tokens
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(db_file)
+            .context(format!("failed to create database file: {:?}", db_file))?;
+    }
+
+    let mut conn = sqlite::open(db_file.to_str().unwrap())
+        .map_err::<Error, _>(|e| e.into())?;
+
+    // every connection is handled by its own thread
+    conn.set_busy_timeout(5000)
+        .context("failed to set busy timeout")?;
+
+    conn.execute("PRAGMA foreign_keys = ON")
+        .context("failed to enable foreign_key support")?;
+
+    migrate(&conn)
+        .context("failed to migrate database")?;
+
+    Ok(conn)
+}
+
+// Run a closure in a write transaction. The lock is taken up front so
+// concurrent syncs wait for each other instead of failing on upgrade.
+pub fn transaction<T, F>(conn: &Connection, f: F) -> Result<T, Error>
+    where F: FnOnce(&Connection) -> Result<T, Error> {
+    conn.execute("BEGIN IMMEDIATE")
+        .context("failed to begin transaction")?;
+
+    match f(conn) {
+        Ok(x) => {
+            conn.execute("COMMIT")
+                .context("failed to commit transaction")?;
+            Ok(x)
+        },
+        Err(e) => {
+            conn.execute("ROLLBACK").ok();
+            Err(e)
+        }
+    }
+}
+
+// create a user or replace the password of an existing one
+pub fn set_user(conn: &Connection, name: &str, password: &str) -> Result<(), Error> {
+    let salt = random_hex(16)
+        .context("failed to generate salt")?;
+    let hash = hash_password(salt.as_str(), password);
+
+    let mut statement = conn.prepare(
+        "INSERT INTO `users` (name, salt, password_hash, created)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(name) DO UPDATE
+        SET salt = excluded.salt, password_hash = excluded.password_hash")
+        .context("failed to prepare user save statement")?;
+
+    statement.bind(1, name)
+        .context("failed to bind user name")?;
+    statement.bind(2, salt.as_str())
+        .context("failed to bind salt")?;
+    statement.bind(3, hash.as_str())
+        .context("failed to bind password hash")?;
+    statement.bind(4, get_utc_now())
+        .context("failed to bind time")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    // a new password invalidates every session
+    let mut statement = conn.prepare(
+        "DELETE FROM `tokens` WHERE user_id IN (
+            SELECT id FROM `users` WHERE name = ?
+        )")
+        .context("failed to prepare token delete statement")?;
+
+    statement.bind(1, name)
+        .context("failed to bind user name")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(())
+}
+
+// return the id of the user if the password is correct
+pub fn check_password(conn: &Connection, name: &str, password: &str) -> Result<Option<i64>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT id, salt, password_hash FROM `users`
+        WHERE name = ?")
+        .context("failed to prepare user lookup statement")?;
+
+    statement.bind(1, name)
+        .context("failed to bind user name")?;
+
+    if statement.next().context("failed to execute sql statement")? == State::Done {
+        return Ok(None);
+    }
+
+    let user_id = statement.read::<i64>(0)
+        .context("failed to read user id")?;
+    let salt = statement.read::<String>(1)
+        .context("failed to read salt")?;
+    let hash = statement.read::<String>(2)
+        .context("failed to read password hash")?;
+
+    if !constant_time_eq(hash.as_bytes(), hash_password(salt.as_str(), password).as_bytes()) {
+        return Ok(None);
+    }
+
+    Ok(Some(user_id))
+}
+
+pub fn create_credentials(conn: &Connection, user_id: i64) -> Result<Credentials, Error> {
+    let now = get_utc_now();
+
+    let credentials = Credentials {
+        token: random_hex(32).context("failed to generate token")?,
+        refresh_token: random_hex(32).context("failed to generate refresh token")?,
+        expires: now + ACCESS_TOKEN_LIFETIME,
+    };
+
+    let mut statement = conn.prepare(
+        "INSERT INTO `tokens` (token, user_id, kind, expires)
+        VALUES (?, ?, 'access', ?), (?, ?, 'refresh', ?)")
+        .context("failed to prepare token save statement")?;
+
+    statement.bind(1, credentials.token.as_str())
+        .context("failed to bind token")?;
+    statement.bind(2, user_id)
+        .context("failed to bind user id")?;
+    statement.bind(3, credentials.expires)
+        .context("failed to bind expiry time")?;
+    statement.bind(4, credentials.refresh_token.as_str())
+        .context("failed to bind refresh token")?;
+    statement.bind(5, user_id)
+        .context("failed to bind user id")?;
+    statement.bind(6, now + REFRESH_TOKEN_LIFETIME)
+        .context("failed to bind expiry time")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    // forget tokens nobody can use anymore
+    let mut statement = conn.prepare("DELETE FROM `tokens` WHERE expires < ?")
+        .context("failed to prepare token delete statement")?;
+
+    statement.bind(1, now - REFRESH_TOKEN_LIFETIME)
+        .context("failed to bind time")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(credentials)
+}
+
+pub fn check_token(conn: &Connection, token: &str, kind: &str) -> Result<TokenStatus, Error> {
+    let mut statement = conn.prepare(
+        "SELECT user_id, expires FROM `tokens`
+        WHERE token = ? AND kind = ?")
+        .context("failed to prepare token lookup statement")?;
+
+    statement.bind(1, token)
+        .context("failed to bind token")?;
+    statement.bind(2, kind)
+        .context("failed to bind token kind")?;
+
+    if statement.next().context("failed to execute sql statement")? == State::Done {
+        return Ok(TokenStatus::Unknown);
+    }
+
+    let user_id = statement.read::<i64>(0)
+        .context("failed to read user id")?;
+    let expires = statement.read::<i64>(1)
+        .context("failed to read expiry time")?;
+
+    if expires < get_utc_now() {
+        return Ok(TokenStatus::Expired);
+    }
+
+    Ok(TokenStatus::Valid(user_id))
+}
+
+// refresh tokens can only be used once
+pub fn delete_token(conn: &Connection, token: &str) -> Result<(), Error> {
+    let mut statement = conn.prepare("DELETE FROM `tokens` WHERE token = ?")
+        .context("failed to prepare token delete statement")?;
+
+    statement.bind(1, token)
+        .context("failed to bind token")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(())
+}
+
+// Return the rows of a user the server received after `since` in the format
+// the client sends them.
+pub fn get_changes(conn: &Connection, user_id: i64, since: i64) -> Result<JsonValue, Error> {
+    let queries = vec![
+        ("snippets", "SELECT uuid, name, content, language, description, source, deleted, created_at, version, last_updated
+            FROM `snippets`
+            WHERE user_id = ? AND received > ?",
+            vec!["uuid", "name", "content", "language", "description", "source", "deleted", "created_at", "version", "last_updated"]),
+        ("tags", "SELECT name, deleted, last_updated FROM `tags`
+            WHERE user_id = ? AND received > ?",
+            vec!["name", "deleted", "last_updated"]),
+        ("snippet_tags", "SELECT snippet_uuid, tag_name, deleted, last_updated FROM `snippet_tags`
+            WHERE user_id = ? AND received > ?",
+            vec!["snippet_uuid", "tag_name", "deleted", "last_updated"]),
+        ("tombstones", "SELECT snippet_uuid, name, last_updated FROM `tombstones`
+            WHERE user_id = ? AND received > ?",
+            vec!["snippet_uuid", "name", "last_updated"]),
+    ];
+
+    let mut changes = JsonValue::new_object();
+
+    for (table, query, columns) in queries {
+        let mut cursor = conn.prepare(query)
+            .context(format!("failed to prepare {} select statement", table))?
+            .cursor();
+
+        cursor.bind(&[Value::Integer(user_id), Value::Integer(since)])
+            .context("failed to bind values")?;
+
+        let mut rows = JsonValue::new_array();
+
+        while let Some(values) = cursor.next().context("failed to execute sql statement")? {
+            let mut row = JsonValue::new_object();
+
+            for (column, value) in columns.iter().zip(values.iter()) {
+                row[*column] = match *value {
+                    Value::Integer(x) => x.into(),
+                    Value::String(ref x) => x.as_str().into(),
+                    _ => JsonValue::Null,
+                };
+            }
+
+            rows.push(row)
+                .context("failed to build response rows")?;
+        }
+
+        changes[table] = rows;
+    }
+
+    Ok(changes)
+}
+
+// Merge the changes uploaded by a client. Rows only replace older versions,
+// snippets purged by any client stay purged. Snippets are replaced by higher
+// versions only, so the server wins when two clients changed the same one.
+pub fn save_changes(conn: &Connection, user_id: i64, data: &JsonValue) -> Result<(), Error> {
+    let now = get_utc_now();
+
+    let mut statement = conn.prepare(
+        "INSERT INTO `tombstones` (user_id, snippet_uuid, name, last_updated, received)
+        VALUES (?, ?, ?, ?, ?)")
+        .context("failed to prepare tombstone save statement")?
+        .cursor();
+
+    for row in data["tombstones"].members() {
+        let uuid = get_string(row, "snippet_uuid")?;
+
+        statement.bind(&[Value::Integer(user_id),
+                         Value::String(uuid.clone()),
+                         Value::String(get_string(row, "name")?),
+                         Value::Integer(get_i64(row, "last_updated")?),
+                         Value::Integer(now)])
+            .context("failed to bind values")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        for table in [("snippets", "uuid"), ("snippet_tags", "snippet_uuid")] {
+            let query = format!("DELETE FROM `{}` WHERE user_id = ? AND {} = ?", table.0, table.1);
+            let mut delete = conn.prepare(query)
+                .context("failed to prepare purge statement")?
+                .cursor();
+
+            delete.bind(&[Value::Integer(user_id), Value::String(uuid.clone())])
+                .context("failed to bind values")?;
+
+            delete.next()
+                .context("failed to execute sql statement")?;
+        }
+    }
+
+    let mut statement = conn.prepare(
+        "INSERT INTO `snippets` (user_id, uuid, name, content, deleted, last_updated, received,
+            description, source, created_at, version, language)
+        SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, IFNULL(?11, 0), ?12
+        WHERE NOT EXISTS (SELECT 1 FROM `tombstones` WHERE user_id = ?1 AND snippet_uuid = ?2)
+        ON CONFLICT(user_id, uuid) DO UPDATE
+        SET name = excluded.name, content = excluded.content, deleted = excluded.deleted,
+            last_updated = excluded.last_updated, received = excluded.received,
+            description = excluded.description, source = excluded.source,
+            created_at = MIN(snippets.created_at, excluded.created_at),
+            version = IFNULL(?11, snippets.version + 1), language = excluded.language
+        WHERE IFNULL(?11 > snippets.version, excluded.last_updated > snippets.last_updated)")
+        .context("failed to prepare snippet save statement")?
+        .cursor();
+
+    for row in data["snippets"].members() {
+        let last_updated = get_i64(row, "last_updated")?;
+
+        // clients from before languages, descriptions and creation times send
+        // none of them, the ones from before versions decide by update time
+        statement.bind(&[Value::Integer(user_id),
+                         Value::String(get_string(row, "uuid")?),
+                         Value::String(get_string(row, "name")?),
+                         Value::String(get_string(row, "content")?),
+                         Value::Integer(get_i64(row, "deleted")?),
+                         Value::Integer(last_updated),
+                         Value::Integer(now),
+                         get_optional_string(row, "description"),
+                         get_optional_string(row, "source"),
+                         Value::Integer(row["created_at"].as_i64().unwrap_or(last_updated)),
+                         get_optional_i64(row, "version"),
+                         get_optional_string(row, "language")])
+            .context("failed to bind values")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+    }
+
+    let mut statement = conn.prepare(
+        "INSERT INTO `tags` (user_id, name, deleted, last_updated, received)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(user_id, name) DO UPDATE
+        SET deleted = excluded.deleted, last_updated = excluded.last_updated,
+            received = excluded.received
+        WHERE excluded.last_updated > tags.last_updated")
+        .context("failed to prepare tag save statement")?
+        .cursor();
+
+    for row in data["tags"].members() {
+        statement.bind(&[Value::Integer(user_id),
+                         Value::String(get_string(row, "name")?),
+                         Value::Integer(get_i64(row, "deleted")?),
+                         Value::Integer(get_i64(row, "last_updated")?),
+                         Value::Integer(now)])
+            .context("failed to bind values")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+    }
+
+    let mut statement = conn.prepare(
+        "INSERT INTO `snippet_tags` (user_id, snippet_uuid, tag_name, deleted, last_updated, received)
+        SELECT ?1, ?2, ?3, ?4, ?5, ?6
+        WHERE NOT EXISTS (SELECT 1 FROM `tombstones` WHERE user_id = ?1 AND snippet_uuid = ?2)
+        ON CONFLICT(user_id, snippet_uuid, tag_name) DO UPDATE
+        SET deleted = excluded.deleted, last_updated = excluded.last_updated,
+            received = excluded.received
+        WHERE excluded.last_updated > snippet_tags.last_updated")
+        .context("failed to prepare snippet_tag save statement")?
+        .cursor();
+
+    for row in data["snippet_tags"].members() {
+        statement.bind(&[Value::Integer(user_id),
+                         Value::String(get_string(row, "snippet_uuid")?),
+                         Value::String(get_string(row, "tag_name")?),
+                         Value::Integer(get_i64(row, "deleted")?),
+                         Value::Integer(get_i64(row, "last_updated")?),
+                         Value::Integer(now)])
+            .context("failed to bind values")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+    }
+
+    Ok(())
+}
+
+fn get_i64(value: &JsonValue, key: &str) -> Result<i64, Error> {
+    value[key].as_i64()
+        .ok_or(format_err!("missing or invalid field: {}", key))
+}
+
+fn get_string(value: &JsonValue, key: &str) -> Result<String, Error> {
+    value[key].as_str()
+        .map(|x| x.to_string())
+        .ok_or(format_err!("missing or invalid field: {}", key))
+}
+
+// NULL for missing fields
+fn get_optional_string(value: &JsonValue, key: &str) -> Value {
+    match value[key].as_str() {
+        Some(x) => Value::String(x.to_string()),
+        None => Value::Null,
+    }
+}
+
+fn get_optional_i64(value: &JsonValue, key: &str) -> Value {
+    match value[key].as_i64() {
+        Some(x) => Value::Integer(x),
+        None => Value::Null,
+    }
+}
+
+pub fn get_utc_now() -> i64 {
+    ::time::now_utc().to_timespec().sec
+}
+
+fn random_hex(len: usize) -> Result<String, Error> {
+    let mut bytes = vec![0u8; len];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut x| x.read_exact(bytes.as_mut_slice()))
+        .context("failed to read /dev/urandom")?;
+
+    Ok(to_hex(bytes.as_slice()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<String>()
+}
+
+// salted and iterated to slow down brute forcing a stolen database
+fn hash_password(salt: &str, password: &str) -> String {
+    let mut hash = Sha256::digest(format!("{}{}", salt, password).as_bytes());
+
+    for _ in 1..PASSWORD_ROUNDS {
+        hash = Sha256::digest(hash.as_slice());
+    }
+
+    to_hex(hash.as_slice())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn migrate(conn: &Connection) -> Result<(), Error> {
+    let version = get_schema_version(conn)
+        .context("failed to get schema version")?;
+    let latest = MIGRATIONS.len() as i64;
+
+    if version > latest {
+        bail!("unsupported schema version {}, this server supports up to version {}", version, latest);
+    }
+
+    if version == latest {
+        return Ok(());
+    }
+
+    transaction(conn, |conn| {
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            conn.execute(migration)
+                .context(format!("failed to apply migration {}", i + 1))?;
+        }
+
+        let mut statement = conn.prepare(
+            "INSERT INTO `metadata` (key, value) VALUES ('schema_version', ?)")
+            .context("failed to prepare metadata save statement")?;
+
+        statement.bind(1, latest.to_string().as_str())
+            .context("failed to bind schema version")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        Ok(())
+    })
+}
+
+// a database without a metadata table is empty
+fn get_schema_version(conn: &Connection) -> Result<i64, Error> {
+    let mut statement = conn.prepare(
+        "SELECT COUNT(*) FROM `sqlite_master`
+        WHERE type = 'table' AND name = 'metadata'")
+        .context("failed to prepare table lookup statement")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    if statement.read::<i64>(0).context("failed to read table count")? == 0 {
+        return Ok(0);
+    }
+
+    let mut statement = conn.prepare(
+        "SELECT value FROM `metadata` WHERE key = 'schema_version'")
+        .context("failed to prepare metadata lookup statement")?;
+
+    if statement.next().context("failed to execute sql statement")? == State::Done {
+        return Ok(0);
+    }
+
+    let value = statement.read::<String>(0)
+        .context("failed to read schema version")?;
+
+    let version = value.parse::<i64>()
+        .context("failed to parse schema version")?;
+
+    Ok(version)
+}
@@ -0,0 +1,170 @@
+extern crate libc;
+extern crate clap;
+extern crate failure;
+extern crate sqlite;
+extern crate time;
+extern crate bufstream;
+extern crate native_tls;
+#[macro_use]
+extern crate json;
+extern crate sha2;
+
+mod db;
+mod server;
+
+use clap::{Arg, App, ArgMatches, AppSettings, SubCommand};
+use std::io::prelude::*;
+use std::path::Path;
+use std::{env, fs, io, mem};
+use std::os::unix::io::AsRawFd;
+use native_tls::Identity;
+use failure::*;
+
+// process cli arguments with clap
+fn process_cli<'a>() -> ArgMatches<'a> {
+    App::new("rsm-server")
+        .version("0.1")
+        .author("Cristian Kubis <cristian.kubis@tsunix.de>")
+        .about("Sync server for the rsm snippet manager")
+        .setting(AppSettings::DeriveDisplayOrder)
+        .setting(AppSettings::SubcommandRequired)
+        .arg(Arg::with_name("db")
+            .help("path of the server database file")
+            .long("--db")
+            .takes_value(true)
+            .default_value("rsm-server.db")
+            .global(true))
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Used to accept sync connections")
+                .arg(Arg::with_name("listen")
+                    .help("address to listen on")
+                    .short("-l")
+                    .long("--listen")
+                    .takes_value(true)
+                    .default_value("127.0.0.1:5000"))
+                .arg(Arg::with_name("identity")
+                    .help("PKCS#12 file with the server certificate and key")
+                    .short("-i")
+                    .long("--identity")
+                    .takes_value(true)
+                    .required(true)))
+        .subcommand(
+            SubCommand::with_name("adduser")
+                .about("Used to create a user or change its password")
+                .arg(Arg::with_name("user")
+                    .help("name of the user")
+                    .required(true)))
+        .get_matches()
+}
+
+fn run() -> Result<(), Error> {
+    let app_matches = process_cli();
+    let db_path = Path::new(app_matches.value_of("db").unwrap());
+
+    match app_matches.subcommand() {
+        ("serve", Some(sub_matches)) => {
+            let identity = load_identity(Path::new(sub_matches.value_of("identity").unwrap()))
+                .context("failed to load server identity")?;
+
+            // make sure the database can be opened before accepting connections
+            db::connect(db_path)
+                .context("failed to connect to database")?;
+
+            server::serve(sub_matches.value_of("listen").unwrap(), identity, db_path)
+        },
+        ("adduser", Some(sub_matches)) => {
+            let user = sub_matches.value_of("user").unwrap();
+            let password = read_password("Password: ")
+                .context("failed to read password")?;
+
+            if password.is_empty() {
+                bail!("the password must not be empty");
+            }
+
+            let conn = db::connect(db_path)
+                .context("failed to connect to database")?;
+
+            db::set_user(&conn, user, password.as_str())
+                .context("failed to save user")?;
+
+            println!("Saved user {}", user);
+
+            Ok(())
+        },
+        _ => panic!("unexpected error"),
+    }
+}
+
+// the password of the PKCS#12 file is taken from the environment so it does
+// not show up in the process list
+fn load_identity(path: &Path) -> Result<Identity, Error> {
+    let mut der = Vec::new();
+    fs::File::open(path)
+        .and_then(|mut x| x.read_to_end(&mut der))
+        .context(format!("failed to read identity file: {:?}", path))?;
+
+    let password = env::var("RSM_SERVER_IDENTITY_PASSWORD").unwrap_or_default();
+
+    let identity = Identity::from_pkcs12(der.as_slice(), password.as_str())
+        .context("failed to parse PKCS#12 identity")?;
+
+    Ok(identity)
+}
+
+fn read_line(prompt: &str) -> Result<String, Error> {
+    print!("{}", prompt);
+    io::stdout().flush()
+        .context("failed to flush stdout")?;
+
+    let mut buffer = String::new();
+    io::stdin().read_line(&mut buffer)
+        .context("failed to read user input")?;
+
+    Ok(buffer.trim_end_matches(['\n', '\r']).to_string())
+}
+
+fn read_password(prompt: &str) -> Result<String, Error> {
+    let fd = io::stdin().as_raw_fd();
+
+    if unsafe { libc::isatty(fd) } == 0 {
+        return read_line(prompt);
+    }
+
+    let mut term: libc::termios = unsafe { mem::zeroed() };
+
+    if unsafe { libc::tcgetattr(fd, &mut term) } != 0 {
+        bail!("failed to get terminal attributes");
+    }
+
+    let echo_term = term;
+    term.c_lflag &= !libc::ECHO;
+
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &term) } != 0 {
+        bail!("failed to disable terminal echo");
+    }
+
+    let password = read_line(prompt);
+
+    // restore echo even if reading failed
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &echo_term) };
+    println!();
+
+    password
+}
+
+fn main() {
+    if let Err(e) = run() {
+        let mut stderr = io::stderr();
+        let mut fail: &Fail = e.as_fail();
+        writeln!(&mut stderr, "{}", fail).ok();
+
+        while let Some(cause) = fail.cause() {
+            writeln!(&mut stderr, "caused by: {}", cause).ok();
+            fail = cause;
+        }
+
+        stderr.flush().ok();
+        ::std::process::exit(1);
+    }
+}
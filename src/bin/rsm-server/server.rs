@@ -0,0 +1,244 @@
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{io, thread};
+use bufstream::BufStream;
+use native_tls::{Identity, TlsAcceptor, TlsStream};
+use json::{self, JsonValue};
+use failure::*;
+use sqlite::Connection;
+use db::{self, TokenStatus};
+
+// largest sync payload accepted from a client
+static MAX_PAYLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+// error answered to the client. The code lets clients react to it
+struct ClientError {
+    message: String,
+    code: Option<&'static str>,
+}
+
+impl ClientError {
+    fn new(message: &str) -> ClientError {
+        ClientError { message: message.to_string(), code: None }
+    }
+
+    fn unauthorized() -> ClientError {
+        ClientError { message: "invalid credentials".to_string(), code: Some("unauthorized") }
+    }
+
+    fn token_expired() -> ClientError {
+        ClientError { message: "token expired".to_string(), code: Some("token_expired") }
+    }
+}
+
+type Response = Result<JsonValue, ClientError>;
+
+pub fn serve(address: &str, identity: Identity, db_path: &Path) -> Result<(), Error> {
+    let acceptor = TlsAcceptor::new(identity)
+        .context("failed to create TLS acceptor")?;
+    let acceptor = Arc::new(acceptor);
+
+    let listener = TcpListener::bind(address)
+        .context(format!("failed to listen on {}", address))?;
+
+    println!("Listening on {}", address);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("failed to accept connection: {}", e);
+                continue;
+            },
+        };
+
+        let acceptor = acceptor.clone();
+        let db_path = db_path.to_path_buf();
+
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(&acceptor, stream, db_path) {
+                let causes = e.iter_chain()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>();
+
+                eprintln!("{}", causes.as_slice().join(": "));
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_connection(acceptor: &TlsAcceptor, stream: TcpStream, db_path: PathBuf) -> Result<(), Error> {
+    let duration = Duration::new(60, 0);
+    stream.set_read_timeout(Some(duration))
+        .context("failed to set read timeout")?;
+    stream.set_write_timeout(Some(duration))
+        .context("failed to set write timeout")?;
+
+    let stream = acceptor.accept(stream)
+        .map_err(|e| format_err!("{}", e))
+        .context("failed to perform TLS handshake")?;
+
+    let mut stream = BufStream::new(stream);
+
+    let conn = db::connect(&db_path)
+        .context("failed to connect to database")?;
+
+    let response = match handle_request(&conn, &mut stream) {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => error_response(e),
+        Err(e) => {
+            // internal details stay in the server log
+            let causes = e.iter_chain()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>();
+            eprintln!("{}", causes.as_slice().join(": "));
+
+            error_response(ClientError::new("internal server error"))
+        },
+    };
+
+    stream.write_all(format!("{}\n", json::stringify(response)).as_bytes())
+        .context("failed to send response")?;
+
+    stream.flush()
+        .context("failed to flush stream")?;
+
+    Ok(())
+}
+
+fn error_response(error: ClientError) -> JsonValue {
+    let mut response = object! {
+        "error" => error.message
+    };
+
+    if let Some(code) = error.code {
+        response["code"] = code.into();
+    }
+
+    response
+}
+
+// The first line selects the request. It is either a LOGIN or REFRESH
+// command or the access token of a sync request.
+fn handle_request(conn: &Connection, stream: &mut BufStream<TlsStream<TcpStream>>) -> Result<Response, Error> {
+    let first = read_line(stream)?;
+
+    match first.as_str() {
+        "LOGIN" => {
+            let user = read_line(stream)?;
+            let password = read_line(stream)?;
+
+            login(conn, user.as_str(), password.as_str())
+        },
+        "REFRESH" => {
+            let refresh_token = read_line(stream)?;
+
+            refresh(conn, refresh_token.as_str())
+        },
+        token => {
+            let last_synced = match read_line(stream)?.parse::<i64>() {
+                Ok(x) => x,
+                Err(_) => return Ok(Err(ClientError::new("invalid last synced time"))),
+            };
+
+            let size = match read_line(stream)?.parse::<usize>() {
+                Ok(x) if x <= MAX_PAYLOAD_SIZE => x,
+                Ok(_) => return Ok(Err(ClientError::new("sync data too large"))),
+                Err(_) => return Ok(Err(ClientError::new("invalid sync data size"))),
+            };
+
+            let mut data = vec![0u8; size];
+            stream.read_exact(data.as_mut_slice())
+                .context("failed to read sync data")?;
+
+            let data = match String::from_utf8(data).ok().and_then(|x| json::parse(x.as_str()).ok()) {
+                Some(x) => x,
+                None => return Ok(Err(ClientError::new("invalid sync data"))),
+            };
+
+            sync(conn, token, last_synced, &data)
+        },
+    }
+}
+
+fn read_line(stream: &mut BufStream<TlsStream<TcpStream>>) -> Result<String, Error> {
+    let mut buf = String::new();
+    stream.read_line(&mut buf)
+        .context("failed to read request")?;
+
+    if buf.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by client")
+            .context("failed to read request").into());
+    }
+
+    Ok(buf.trim_end_matches(['\n', '\r']).to_string())
+}
+
+fn credentials_response(credentials: db::Credentials) -> JsonValue {
+    object! {
+        "token" => credentials.token,
+        "refresh_token" => credentials.refresh_token,
+        "expires" => credentials.expires
+    }
+}
+
+fn login(conn: &Connection, user: &str, password: &str) -> Result<Response, Error> {
+    let user_id = match db::check_password(conn, user, password).context("failed to check password")? {
+        Some(x) => x,
+        None => return Ok(Err(ClientError::unauthorized())),
+    };
+
+    let credentials = db::create_credentials(conn, user_id)
+        .context("failed to create credentials")?;
+
+    Ok(Ok(credentials_response(credentials)))
+}
+
+fn refresh(conn: &Connection, refresh_token: &str) -> Result<Response, Error> {
+    db::transaction(conn, |conn| {
+        let user_id = match db::check_token(conn, refresh_token, "refresh").context("failed to check refresh token")? {
+            TokenStatus::Valid(x) => x,
+            _ => return Ok(Err(ClientError::unauthorized())),
+        };
+
+        db::delete_token(conn, refresh_token)
+            .context("failed to delete refresh token")?;
+
+        let credentials = db::create_credentials(conn, user_id)
+            .context("failed to create credentials")?;
+
+        Ok(Ok(credentials_response(credentials)))
+    })
+}
+
+// The changes are collected before the uploaded ones are saved so clients do
+// not get their own changes back. Clients ask for the changes since the sync
+// time of the response, which comes from the server clock like the received
+// times of the rows.
+fn sync(conn: &Connection, token: &str, last_synced: i64, data: &JsonValue) -> Result<Response, Error> {
+    let user_id = match db::check_token(conn, token, "access").context("failed to check token")? {
+        TokenStatus::Valid(x) => x,
+        TokenStatus::Expired => return Ok(Err(ClientError::token_expired())),
+        TokenStatus::Unknown => return Ok(Err(ClientError::unauthorized())),
+    };
+
+    db::transaction(conn, |conn| {
+        // rows received later in the same second are sent again next time
+        let sync_time = db::get_utc_now() - 1;
+
+        let mut changes = db::get_changes(conn, user_id, last_synced)
+            .context("failed to get changes")?;
+
+        db::save_changes(conn, user_id, data)
+            .context("failed to save changes")?;
+
+        changes["sync_time"] = sync_time.into();
+
+        Ok(Ok(changes))
+    })
+}
@@ -1,21 +1,64 @@
 use std::io::prelude::*;
-use std::io;
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::fs;
+use std::{io, cmp};
 use failure::*;
-use ansi_term::{Style, Colour};
+use ansi_term::{Style, Colour, ANSIString, ANSIStrings};
+use rsm::snippet::{self, Snippet};
+use rsm::template;
+use rsm::language;
+use rsm::config::{Config, DisplayConfig};
+#[cfg(feature = "sync")]
+use rsm::config::ConflictStrategy;
+use rsm::{util, SnippetStore, Modification, Duplicates, Imported};
+use rsm::exchange;
+use rsm::error::ErrorKind;
 use content;
-use util;
-use db;
+use highlight;
+use output::{self, Format};
+use picker::{self, Action};
+use term;
 
 pub enum ModifyOperation<'a> {
     Name(String),
     Add(Vec<&'a str>),
     Remove(Vec<&'a str>),
+    Language(Option<String>),
+    Description(Option<String>),
+    Source(Option<String>),
     Content,
 }
 
-pub fn add_snippet(name: String, tags: Option<Vec<&str>>) -> Result<(), Error> {
-    let content = (if util::is_a_tty() {
-        content::get_from_editor(None)
+// Known languages go by their name, `none` means no language. Unknown ones
+// are kept as they are, just without highlighting.
+pub fn parse_language(language: &str) -> Option<String> {
+    match language::find(language) {
+        Some(known) => Some(known.name.to_string()),
+        None if language.eq_ignore_ascii_case("none") => None,
+        None => Some(language.trim().to_lowercase()),
+    }
+}
+
+// empty values clear optional fields like the description
+pub fn parse_optional(value: &str) -> Option<String> {
+    match value.trim() {
+        "" => None,
+        value => Some(value.to_string()),
+    }
+}
+
+// the language is detected unless given
+pub fn add_snippet(config: &Config, name: String, tags: Option<Vec<&str>>, language: Option<String>, description: Option<String>, source: Option<String>) -> Result<(), Error> {
+    // the name is all there is to go by before the content is written
+    let extension = match language {
+        Some(ref language) => language::extension(language),
+        None => language::detect(name.as_str(), "").and_then(|x| x.extensions.first().cloned()),
+    };
+
+    let content = (if term::is_a_tty() {
+        content::get_from_editor(&config.editor, None, extension)
             .context("failed to get content from editor")
     } else {
         content::get_from_stdin()
@@ -23,109 +66,214 @@ pub fn add_snippet(name: String, tags: Option<Vec<&str>>) -> Result<(), Error> {
     })
     .context("failed to get snippet content")?;
 
-    let conn = db::connect()
-        .context("failed to connect to the database")?;
+    let store = SnippetStore::open(&config.db_path)?;
 
-    let snippet_id = db::save_snippet(&conn, name, content, tags)
-        .context("failed to save snippet")?;
+    let snippet_id = store.add(name, content, tags, language, description, source)?;
 
     println!("Created snippet {}.", snippet_id);
 
     Ok(())
 }
 
-pub fn show_snippet(snippet_id: i64) -> Result<(), Error> {
-    let conn = db::connect()
-        .context("failed to connect to database")?;
+pub fn show_snippet(config: &Config, id: &str, revision: Option<i64>, format: Format) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup(id)?;
 
-    let snippet = db::get_snippet(&conn, snippet_id)
-        .context("failed to load snippet")?;
+    let snippet = match revision {
+        Some(revision) => store.get_revision(snippet_id, revision)?,
+        None => store.get(snippet_id)?,
+    };
 
-    println!("{}", snippet);
+    if format != Format::Text {
+        return output::print_snippet(format, &snippet);
+    }
+
+    let language = snippet.language.as_ref().and_then(|x| language::find(x));
+
+    // the alternate form leaves out the styling
+    if config.display.color {
+        let snippet = match language {
+            Some(language) => Snippet { content: highlight::highlight(snippet.content.as_str(), language), ..snippet },
+            None => snippet,
+        };
+
+        println!("{}", snippet);
+    } else {
+        println!("{:#}", snippet);
+    }
 
     Ok(())
 }
 
-pub fn modify_snippet(snippet_id: i64, op: ModifyOperation) -> Result<(), Error> {
-    let conn = db::connect()
-        .context("failed to connect to database")?;
+pub fn show_history(config: &Config, id: &str) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup(id)?;
 
-    let snippet = db::get_snippet(&conn, snippet_id)
-        .context("failed to load snippet")?;
+    let revisions = store.history(snippet_id)?;
 
-    match op {
-        ModifyOperation::Name(name) => {
-            db::rename_snippet(&conn, snippet_id, name)
-                .context("failed to rename snippet")?;
-        },
-        ModifyOperation::Add(tags) => {
-            db::save_tags(&conn, snippet_id, tags)
-                .context("failed to add tags to snippet")?;
-        },
-        ModifyOperation::Remove(tags) => {
-            db::remove_tags_by_name(&conn, snippet_id, tags)
-                .context("failed to remove tags to snippet")?;
-        },
+    if revisions.is_empty() {
+        println!("No revisions found");
+        return Ok(());
+    }
+
+    let rev_padding = cmp::max(3, revisions.last().unwrap().revision.to_string().len());
+    let date_padding = 19;
+
+    // print list header
+    let style = term::header_style(&config.display);
+    println!("{} {} {}",
+             style.paint(format!("{:1$}", "Rev", rev_padding)),
+             style.paint(format!("{:1$}", "Date", date_padding)),
+             style.paint("Change"));
+
+    for (i, revision) in revisions.iter().enumerate() {
+        let style = term::row_style(&config.display, i);
+
+        let revision_line = format!("{:3$} {:4$} {}",
+                                    revision.revision,
+                                    util::format_time(revision.created),
+                                    revision.summary(),
+                                    rev_padding,
+                                    date_padding);
+
+        println!("{}", style.paint(revision_line));
+    }
+
+    Ok(())
+}
+
+pub fn revert_snippet(config: &Config, id: &str, revision: i64) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup(id)?;
+
+    store.revert(snippet_id, revision)?;
+
+    println!("Reverted snippet {} to revision {}", snippet_id, revision);
+
+    Ok(())
+}
+
+pub fn modify_snippet(config: &Config, id: &str, op: ModifyOperation) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup(id)?;
+
+    let modification = match op {
+        ModifyOperation::Name(name) => Modification::Name(name),
+        ModifyOperation::Add(tags) => Modification::AddTags(tags),
+        ModifyOperation::Remove(tags) => Modification::RemoveTags(tags),
+        ModifyOperation::Language(language) => Modification::Language(language),
+        ModifyOperation::Description(description) => Modification::Description(description),
+        ModifyOperation::Source(source) => Modification::Source(source),
         ModifyOperation::Content => {
-            let content = content::get_from_editor(Some(snippet.content))
+            let snippet = store.get(snippet_id)?;
+            let extension = snippet.language.as_ref().and_then(|x| language::extension(x));
+
+            let content = content::get_from_editor(&config.editor, Some(snippet.content), extension)
                 .context("failed to get new content from editor")?;
 
-            db::change_snippet_content(&conn, snippet_id, content)
-                .context("failed to change snippet content")?;
+            Modification::Content(content)
         },
+    };
+
+    store.modify(snippet_id, modification)
+}
+
+pub fn delete_snippet(config: &Config, id: &str, confirmation: bool) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup(id)?;
+
+    let snippet = store.get(snippet_id)?;
+
+    if !confirmation {
+        let prompt = format!("Delete snippet {} '{}'", snippet_id, snippet.name);
+
+        if !confirm(prompt.as_str()).context("failed to get confirmation")? {
+            println!("Snippet not deleted");
+            return Ok(());
+        }
     }
 
+    store.delete(snippet_id)?;
+    println!("Deleted snippet {} '{}'", snippet_id, snippet.name);
+
     Ok(())
 }
 
-pub fn delete_snippet(snippet_id: i64, confirmation: bool) -> Result<(), Error> {
-    let conn = db::connect()
-        .context("failed to connect to database")?;
+pub fn list_trash(config: &Config) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
 
-    let snippet = db::get_snippet(&conn, snippet_id)
-        .context("failed to load snippet")?;
+    let snippets = store.trash()?;
 
-    if !confirmation {
-        let stdin = io::stdin();
-        let mut handle = stdin.lock();
+    if snippets.is_empty() {
+        println!("Trash is empty");
+        return Ok(());
+    }
 
-        loop {
-            print!("Delete snippet {} '{}' (yes/no) ", snippet_id, snippet.name);
-            io::stdout().flush()
-                .context("failed to flush stdout")?;
+    let id_padding = snippets.iter().fold(2, |acc, x| cmp::max(acc, x.0.id.to_string().len()));
+    let date_padding = 19;
 
-            let mut buffer = String::new();
-            handle.read_line(&mut buffer)
-                .context("failed to read user input")?;
+    // print list header
+    let style = term::header_style(&config.display);
+    println!("{} {} {}",
+             style.paint(format!("{:1$}", "Id", id_padding)),
+             style.paint(format!("{:1$}", "Deleted", date_padding)),
+             style.paint("Name"));
 
-            if buffer != "\n" {
-                let input = &buffer.as_str()[..buffer.len()-1];
+    for (i, &(ref snippet, deleted_at)) in snippets.iter().enumerate() {
+        let style = term::row_style(&config.display, i);
 
-                if "yes".starts_with(input) {
-                    break;
-                }
+        let snippet_line = format!("{:3$} {:4$} {}",
+                                   snippet.id,
+                                   util::format_time(deleted_at),
+                                   snippet.name,
+                                   id_padding,
+                                   date_padding);
 
-                if "no".starts_with(input) {
-                    println!("Snippet not deleted");
-                    return Ok(());
-                }
-            }
-        }
+        println!("{}", style.paint(snippet_line));
     }
 
-    db::delete_snippet(&conn, snippet_id)
-        .context("failed to delete snippet")?;
-    println!("Deleted snippet {} '{}'", snippet_id, snippet.name);
+    Ok(())
+}
+
+pub fn restore_snippet(config: &Config, id: &str) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup_deleted(id)?;
+
+    store.restore(snippet_id)?;
+
+    println!("Restored snippet {}", snippet_id);
+
+    Ok(())
+}
+
+pub fn purge_snippets(config: &Config, older_than: Option<i64>, confirmation: bool) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+
+    if !confirmation && !confirm("Permanently remove deleted snippets").context("failed to get confirmation")? {
+        println!("No snippets purged");
+        return Ok(());
+    }
+
+    // days to seconds
+    let (purged, held_back) = store.purge(older_than.map(|x| x * 24 * 60 * 60))?;
+
+    println!("Purged {} snippet(s)", purged);
+
+    if held_back > 0 {
+        println!("Kept {} snippet(s) deleted since the last sync, sync to purge them", held_back);
+    }
 
     Ok(())
 }
 
-pub fn list_snippets(name: Option<String>, tags: Option<Vec<&str>>) -> Result<(), Error> {
-    let conn = db::connect()
-        .context("failed to connect to database")?;
+pub fn list_snippets(config: &Config, name: Option<String>, tags: Option<Vec<&str>>, format: Format) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let snippets = store.list(name, tags)?;
 
-    let snippets = db::search_snippets(&conn, name, tags)
-        .context("failed to search snippets")?;
+    if format != Format::Text {
+        return output::print_snippets(format, &snippets);
+    }
 
     if snippets.is_empty() {
         println!("No snippets found");
@@ -133,21 +281,17 @@ pub fn list_snippets(name: Option<String>, tags: Option<Vec<&str>>) -> Result<()
     }
 
     // get the max width for each list column
-    let (id_padding, tag_padding, name_padding) = util::get_list_col_widths(&snippets);
+    let (id_padding, tag_padding, name_padding) = term::get_list_col_widths(&snippets);
 
     // print list header
-    let style = Style::new().underline();
+    let style = term::header_style(&config.display);
     println!("{} {} {}",
              style.paint(format!("{:1$}", "Id", id_padding)),
              style.paint(format!("{:1$}", "Tags", tag_padding)),
              style.paint(format!("{:1$}", "Name", name_padding)));
 
     for (i, snippet) in snippets.iter().enumerate() {
-        let style = if i % 2 == 0 {
-            Style::new()
-        } else {
-            Style::new().on(Colour::Fixed(235))
-        };
+        let style = term::row_style(&config.display, i);
 
         let snippet_line = format!("{:3$} {:4$} {:5$}",
                                    snippet.id,
@@ -163,39 +307,506 @@ pub fn list_snippets(name: Option<String>, tags: Option<Vec<&str>>) -> Result<()
     Ok(())
 }
 
+pub fn pick_snippet(config: &Config, query: String, tags: Option<Vec<&str>>, action: Action) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let snippets = store.list(None, tags)?;
+
+    if snippets.is_empty() {
+        println!("No snippets found");
+        return Ok(());
+    }
+
+    let (snippet, action) = picker::pick(snippets, query, action, config.display.color)?
+        .ok_or(ErrorKind::PickAborted)?;
+
+    match action {
+        Action::Print => print_content(snippet.content.as_str()),
+        Action::Copy => {
+            content::copy_to_clipboard(config.clipboard.as_deref(), snippet.content.as_str())
+                .context("failed to copy snippet to the clipboard")?;
+
+            println!("Copied snippet {} '{}'", snippet.id, snippet.name);
+        },
+        Action::Edit => {
+            modify_snippet(config, snippet.id.to_string().as_str(), ModifyOperation::Content)?;
+        },
+    }
+
+    Ok(())
+}
+
+pub fn use_snippet(config: &Config, id: &str, values: HashMap<String, String>) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup(id)?;
+
+    let snippet = store.get(snippet_id)?;
+    let content = fill_template(&store, &snippet, values)?;
+
+    print_content(content.as_str());
+
+    Ok(())
+}
+
+// returns the exit status of the snippet
+pub fn run_snippet(config: &Config, id: &str, args: Vec<&str>, values: HashMap<String, String>, confirmation: bool) -> Result<i32, Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup(id)?;
+
+    let snippet = store.get(snippet_id)?;
+    let content = fill_template(&store, &snippet, values)?;
+
+    if !confirmation {
+        let style = if config.display.color {
+            Style::new().bold()
+        } else {
+            Style::new()
+        };
+
+        for line in content.lines() {
+            println!("    {}", style.paint(line));
+        }
+
+        let prompt = format!("Run snippet {} '{}'", snippet_id, snippet.name);
+
+        if !confirm(prompt.as_str()).context("failed to get confirmation")? {
+            println!("Snippet not run");
+            return Ok(0);
+        }
+    }
+
+    let status = content::execute(config.shell.as_str(), content.as_str(), args)?;
+
+    // like shells report processes killed by a signal
+    Ok(match status.code() {
+        Some(code) => code,
+        None => 128 + status.signal().unwrap_or(0),
+    })
+}
+
+// Render the placeholders of a snippet. Values missing from `values` are
+// asked for, offering the last used value or the default of the placeholder.
+// Without a terminal to ask on those have to do.
+fn fill_template(store: &SnippetStore, snippet: &Snippet, mut values: HashMap<String, String>) -> Result<String, Error> {
+    let placeholders = template::placeholders(snippet.content.as_str());
+
+    if placeholders.is_empty() {
+        return Ok(snippet.content.clone());
+    }
+
+    let remembered = store.variables(snippet.id)?;
+
+    // values for names the snippet does not use are not worth keeping
+    values.retain(|name, _| placeholders.iter().any(|x| &x.name == name));
+
+    for placeholder in placeholders {
+        if values.contains_key(&placeholder.name) {
+            continue;
+        }
+
+        let default = remembered.get(&placeholder.name).cloned().or(placeholder.default);
+
+        let value = if term::is_a_tty() {
+            let prompt = match default {
+                Some(ref default) => format!("{} [{}]: ", placeholder.name, default),
+                None => format!("{}: ", placeholder.name),
+            };
+
+            let input = term::ask(prompt.as_str())
+                .context("failed to read placeholder value")?;
+
+            if input.is_empty() {
+                default.unwrap_or_default()
+            } else {
+                input
+            }
+        } else {
+            default.ok_or(ErrorKind::MissingVariable(placeholder.name.clone()))?
+        };
+
+        values.insert(placeholder.name, value);
+    }
+
+    store.remember_variables(snippet.id, &values)?;
+
+    template::render(snippet.content.as_str(), &values)
+}
+
+// the content alone so it can be piped into a shell
+fn print_content(content: &str) {
+    if content.ends_with('\n') {
+        print!("{}", content);
+    } else {
+        println!("{}", content);
+    }
+}
+
+pub fn search_snippets(config: &Config, query: String) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let matches = store.search(query.as_str())?;
+
+    if matches.is_empty() {
+        println!("No snippets found");
+        return Ok(());
+    }
+
+    // get the max width for each list column
+    let (snippets, excerpts): (Vec<Snippet>, Vec<String>) = matches.into_iter()
+        .map(|x| (x.snippet, x.excerpt))
+        .unzip();
+    let (id_padding, tag_padding, name_padding) = term::get_list_col_widths(&snippets);
+
+    // print list header
+    let style = term::header_style(&config.display);
+    println!("{} {} {} {}",
+             style.paint(format!("{:1$}", "Id", id_padding)),
+             style.paint(format!("{:1$}", "Tags", tag_padding)),
+             style.paint(format!("{:1$}", "Name", name_padding)),
+             style.paint("Match"));
+
+    for (i, (snippet, excerpt)) in snippets.iter().zip(excerpts.iter()).enumerate() {
+        let style = term::row_style(&config.display, i);
+
+        let snippet_line = format!("{:3$} {:4$} {:5$} ",
+                                   snippet.id,
+                                   snippet.tags.as_slice().join(", "),
+                                   snippet.name, id_padding,
+                                   tag_padding,
+                                   name_padding);
+
+        let mut line = vec![style.paint(snippet_line)];
+        line.extend(highlight_excerpt(&config.display, excerpt, style));
+
+        println!("{}", ANSIStrings(&line));
+    }
+
+    Ok(())
+}
+
+// split an excerpt at the match markers and paint the matched terms
+fn highlight_excerpt(display: &DisplayConfig, excerpt: &str, style: Style) -> Vec<ANSIString<'static>> {
+    let match_style = if display.color {
+        style.bold().fg(Colour::Yellow)
+    } else {
+        style
+    };
+
+    let excerpt = excerpt.replace(['\n', '\t'], " ");
+    let mut parts = excerpt.split(snippet::MATCH_START);
+    let mut painted = Vec::new();
+
+    if let Some(text) = parts.next() {
+        painted.push(style.paint(text.to_string()));
+    }
+
+    for part in parts {
+        let mut split = part.splitn(2, snippet::MATCH_END);
+
+        if let Some(term) = split.next() {
+            painted.push(match_style.paint(term.to_string()));
+        }
+
+        if let Some(text) = split.next() {
+            painted.push(style.paint(text.to_string()));
+        }
+    }
+
+    painted
+}
+
+pub fn list_conflicts(config: &Config) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let conflicts = store.conflicts()?;
+
+    if conflicts.is_empty() {
+        println!("No conflicts found");
+        return Ok(());
+    }
+
+    let id_padding = conflicts.iter().fold(2, |acc, x| cmp::max(acc, x.snippet_id.to_string().len()));
+    let date_padding = 19;
+
+    // print list header
+    let style = term::header_style(&config.display);
+    println!("{} {} {}",
+             style.paint(format!("{:1$}", "Id", id_padding)),
+             style.paint(format!("{:1$}", "Detected", date_padding)),
+             style.paint("Name"));
+
+    for (i, conflict) in conflicts.iter().enumerate() {
+        let style = term::row_style(&config.display, i);
+
+        let name = if conflict.name == conflict.remote_name {
+            conflict.name.clone()
+        } else {
+            format!("{} / {}", conflict.name, conflict.remote_name)
+        };
+
+        let conflict_line = format!("{:3$} {:4$} {}",
+                                    conflict.snippet_id,
+                                    util::format_time(conflict.created),
+                                    name,
+                                    id_padding,
+                                    date_padding);
+
+        println!("{}", style.paint(conflict_line));
+    }
+
+    Ok(())
+}
+
+pub fn merge_conflict(config: &Config, id: &str) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+    let snippet_id = store.lookup(id)?;
+
+    let conflict = store.conflict(snippet_id)?;
+    let language = store.get(snippet_id)?.language;
+
+    // both versions separated by the usual conflict markers
+    let merge_content = format!("<<<<<<< local\n{}\n=======\n{}\n>>>>>>> remote\n",
+                                conflict.content.trim_end_matches('\n'),
+                                conflict.remote_content.trim_end_matches('\n'));
+
+    let extension = language.as_ref().and_then(|x| language::extension(x));
+
+    let content = content::get_from_editor(&config.editor, Some(merge_content), extension)
+        .context("failed to get merged content from editor")?;
+
+    if has_conflict_markers(content.as_str()) {
+        return Err(ErrorKind::UnresolvedConflict.into());
+    }
+
+    // differing names are not merged, the local one is kept
+    let name = conflict.name.clone();
+
+    store.resolve_conflict(&conflict, name, content)?;
+
+    println!("Merged snippet {}", snippet_id);
+
+    Ok(())
+}
+
+// Markers are only left over if all three appear in order, snippets may
+// contain lines like `=======` on their own.
+fn has_conflict_markers(content: &str) -> bool {
+    let markers = ["<<<<<<<", "=======", ">>>>>>>"];
+    let mut found = 0;
+
+    for line in content.lines() {
+        if line.starts_with(markers[found]) {
+            found += 1;
+
+            if found == markers.len() {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+// the insecure mode must never go unnoticed
+#[cfg(feature = "sync")]
+fn warn_insecure(config: &Config) {
+    if config.sync.insecure {
+        let style = Style::new().bold().fg(Colour::Red);
+        eprintln!("{}", style.paint("WARNING: TLS certificate verification is disabled. Never use --insecure outside of local development!"));
+    }
+}
+
+#[cfg(feature = "sync")]
+pub fn login(config: &Config, user: Option<&str>) -> Result<(), Error> {
+    warn_insecure(config);
+
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let user = match user {
+        Some(user) => user.to_string(),
+        None => term::read_line("Username: ")
+            .context("failed to read username")?,
+    };
+
+    let password = term::read_password("Password: ")
+        .context("failed to read password")?;
+
+    store.login(&config.sync, user.as_str(), password.as_str())?;
+
+    println!("Logged in as {}", user);
+
+    Ok(())
+}
+
 #[cfg(feature = "sync")]
-pub fn sync_data() -> Result<(), Error> {
-    use std::str::FromStr;
-    use sync;
-    use json;
+pub fn sync_data(config: &Config) -> Result<(), Error> {
+    warn_insecure(config);
+
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let summary = store.sync(&config.sync)?;
+    let conflicts = summary.conflicts;
+
+    println!("Uploaded {} snippet(s), received {} snippet(s)", summary.uploaded, summary.received);
+
+    if conflicts > 0 {
+        match config.sync.conflict {
+            ConflictStrategy::LastWriterWins => println!("Resolved {} conflict(s) by keeping the latest change", conflicts),
+            ConflictStrategy::KeepBoth => println!("Saved local copies of {} conflicting snippet(s)", conflicts),
+            ConflictStrategy::Manual => println!("{} conflict(s) need to be merged, run 'rsm conflicts'", conflicts),
+        }
+    }
+
+    Ok(())
+}
+
+// writes to stdout without a path, filters work as they do for `list`
+pub fn export_snippets(config: &Config, format: exchange::Format, path: Option<&str>, name: Option<String>, tags: Option<Vec<&str>>) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let entries = store.export(name, tags)?;
+
+    match (path, format.is_single_file()) {
+        (Some(path), true) => {
+            let data = format.serialize(&entries)?;
+
+            fs::write(path, data)
+                .context(format!("failed to write export file {}", path))?;
+        },
+        (None, true) => print!("{}", format.serialize(&entries)?),
+        (Some(path), false) => exchange::directory::write(Path::new(path), &entries)?,
+        (None, false) => return Err(ErrorKind::InvalidArgument("the dir format needs an output directory".to_string()).into()),
+    }
+
+    if let Some(path) = path {
+        println!("Exported {} snippet(s) to {}", entries.len(), path);
+    }
 
-    let conn = db::connect()
-        .context("failed to connect to database")?;
+    Ok(())
+}
+
+// snippet files for an editor, placeholders become tab stops
+pub fn export_editor_snippets(config: &Config, editor: exchange::Editor, path: Option<&str>, name: Option<String>, tags: Option<Vec<&str>>) -> Result<(), Error> {
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let entries = store.export(name, tags)?;
+
+    match (path, editor.is_single_file()) {
+        (Some(path), true) => {
+            let data = editor.serialize(&entries)?;
 
-    let meta_value = db::get_metadata_value(&conn, "last_synced")
-        .context("failed to get last synced time")?;
+            fs::write(path, data)
+                .context(format!("failed to write export file {}", path))?;
+        },
+        (None, true) => print!("{}", editor.serialize(&entries)?),
+        (Some(path), false) => editor.write(Path::new(path), &entries)?,
+        (None, false) => return Err(ErrorKind::InvalidArgument(format!("{:?} snippets need an output directory", editor).to_lowercase()).into()),
+    }
 
-    let last_synced = i64::from_str(meta_value.as_str())
-        .context("failed to parse last synced string")?;
+    if let Some(path) = path {
+        println!("Exported {} snippet(s) to {}", entries.len(), path);
+    }
 
-    let sync_data = db::get_sync_data(&conn, last_synced)
-        .context("failed to get sync data")?;
+    Ok(())
+}
 
-    let json_data = object!{
-        "snippets" => sync_data.0,
-        "tags" => sync_data.1,
-        "snippet_tags" => sync_data.2
+// reads stdin without a path, the format is guessed from the path if not given
+pub fn import_snippets(config: &Config, format: Option<exchange::Format>, path: Option<&str>, duplicates: Duplicates) -> Result<(), Error> {
+    let format = match (format, path) {
+        (Some(format), _) => format,
+        (None, Some(path)) => exchange::Format::detect(Path::new(path)),
+        (None, None) => exchange::Format::Json,
     };
 
-    let serialized_data = json::stringify(json_data);
+    let entries = match (path, format.is_single_file()) {
+        (Some(path), true) => {
+            let data = fs::read_to_string(path)
+                .context(format!("failed to read import file {}", path))?;
 
-    // dummy data for testing of the server side code
-    sync::sync_data("localhost:5000", "localhost", last_synced, serialized_data.as_str())
-        .context("failed to sync data")?;
+            format.parse(data.as_str())?
+        },
+        (None, true) => {
+            let data = content::get_from_stdin()
+                .context("failed to read snippets from stdin")?;
+
+            format.parse(data.as_str())?
+        },
+        (Some(path), false) => exchange::directory::read(Path::new(path))?,
+        (None, false) => return Err(ErrorKind::InvalidArgument("the dir format needs an input directory".to_string()).into()),
+    };
+
+    let store = SnippetStore::open(&config.db_path)?;
 
-    let sync_time = format!("{}", util::get_utc_now());
-    db::set_metadata_value(&conn, "last_synced", sync_time.as_str())
-        .context("failed to update sync time")?;
+    let outcomes = store.import(entries, duplicates)?;
+    print_import_report(&outcomes);
 
     Ok(())
 }
+
+// snippets of other snippet managers, from a file or a directory of them
+pub fn import_foreign_snippets(config: &Config, source: exchange::Source, path: &str, duplicates: Duplicates) -> Result<(), Error> {
+    let (entries, skipped) = source.read(Path::new(path))?;
+
+    for note in &skipped {
+        println!("Skipped {}", note);
+    }
+
+    let store = SnippetStore::open(&config.db_path)?;
+
+    let outcomes = store.import(entries, duplicates)?;
+    print_import_report(&outcomes);
+
+    Ok(())
+}
+
+// everything that did not go as asked for, then the totals
+fn print_import_report(outcomes: &[(String, Imported)]) {
+    let mut added = 0;
+    let mut skipped = 0;
+
+    for (name, outcome) in outcomes {
+        match *outcome {
+            Imported::Added(_) => added += 1,
+            Imported::Renamed(snippet_id, ref new_name) => {
+                println!("Imported '{}' as snippet {} '{}'", name, snippet_id, new_name);
+                added += 1;
+            },
+            Imported::Replaced(snippet_id) => {
+                println!("Replaced snippet {} '{}'", snippet_id, name);
+                added += 1;
+            },
+            Imported::Skipped(ref kind) => {
+                println!("Skipped '{}': {}", name, kind);
+                skipped += 1;
+            },
+        }
+    }
+
+    println!("Imported {} snippet(s), skipped {}", added, skipped);
+}
+
+// ask the user a yes/no question on stdin
+fn confirm(prompt: &str) -> Result<bool, Error> {
+    let stdin = io::stdin();
+    let mut handle = stdin.lock();
+
+    loop {
+        print!("{} (yes/no) ", prompt);
+        io::stdout().flush()
+            .context("failed to flush stdout")?;
+
+        let mut buffer = String::new();
+        let read = handle.read_line(&mut buffer)
+            .context("failed to read user input")?;
+
+        // nobody left to answer
+        if read == 0 {
+            println!();
+            return Ok(false);
+        }
+
+        if let Some(answer) = util::parse_answer(buffer.as_str()) {
+            return Ok(answer);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::path::{Path, PathBuf};
+use std::io::prelude::*;
+use std::str::FromStr;
+use std::{env, fs, io};
+use failure::*;
+use toml;
+
+static CONFIG_PATH: &str = "rsm/config.toml";
+static DB_PATH: &str = "rsm/data.db";
+// database location used before XDG_DATA_HOME was honored
+static LEGACY_DB_PATH: &'static str = ".local/rsm/data.db";
+
+pub struct Config {
+    pub db_path: PathBuf,
+    pub editor: String,
+    // command copied text is piped into, detected when unset
+    pub clipboard: Option<String>,
+    // shell running snippets without a shebang line
+    pub shell: String,
+    #[cfg_attr(not(feature = "sync"), allow(dead_code))]
+    pub sync: SyncConfig,
+    pub display: DisplayConfig,
+}
+
+#[cfg_attr(not(feature = "sync"), allow(dead_code))]
+pub struct SyncConfig {
+    pub server: String,
+    pub domain: String,
+    // additional CA certificates in PEM format to trust
+    pub ca_file: Option<PathBuf>,
+    // SHA-256 fingerprint the server certificate has to match
+    pub pin: Option<String>,
+    // skip certificate verification. Only set by the --insecure flag
+    pub insecure: bool,
+    // how snippets changed both locally and remotely are merged
+    pub conflict: ConflictStrategy,
+    // seconds to wait for the server before giving up
+    pub timeout: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ConflictStrategy {
+    // the most recently changed version replaces the other one
+    LastWriterWins,
+    // the remote version is applied and the local one saved as a copy
+    KeepBoth,
+    // the conflict is recorded and merged with `rsm conflicts`
+    Manual,
+}
+
+impl FromStr for ConflictStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ConflictStrategy, Self::Err> {
+        match s {
+            "last-writer-wins" => Ok(ConflictStrategy::LastWriterWins),
+            "keep-both" => Ok(ConflictStrategy::KeepBoth),
+            "manual" => Ok(ConflictStrategy::Manual),
+            _ => bail!("unknown conflict strategy '{}', expected last-writer-wins, keep-both or manual", s),
+        }
+    }
+}
+
+pub struct DisplayConfig {
+    pub color: bool,
+}
+
+// layout of the config file. Every value is optional and falls back to the
+// environment and the built-in defaults
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    database: DatabaseSection,
+    editor: EditorSection,
+    clipboard: ClipboardSection,
+    shell: ShellSection,
+    sync: SyncSection,
+    display: DisplaySection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct DatabaseSection {
+    path: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct EditorSection {
+    command: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ClipboardSection {
+    command: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ShellSection {
+    command: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct SyncSection {
+    server: Option<String>,
+    domain: Option<String>,
+    ca_file: Option<String>,
+    pin: Option<String>,
+    conflict: Option<String>,
+    timeout: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct DisplaySection {
+    color: Option<bool>,
+}
+
+// Loads the configuration. Values are taken from the command line, `RSM_*`
+// environment variables, the config file and the defaults, in that order.
+pub fn load(config_path: Option<&str>, db_path: Option<&str>) -> Result<Config, Error> {
+    let file = read_config_file(config_path)
+        .context("failed to read config file")?;
+
+    let db_path = match db_path.map(|x| x.to_string()).or(env_var("RSM_DB")).or(file.database.path) {
+        Some(path) => expand_home(path.as_str())
+            .context("failed to expand database path")?,
+        None => default_db_path()
+            .context("failed to get default database path")?,
+    };
+
+    let editor = env_var("RSM_EDITOR")
+        .or(file.editor.command)
+        .or(env_var("EDITOR"))
+        .unwrap_or("/usr/bin/editor".to_string());
+
+    let clipboard = env_var("RSM_CLIPBOARD")
+        .or(file.clipboard.command);
+
+    // not $SHELL, snippets are shared and usually written for sh
+    let shell = env_var("RSM_SHELL")
+        .or(file.shell.command)
+        .unwrap_or("/bin/sh".to_string());
+
+    let ca_file = match env_var("RSM_SYNC_CA_FILE").or(file.sync.ca_file) {
+        Some(path) => Some(expand_home(path.as_str())
+            .context("failed to expand CA file path")?),
+        None => None,
+    };
+
+    let conflict = match env_var("RSM_SYNC_CONFLICT").or(file.sync.conflict) {
+        Some(strategy) => ConflictStrategy::from_str(strategy.as_str())
+            .context("failed to parse conflict strategy")?,
+        None => ConflictStrategy::KeepBoth,
+    };
+
+    let timeout = match env_var("RSM_SYNC_TIMEOUT") {
+        Some(value) => u64::from_str(value.as_str())
+            .context("failed to parse RSM_SYNC_TIMEOUT")?,
+        None => file.sync.timeout.unwrap_or(60),
+    };
+
+    let sync = SyncConfig {
+        server: env_var("RSM_SYNC_SERVER")
+            .or(file.sync.server)
+            .unwrap_or("localhost:5000".to_string()),
+        domain: env_var("RSM_SYNC_DOMAIN")
+            .or(file.sync.domain)
+            .unwrap_or("localhost".to_string()),
+        ca_file: ca_file,
+        pin: env_var("RSM_SYNC_PIN")
+            .or(file.sync.pin),
+        insecure: false,
+        conflict: conflict,
+        timeout: timeout,
+    };
+
+    let color = match env_var("RSM_COLOR") {
+        Some(value) => parse_bool(value.as_str())
+            .context("failed to parse RSM_COLOR")?,
+        None => file.display.color.unwrap_or(true),
+    };
+
+    let config = Config {
+        db_path: db_path,
+        editor: editor,
+        clipboard: clipboard,
+        shell: shell,
+        sync: sync,
+        display: DisplayConfig {
+            color: color,
+        },
+    };
+
+    Ok(config)
+}
+
+fn read_config_file(config_path: Option<&str>) -> Result<ConfigFile, Error> {
+    // an explicitly requested config file has to exist
+    let (path, required) = match config_path.map(|x| x.to_string()).or(env_var("RSM_CONFIG")) {
+        Some(path) => (expand_home(path.as_str()).context("failed to expand config path")?, true),
+        None => (default_config_path().context("failed to get default config path")?, false),
+    };
+
+    let mut file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(ConfigFile::default()),
+        Err(e) => return Err(e.context(format!("failed to open config file: {:?}", path)).into()),
+    };
+
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .context(format!("failed to read config file: {:?}", path))?;
+
+    let config = toml::from_str(content.as_str())
+        .context(format!("failed to parse config file: {:?}", path))?;
+
+    Ok(config)
+}
+
+fn default_config_path() -> Result<PathBuf, Error> {
+    let mut path = match env_var("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => home_dir()?.join(".config"),
+    };
+
+    path.push(CONFIG_PATH);
+
+    Ok(path)
+}
+
+fn default_db_path() -> Result<PathBuf, Error> {
+    let legacy_path = home_dir()?.join(LEGACY_DB_PATH);
+
+    let path = match env_var("XDG_DATA_HOME") {
+        Some(dir) => Path::new(dir.as_str()).join(DB_PATH),
+        None => return Ok(legacy_path),
+    };
+
+    // keep using a database created before XDG_DATA_HOME was honored
+    if !path.exists() && legacy_path.exists() {
+        return Ok(legacy_path);
+    }
+
+    Ok(path)
+}
+
+fn home_dir() -> Result<PathBuf, Error> {
+    let home = env::var("HOME")
+        .context("failed to get HOME directory")?;
+
+    Ok(PathBuf::from(home))
+}
+
+fn expand_home(path: &str) -> Result<PathBuf, Error> {
+    if let Some(rest) = path.strip_prefix("~/") {
+        return Ok(home_dir()?.join(rest));
+    }
+
+    Ok(PathBuf::from(path))
+}
+
+// unset and empty variables are treated the same
+fn env_var(key: &str) -> Option<String> {
+    env::var(key).ok().and_then(|x| if x.is_empty() { None } else { Some(x) })
+}
+
+fn parse_bool(value: &str) -> Result<bool, Error> {
+    match value {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => bail!("expected a boolean value, got '{}'", value),
+    }
+}
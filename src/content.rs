@@ -1,18 +1,21 @@
-use std::process::{Command, ExitStatus};
+use std::process::{Command, ExitStatus, Stdio};
 use std::io::prelude::*;
 use std::ffi::OsStr;
 use std::path::Path;
-use std::{env, io, fs};
+use std::{io, fs};
 use mktemp::Temp;
 use failure::*;
+use rsm::error::ErrorKind;
 
-fn run_editor<S: AsRef<OsStr>>(file: S) -> Result<(), Error> {
-    // get the prefered editor
-    let editor = env::var("EDITOR")
-        .unwrap_or("/usr/bin/editor".to_string());
+fn run_editor<S: AsRef<OsStr>>(editor: &str, file: S) -> Result<(), Error> {
+    // the editor command may come with its own arguments
+    let mut args = editor.split_whitespace();
+    let program = args.next()
+        .ok_or(format_err!("empty editor command"))?;
 
     // start the editor and wait for its exit status
-    let status: ExitStatus = Command::new(editor)
+    let status: ExitStatus = Command::new(program)
+        .args(args)
         .arg(file)
         .status()
         .map_err::<Error, _>(|e| e.into())?;
@@ -23,11 +26,48 @@ fn run_editor<S: AsRef<OsStr>>(file: S) -> Result<(), Error> {
         Some(code) => Err(format_err!("status code: {}", code)),
         None => Err(format_err!("terminated by signal")),
     })
-    .context("editor exited unexpectedly")?;
+    .context(ErrorKind::EditorAborted)?;
 
     Ok(())
 }
 
+// Run a snippet with the interpreter of its shebang line or `shell -c`. The
+// arguments are passed on as $1, $2, ...
+pub fn execute(shell: &str, content: &str, args: Vec<&str>) -> Result<ExitStatus, Error> {
+    // the script is kept in a file for interpreters that cannot take it as an argument
+    let tmp_file = Temp::new_file()
+        .context("failed to create temporary file")?;
+
+    let mut command = if content.starts_with("#!") {
+        let shebang = content.lines().next().unwrap_or("");
+        let mut interpreter = shebang[2..].split_whitespace();
+        let program = interpreter.next()
+            .ok_or(format_err!("empty shebang line"))?;
+
+        fs::write(tmp_file.as_ref(), content)
+            .context("failed to write script to temporary file")?;
+
+        let mut command = Command::new(program);
+        command.args(interpreter).arg(tmp_file.as_ref());
+        command
+    } else {
+        let mut shell_args = shell.split_whitespace();
+        let program = shell_args.next()
+            .ok_or(format_err!("empty shell command"))?;
+
+        // the first argument after the command becomes $0
+        let mut command = Command::new(program);
+        command.args(shell_args).arg("-c").arg(content).arg("rsm");
+        command
+    };
+
+    let status = command.args(args)
+        .status()
+        .context("failed to start snippet")?;
+
+    Ok(status)
+}
+
 fn read_file_content<P: AsRef<Path>>(path: P) -> Result<String, Error> {
     let mut file = fs::File::open(path.as_ref())
         .context("failed to open tmp file: {}")?;
@@ -55,27 +95,71 @@ pub fn get_from_stdin() -> Result<String, Error> {
     Ok(content)
 }
 
-pub fn get_from_editor(init_content: Option<String>) -> Result<String, Error> {
-    // tmp file gets deleted when going out of scope
-    let tmp_file = Temp::new_file()
-        .context("failed to create temporary file")?;
+// the extension lets the editor highlight the content
+pub fn get_from_editor(editor: &str, init_content: Option<String>, extension: Option<&str>) -> Result<String, Error> {
+    // tmp dir and file get deleted when going out of scope
+    let tmp_dir = Temp::new_dir()
+        .context("failed to create temporary directory")?;
 
-    // initial content to edit. Used when editing a snippet
-    if let Some(content) = init_content {
-        let mut file = fs::OpenOptions::new().write(true).open(tmp_file.as_ref())
-            .context("failed to open temporary file")?;
+    let tmp_file = match extension {
+        Some(extension) => tmp_dir.as_ref().join(format!("snippet.{}", extension)),
+        None => tmp_dir.as_ref().join("snippet"),
+    };
 
-        file.write_all(content.as_bytes())
-            .context("failed to write content to temporary file")?;
-    }
+    // initial content to edit. Used when editing a snippet
+    fs::write(&tmp_file, init_content.unwrap_or_default())
+        .context("failed to write content to temporary file")?;
 
     // run the editor and write the content to the tmp file
-    run_editor(tmp_file.as_ref())
+    run_editor(editor, &tmp_file)
         .context("failed to start editor")?;
 
     // read the content written by the editor
-    let content = read_file_content(tmp_file.as_ref())
+    let content = read_file_content(&tmp_file)
         .context("failed to fetch content from tmp file")?;
 
     Ok(content)
 }
+
+// tried in order when no clipboard command is configured
+static CLIPBOARD_COMMANDS: &[&str] = &[
+    "wl-copy",
+    "xclip -selection clipboard",
+    "xsel --clipboard --input",
+    "pbcopy",
+];
+
+// pipe the content into the clipboard command
+pub fn copy_to_clipboard(command: Option<&str>, content: &str) -> Result<(), Error> {
+    let commands = match command {
+        Some(command) => vec![command],
+        None => CLIPBOARD_COMMANDS.to_vec(),
+    };
+
+    for command in commands {
+        let mut args = command.split_whitespace();
+        let program = args.next()
+            .ok_or(format_err!("empty clipboard command"))?;
+
+        let mut child = match Command::new(program).args(args).stdin(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            // try the next one if the program is not installed
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.context(format!("failed to run clipboard command '{}'", command)).into()),
+        };
+
+        child.stdin.take().unwrap().write_all(content.as_bytes())
+            .context("failed to write to clipboard command")?;
+
+        let status = child.wait()
+            .context("failed to wait for clipboard command")?;
+
+        if !status.success() {
+            bail!("clipboard command '{}' failed", command);
+        }
+
+        return Ok(());
+    }
+
+    bail!("no clipboard command found, set RSM_CLIPBOARD")
+}
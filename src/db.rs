@@ -1,35 +1,294 @@
-use std::path::PathBuf;
-use std::{env, fs};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::{fs, io, cmp};
 use sqlite::{self, Connection, Value, State};
 use failure::*;
-use snippet::Snippet;
+use snippet::{self, Snippet, SearchMatch, Revision, RevisionField, Conflict};
+#[cfg(feature = "sync")]
+use config::ConflictStrategy;
 use util;
-use error;
+use error::ErrorKind;
 #[cfg(feature = "sync")]
 use sync;
 
-static DB_PATH: &'static str = ".local/rsm/data.db";
-
-pub fn connect() -> Result<Connection, Error> {
-    let db_file = get_db_path()
-        .context("failed to get database path")?;
+// SQL expression generating a random (version 4) UUID
+macro_rules! uuid_sql {
+    () => {
+        "lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4' ||
+        substr(lower(hex(randomblob(2))), 2) || '-' ||
+        substr('89ab', 1 + (abs(random()) % 4), 1) ||
+        substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)))"
+    };
+}
 
+// Ordered list of schema migrations. The schema version of a database is the
+// number of migrations applied to it, so new migrations must only ever be
+// appended to the end of this list.
+static MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE snippets(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name VARCHAR(64) UNIQUE,
+        content TEXT,
+        deleted INTEGER DEFAULT 0,
+        last_updated INTEGER NOT NULL
+    );
+    CREATE TABLE tags(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name VARCHAR(64),
+        deleted INTEGER DEFAULT 0,
+        last_updated INTEGER NOT NULL,
+        UNIQUE(name) ON CONFLICT IGNORE
+    );
+    CREATE TABLE snippet_tags(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        snippet_id INTEGER REFERENCES snippets(id),
+        tag_id INTEGER REFERENCES tags(id),
+        deleted INTEGER DEFAULT 0,
+        last_updated INTEGER NOT NULL,
+        UNIQUE(snippet_id, tag_id)
+    );
+    CREATE TABLE metadata(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        key VARCHAR(32),
+        value TEXT,
+        UNIQUE(key) ON CONFLICT REPLACE
+    );
+    INSERT INTO `metadata` (key, value) VALUES ('last_synced', 0);",
+    // 2: revision history of snippet names and contents
+    "CREATE TABLE revisions(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        snippet_id INTEGER REFERENCES snippets(id),
+        revision INTEGER NOT NULL,
+        field VARCHAR(16) NOT NULL,
+        old_value TEXT,
+        new_value TEXT,
+        created INTEGER NOT NULL,
+        UNIQUE(snippet_id, revision)
+    );",
+    // 3: full-text index over snippet names and contents
+    "CREATE VIRTUAL TABLE snippets_fts USING fts5(
+        name,
+        content,
+        content='snippets',
+        content_rowid='id'
+    );
+    CREATE TRIGGER snippets_fts_insert AFTER INSERT ON snippets BEGIN
+        INSERT INTO snippets_fts (rowid, name, content)
+        VALUES (new.id, new.name, new.content);
+    END;
+    CREATE TRIGGER snippets_fts_delete AFTER DELETE ON snippets BEGIN
+        INSERT INTO snippets_fts (snippets_fts, rowid, name, content)
+        VALUES ('delete', old.id, old.name, old.content);
+    END;
+    CREATE TRIGGER snippets_fts_update AFTER UPDATE OF name, content ON snippets BEGIN
+        INSERT INTO snippets_fts (snippets_fts, rowid, name, content)
+        VALUES ('delete', old.id, old.name, old.content);
+        INSERT INTO snippets_fts (rowid, name, content)
+        VALUES (new.id, new.name, new.content);
+    END;
+    INSERT INTO snippets_fts (snippets_fts) VALUES ('rebuild');",
+    // 4: records of purged snippets for the sync server
+    "CREATE TABLE tombstones(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        snippet_id INTEGER NOT NULL,
+        name VARCHAR(64),
+        last_updated INTEGER NOT NULL
+    );",
+    // 5: sync base of snippets and unresolved sync conflicts
+    "ALTER TABLE snippets ADD COLUMN base_name VARCHAR(64);
+    ALTER TABLE snippets ADD COLUMN base_content TEXT;
+    CREATE TABLE conflicts(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        snippet_id INTEGER REFERENCES snippets(id),
+        remote_name VARCHAR(64),
+        remote_content TEXT,
+        created INTEGER NOT NULL,
+        UNIQUE(snippet_id) ON CONFLICT REPLACE
+    );",
+    // 6: globally unique snippet ids used by sync
+    concat!(
+    "ALTER TABLE snippets ADD COLUMN uuid CHAR(36);
+    UPDATE snippets SET uuid = ", uuid_sql!(), ";
+    CREATE UNIQUE INDEX snippets_uuid ON snippets(uuid);
+    CREATE TRIGGER snippets_uuid_insert AFTER INSERT ON snippets
+    WHEN new.uuid IS NULL BEGIN
+        UPDATE snippets SET uuid = ", uuid_sql!(), " WHERE id = new.id;
+    END;
+    ALTER TABLE tombstones ADD COLUMN snippet_uuid CHAR(36);"),
+    // 7: last used values of template placeholders
+    "CREATE TABLE variables(
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        snippet_id INTEGER REFERENCES snippets(id),
+        name VARCHAR(64) NOT NULL,
+        value TEXT,
+        UNIQUE(snippet_id, name) ON CONFLICT REPLACE
+    );",
+    // 8: language of snippets for highlighting
+    "ALTER TABLE snippets ADD COLUMN language VARCHAR(32);",
+    // 9: description, source and creation time of snippets. Creation times
+    // were not recorded before, the first revision is the best guess. The
+    // full-text index is rebuilt to cover the new columns
+    "ALTER TABLE snippets ADD COLUMN description TEXT;
+    ALTER TABLE snippets ADD COLUMN source TEXT;
+    ALTER TABLE snippets ADD COLUMN created_at INTEGER;
+    UPDATE snippets SET created_at = IFNULL(
+        (SELECT MIN(created) FROM revisions WHERE revisions.snippet_id = snippets.id),
+        last_updated);
+    DROP TRIGGER snippets_fts_insert;
+    DROP TRIGGER snippets_fts_delete;
+    DROP TRIGGER snippets_fts_update;
+    DROP TABLE snippets_fts;
+    CREATE VIRTUAL TABLE snippets_fts USING fts5(
+        name,
+        content,
+        description,
+        source,
+        content='snippets',
+        content_rowid='id'
+    );
+    CREATE TRIGGER snippets_fts_insert AFTER INSERT ON snippets BEGIN
+        INSERT INTO snippets_fts (rowid, name, content, description, source)
+        VALUES (new.id, new.name, new.content, new.description, new.source);
+    END;
+    CREATE TRIGGER snippets_fts_delete AFTER DELETE ON snippets BEGIN
+        INSERT INTO snippets_fts (snippets_fts, rowid, name, content, description, source)
+        VALUES ('delete', old.id, old.name, old.content, old.description, old.source);
+    END;
+    CREATE TRIGGER snippets_fts_update AFTER UPDATE OF name, content, description, source ON snippets BEGIN
+        INSERT INTO snippets_fts (snippets_fts, rowid, name, content, description, source)
+        VALUES ('delete', old.id, old.name, old.content, old.description, old.source);
+        INSERT INTO snippets_fts (rowid, name, content, description, source)
+        VALUES (new.id, new.name, new.content, new.description, new.source);
+    END;
+    INSERT INTO snippets_fts (snippets_fts) VALUES ('rebuild');",
+    // 10: revision counter of snippets deciding which side wins a conflict
+    "ALTER TABLE snippets ADD COLUMN version INTEGER NOT NULL DEFAULT 0;",
+    // 11: local time of the last sync. The last sync time is the one of the
+    // server from now on, it was the local one before
+    "INSERT INTO `metadata` (key, value)
+    SELECT 'last_uploaded', value FROM `metadata` WHERE key = 'last_synced';",
+];
+
+pub fn connect(db_file: &Path) -> Result<Connection, Error> {
     // check if there is no database yet
     let is_init = db_file.exists();
 
     // init database if necessary
     let conn = if !is_init {
-        init(&db_file)
+        init(db_file)
             .context("failed to initialize database")?
     } else {
-        sqlite::open(db_file.to_str().unwrap())
-            .map_err::<Error, _>(|e| e.into())?
+        let conn = sqlite::open(db_file.to_str().unwrap())
+            .map_err::<Error, _>(|e| e.into())?;
+
+        // once, while the schema is upgraded
+        let version = get_schema_version(&conn)
+            .context("failed to get schema version")?;
+
+        if version < MIGRATIONS.len() as i64 {
+            restrict_permissions(db_file)
+                .context("failed to restrict database permissions")?;
+        }
+
+        conn
     };
 
+    setup(&conn)?;
+
+    Ok(conn)
+}
+
+// a database that is gone once the connection is closed
+pub fn connect_in_memory() -> Result<Connection, Error> {
+    let conn = sqlite::open(":memory:")
+        .map_err::<Error, _>(|e| e.into())?;
+
+    setup(&conn)?;
+
+    Ok(conn)
+}
+
+fn setup(conn: &Connection) -> Result<(), Error> {
     conn.execute("PRAGMA foreign_keys = ON")
         .context("failed to enable foreign_key support")?;
 
-    Ok(conn)
+    // bring the schema up to date
+    migrate(conn)
+        .context("failed to migrate database schema")?;
+
+    Ok(())
+}
+
+// Savepoints are used instead of plain transactions so that functions
+// running in a transaction can be called from other transactions.
+pub fn transaction<T, F>(conn: &Connection, f: F) -> Result<T, Error>
+    where F: FnOnce(&Connection) -> Result<T, Error> {
+    conn.execute("SAVEPOINT rsm_transaction")
+        .context("failed to begin transaction")?;
+
+    match f(conn) {
+        Ok(x) => {
+            conn.execute("RELEASE rsm_transaction")
+                .context("failed to commit transaction")?;
+            Ok(x)
+        },
+        Err(e) => {
+            // the original error is more interesting than a failed rollback
+            conn.execute("ROLLBACK TO rsm_transaction; RELEASE rsm_transaction").ok();
+            Err(e)
+        }
+    }
+}
+
+// ids win over names like when resolving by the list of names
+pub fn find_snippet_id(conn: &Connection, query: &str) -> Result<Option<i64>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT id FROM `snippets`
+        WHERE deleted = 0 AND (id = ?1 OR name = ?2)
+        ORDER BY id = ?1 DESC
+        LIMIT 1")
+        .context("failed to prepare lookup statement")?;
+
+    let snippet_id = match i64::from_str(query) {
+        Ok(x) => Value::Integer(x),
+        Err(_) => Value::Null,
+    };
+
+    statement.bind(1, &snippet_id)
+        .context("failed to bind snippet id")?;
+    statement.bind(2, query)
+        .context("failed to bind name")?;
+
+    if statement.next().context("failed to execute sql statement")? == State::Done {
+        return Ok(None);
+    }
+
+    let snippet_id = statement.read::<i64>(0)
+        .context("failed to read id col")?;
+
+    Ok(Some(snippet_id))
+}
+
+pub fn get_snippet_names(conn: &Connection) -> Result<Vec<(i64, String)>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT id, name FROM `snippets` WHERE deleted = 0 ORDER BY id")
+        .context("failed to prepare load statement")?;
+
+    let mut names = Vec::new();
+
+    while let State::Row = statement.next().context("failed to execute sql statement")? {
+        let snippet_id = statement.read::<i64>(0)
+            .context("failed to read id col")?;
+        let name = statement.read::<String>(1)
+            .context("failed to read name col")?;
+
+        names.push((snippet_id, name));
+    }
+
+    Ok(names)
 }
 
 pub fn search_snippets(conn: &Connection, name: Option<String>, tags: Option<Vec<&str>>) -> Result<Vec<Snippet>, Error> {
@@ -44,11 +303,11 @@ pub fn search_snippets(conn: &Connection, name: Option<String>, tags: Option<Vec
         .join(" AND "));
 
     let query = format!(
-        "SELECT S.id, S.name, S.content FROM `snippets` AS S
+        "SELECT S.id, S.name, S.content, S.last_updated, S.language, S.description, S.source, S.created_at FROM `snippets` AS S
         WHERE S.deleted = 0 AND {}
         UNION
-        SELECT S.id, S.name, S.content FROM `snippets` AS S
-        INNER JOIN `snippet_tags` AS ST ON ST.snippet_id = S.id
+        SELECT S.id, S.name, S.content, S.last_updated, S.language, S.description, S.source, S.created_at FROM `snippets` AS S
+        INNER JOIN `snippet_tags` AS ST ON ST.snippet_id = S.id AND ST.deleted = 0
         INNER JOIN `tags` AS T ON T.id = ST.tag_id
         WHERE S.deleted = 0 AND {}
         GROUP BY S.id",
@@ -83,6 +342,16 @@ pub fn search_snippets(conn: &Connection, name: Option<String>, tags: Option<Vec
             .context("failed to read snippet name")?;
         let content = statement.read::<String>(2)
             .context("failed to read content")?;
+        let updated_at = statement.read::<i64>(3)
+            .context("failed to read update time")?;
+        let language = read_optional_string(&statement, 4)
+            .context("failed to read language")?;
+        let description = read_optional_string(&statement, 5)
+            .context("failed to read description")?;
+        let source = read_optional_string(&statement, 6)
+            .context("failed to read source")?;
+        let created_at = statement.read::<i64>(7)
+            .context("failed to read creation time")?;
 
         let tags = get_snippet_tags(conn, snippet_id)
             .context("failed to load snippet tags")?;
@@ -91,7 +360,12 @@ pub fn search_snippets(conn: &Connection, name: Option<String>, tags: Option<Vec
             id: snippet_id,
             name: name,
             content: content,
-            tags: tags
+            tags: tags,
+            language: language,
+            description: description,
+            source: source,
+            created_at: created_at,
+            updated_at: updated_at,
         };
 
         snippets.push(snippet);
@@ -100,9 +374,88 @@ pub fn search_snippets(conn: &Connection, name: Option<String>, tags: Option<Vec
     Ok(snippets)
 }
 
+// SQLITE_ERROR, which is all FTS5 reports for a malformed query
+const SQLITE_ERROR_CODE: isize = 1;
+
+// the query is only parsed once the statement runs
+fn search_error(query: &str, e: sqlite::Error) -> Error {
+    if e.code != Some(SQLITE_ERROR_CODE) {
+        return e.context("failed to execute sql statement").into();
+    }
+
+    let message = e.message.unwrap_or_default();
+    let message = message.trim_start_matches("fts5: ");
+
+    ErrorKind::InvalidArgument(format!("invalid search query '{}': {}", query, message)).into()
+}
+
+pub fn full_text_search(conn: &Connection, query: &str) -> Result<Vec<SearchMatch>, Error> {
+    // matches in names rank highest, then those in descriptions
+    let mut statement = conn.prepare(
+        "SELECT S.id, S.name, S.content, S.last_updated, snippet(snippets_fts, 1, ?, ?, '...', 12), S.language,
+            S.description, S.source, S.created_at
+        FROM `snippets_fts` AS F
+        INNER JOIN `snippets` AS S ON S.id = F.rowid
+        WHERE S.deleted = 0 AND snippets_fts MATCH ?
+        ORDER BY bm25(snippets_fts, 10.0, 1.0, 5.0, 1.0)")
+        .context("failed to prepare search statement")?;
+
+    statement.bind(1, snippet::MATCH_START)
+        .context("failed to bind match start marker")?;
+    statement.bind(2, snippet::MATCH_END)
+        .context("failed to bind match end marker")?;
+    statement.bind(3, query)
+        .context("failed to bind query")?;
+
+    let mut matches = Vec::new();
+
+    while let State::Row = statement.next().map_err(|e| search_error(query, e))? {
+        let snippet_id = statement.read::<i64>(0)
+            .context("failed to read snippet id")?;
+        let name = statement.read::<String>(1)
+            .context("failed to read snippet name")?;
+        let content = statement.read::<String>(2)
+            .context("failed to read content")?;
+        let updated_at = statement.read::<i64>(3)
+            .context("failed to read update time")?;
+        let excerpt = statement.read::<String>(4)
+            .context("failed to read excerpt")?;
+        let language = read_optional_string(&statement, 5)
+            .context("failed to read language")?;
+        let description = read_optional_string(&statement, 6)
+            .context("failed to read description")?;
+        let source = read_optional_string(&statement, 7)
+            .context("failed to read source")?;
+        let created_at = statement.read::<i64>(8)
+            .context("failed to read creation time")?;
+
+        let tags = get_snippet_tags(conn, snippet_id)
+            .context("failed to load snippet tags")?;
+
+        let snippet = Snippet {
+            id: snippet_id,
+            name: name,
+            content: content,
+            tags: tags,
+            language: language,
+            description: description,
+            source: source,
+            created_at: created_at,
+            updated_at: updated_at,
+        };
+
+        matches.push(SearchMatch {
+            snippet: snippet,
+            excerpt: excerpt,
+        });
+    }
+
+    Ok(matches)
+}
+
 pub fn get_snippet(conn: &Connection, snippet_id: i64) -> Result<Snippet, Error> {
     let mut statement = conn.prepare(
-        "SELECT name, content FROM `snippets`
+        "SELECT name, content, last_updated, language, description, source, created_at FROM `snippets`
         WHERE deleted = 0 AND id = ?")
         .context("failed to prepare load statement")?;
 
@@ -113,13 +466,23 @@ pub fn get_snippet(conn: &Connection, snippet_id: i64) -> Result<Snippet, Error>
         .context("failed to execute sql statement")?;
 
     if state == State::Done {
-        bail!(error::UnknownSnippetId);
+        return Err(ErrorKind::UnknownSnippetId.into());
     }
 
     let name = statement.read::<String>(0)
         .context("failed to read snippet name")?;
     let content = statement.read::<String>(1)
         .context("failed to read snippet content")?;
+    let updated_at = statement.read::<i64>(2)
+        .context("failed to read update time")?;
+    let language = read_optional_string(&statement, 3)
+        .context("failed to read language")?;
+    let description = read_optional_string(&statement, 4)
+        .context("failed to read description")?;
+    let source = read_optional_string(&statement, 5)
+        .context("failed to read source")?;
+    let created_at = statement.read::<i64>(6)
+        .context("failed to read creation time")?;
 
     let tags = get_snippet_tags(conn, snippet_id)
         .context("failed to load snippet tags")?;
@@ -128,19 +491,26 @@ pub fn get_snippet(conn: &Connection, snippet_id: i64) -> Result<Snippet, Error>
         id: snippet_id,
         name: name,
         content: content,
-        tags: tags
+        tags: tags,
+        language: language,
+        description: description,
+        source: source,
+        created_at: created_at,
+        updated_at: updated_at,
     };
 
     Ok(snippet)
 }
 
 pub fn delete_snippet(conn: &Connection, snippet_id: i64) -> Result<(), Error> {
+    let now = util::get_utc_now();
+
     let mut statement = conn.prepare(
-        "UPDATE `snippets` SET deleted = 1, last_updated = ?
+        "UPDATE `snippets` SET deleted = 1, last_updated = ?, version = version + 1
         WHERE id = ?")
         .context("failed to prepare load statement")?;
 
-    statement.bind(1, util::get_utc_now())
+    statement.bind(1, now)
         .context("failed to bind time")?;
     statement.bind(2, snippet_id)
         .context("failed to bind snippet id")?;
@@ -148,44 +518,227 @@ pub fn delete_snippet(conn: &Connection, snippet_id: i64) -> Result<(), Error> {
     statement.next()
         .context("failed to execute sql statement")?;
 
-    // remove all tags linked to this snippet
-    remove_tags_by_snippet_id(conn, snippet_id)
+    // remove all tags linked to this snippet. They share the deletion time
+    // so restoring the snippet can tell them apart from older removals
+    remove_tags_by_snippet_id(conn, snippet_id, now)
         .context("failed to delete snippet tags")?;
 
     Ok(())
 }
 
+pub fn get_deleted_snippets(conn: &Connection) -> Result<Vec<(Snippet, i64)>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT id, name, content, last_updated, language, description, source, created_at FROM `snippets`
+        WHERE deleted = 1
+        ORDER BY last_updated DESC")
+        .context("failed to prepare load statement")?;
+
+    let mut snippets = Vec::new();
+
+    while let State::Row = statement.next().context("failed to execute sql statement")? {
+        let snippet_id = statement.read::<i64>(0)
+            .context("failed to read snippet id")?;
+        let name = statement.read::<String>(1)
+            .context("failed to read snippet name")?;
+        let content = statement.read::<String>(2)
+            .context("failed to read content")?;
+        let deleted_at = statement.read::<i64>(3)
+            .context("failed to read deletion time")?;
+        let language = read_optional_string(&statement, 4)
+            .context("failed to read language")?;
+        let description = read_optional_string(&statement, 5)
+            .context("failed to read description")?;
+        let source = read_optional_string(&statement, 6)
+            .context("failed to read source")?;
+        let created_at = statement.read::<i64>(7)
+            .context("failed to read creation time")?;
+
+        let snippet = Snippet {
+            id: snippet_id,
+            name: name,
+            content: content,
+            tags: Vec::new(),
+            language: language,
+            description: description,
+            source: source,
+            created_at: created_at,
+            updated_at: deleted_at,
+        };
+
+        snippets.push((snippet, deleted_at));
+    }
+
+    Ok(snippets)
+}
+
+pub fn restore_snippet(conn: &Connection, snippet_id: i64) -> Result<(), Error> {
+    let mut statement = conn.prepare(
+        "SELECT last_updated FROM `snippets`
+        WHERE deleted = 1 AND id = ?")
+        .context("failed to prepare load statement")?;
+
+    statement.bind(1, snippet_id)
+        .context("failed to bind snippet id")?;
+
+    let state = statement.next()
+        .context("failed to execute sql statement")?;
+
+    if state == State::Done {
+        return Err(ErrorKind::UnknownSnippetId.into());
+    }
+
+    let deleted_at = statement.read::<i64>(0)
+        .context("failed to read deletion time")?;
+
+    transaction(conn, |conn| {
+        let now = util::get_utc_now();
+
+        let mut statement = conn.prepare(
+            "UPDATE `snippets` SET deleted = 0, last_updated = ?, version = version + 1
+            WHERE id = ?")
+            .context("failed to prepare restore statement")?;
+
+        statement.bind(1, now)
+            .context("failed to bind time")?;
+        statement.bind(2, snippet_id)
+            .context("failed to bind snippet id")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        // only relink the tags removed together with the snippet
+        let mut statement = conn.prepare(
+            "UPDATE `snippet_tags` SET deleted = 0, last_updated = ?
+            WHERE deleted = 1 AND snippet_id = ? AND last_updated = ?")
+            .context("failed to prepare tag restore statement")?;
+
+        statement.bind(1, now)
+            .context("failed to bind time")?;
+        statement.bind(2, snippet_id)
+            .context("failed to bind snippet id")?;
+        statement.bind(3, deleted_at)
+            .context("failed to bind deletion time")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        Ok(())
+    })
+}
+
+pub fn purge_snippets(conn: &Connection, older_than: Option<i64>) -> Result<(usize, usize), Error> {
+    let requested_before = older_than.map_or(i64::MAX, |x| util::get_utc_now() - x);
+    let mut deleted_before = requested_before;
+
+    // once the snippets are synced, deletions have to reach the server
+    // before the rows can go
+    if cfg!(feature = "sync") {
+        let last_uploaded = get_metadata_value(conn, "last_uploaded")
+            .context("failed to get last upload time")?;
+        let last_uploaded = i64::from_str(last_uploaded.as_str())
+            .context("failed to parse last upload string")?;
+
+        let logged_in = find_metadata_value(conn, "sync_token")
+            .context("failed to get sync token")?
+            .is_some();
+
+        if logged_in || last_uploaded > 0 {
+            deleted_before = cmp::min(deleted_before, last_uploaded + 1);
+        }
+    }
+
+    transaction(conn, |conn| {
+        let now = util::get_utc_now();
+
+        let mut statement = conn.prepare(
+            "SELECT COUNT(*) FROM `snippets`
+            WHERE deleted = 1 AND last_updated >= ? AND last_updated < ?")
+            .context("failed to prepare held back statement")?;
+
+        statement.bind(1, deleted_before)
+            .context("failed to bind deletion time")?;
+        statement.bind(2, requested_before)
+            .context("failed to bind deletion time")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        let held_back = statement.read::<i64>(0)
+            .context("failed to read held back count")?;
+
+        // remember the purged snippets so the server can drop them as well
+        let mut statement = conn.prepare(
+            "INSERT INTO `tombstones` (snippet_id, snippet_uuid, name, last_updated)
+            SELECT id, uuid, name, ? FROM `snippets`
+            WHERE deleted = 1 AND last_updated < ?")
+            .context("failed to prepare tombstone statement")?;
+
+        statement.bind(1, now)
+            .context("failed to bind time")?;
+        statement.bind(2, deleted_before)
+            .context("failed to bind deletion time")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        let tables = vec![("snippet_tags", "snippet_id"), ("revisions", "snippet_id"), ("conflicts", "snippet_id"), ("variables", "snippet_id"), ("snippets", "id")];
+
+        for (table, col) in tables {
+            let query = format!(
+                "DELETE FROM `{}` WHERE {} IN (
+                    SELECT id FROM `snippets`
+                    WHERE deleted = 1 AND last_updated < ?
+                )", table, col);
+
+            let mut statement = conn.prepare(query)
+                .context("failed to prepare purge statement")?;
+
+            statement.bind(1, deleted_before)
+                .context("failed to bind deletion time")?;
+
+            statement.next()
+                .context("failed to execute sql statement")?;
+        }
+
+        let purged = get_changes(conn)
+            .context("failed to count purged snippets")?;
+
+        Ok((purged as usize, held_back as usize))
+    })
+}
+
 pub fn save_snippet(conn: &Connection, name: String, content: String, tags: Option<Vec<&str>>) -> Result<i64, Error> {
-    let new_last_updated = util::get_utc_now();
+    // names stay taken by deleted snippets until they are purged
+    if let Some((_, deleted)) = get_snippet_meta(&conn, name.as_str()).context("failed to look up snippet name")? {
+        if deleted {
+            return Err(ErrorKind::DeletedSnippetName.into());
+        }
+
+        return Err(ErrorKind::DupSnippetName.into());
+    }
 
     let mut statement = conn.prepare(
-        "INSERT INTO `snippets` (name, content, last_updated)
-        VALUES (?, ?, ?)
-        ON CONFLICT(name) DO UPDATE
-        SET content = ?, deleted = 0, last_updated = ?
-        WHERE deleted = 1")
+        "INSERT INTO `snippets` (name, content, created_at, last_updated)
+        VALUES (?, ?, ?, ?)")
         .context("failed to prepare save statement")?;
 
+    let now = util::get_utc_now();
+
     statement.bind(1, name.as_str())
         .context("failed to bind name")?;
     statement.bind(2, content.as_str())
         .context("failed to bind content")?;
-    statement.bind(3, new_last_updated)
-        .context("failed to bind time")?;
-    statement.bind(4, content.as_str())
-        .context("failed to bind content")?;
-    statement.bind(5, new_last_updated)
+    statement.bind(3, now)
+        .context("failed to bind creation time")?;
+    statement.bind(4, now)
         .context("failed to bind time")?;
 
     statement.next()
         .context("failed to execute sql statement")?;
 
-    let (snippet_id, last_updated) = get_snippet_meta(&conn, name.as_str())
-        .context("failed to get snippet id")?;
-
-    if new_last_updated != last_updated {
-        bail!(error::DupSnippetName);
-    }
+    let (snippet_id, _) = get_snippet_meta(&conn, name.as_str())
+        .context("failed to get snippet id")?
+        .ok_or(ErrorKind::UnknownSnippetId)?;
 
     if let Some(tags) = tags {
         save_tags(&conn, snippet_id, tags)
@@ -196,12 +749,43 @@ pub fn save_snippet(conn: &Connection, name: String, content: String, tags: Opti
 }
 
 pub fn change_snippet_content(conn: &Connection, snippet_id: i64, content: String) -> Result<(), Error> {
+    let snippet = get_snippet(conn, snippet_id)
+        .context("failed to load snippet")?;
+
+    transaction(conn, |conn| {
+        let mut statement = conn.prepare(
+            "UPDATE `snippets` SET content = ?, last_updated = ?, version = version + 1 WHERE id = ?;")
+            .context("failed to prepare content change statement")?;
+
+        statement.bind(1, content.as_str())
+            .context("failed to bind content")?;
+        statement.bind(2, util::get_utc_now())
+            .context("failed to bind time")?;
+        statement.bind(3, snippet_id)
+            .context("failed to bind id")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        save_revision(conn, snippet_id, RevisionField::Content, snippet.content.as_str(), content.as_str())
+            .context("failed to save content revision")?;
+
+        Ok(())
+    })
+}
+
+// `None` clears the language. Like any change it is synced, so it bumps the
+// update time and the version
+pub fn set_snippet_language(conn: &Connection, snippet_id: i64, language: Option<&str>) -> Result<(), Error> {
+    get_snippet(conn, snippet_id)
+        .context("failed to load snippet")?;
+
     let mut statement = conn.prepare(
-        "UPDATE `snippets` SET content = ?, last_updated = ? WHERE id = ?;")
-        .context("failed to prepare content change statement")?;
+        "UPDATE `snippets` SET language = ?, last_updated = ?, version = version + 1 WHERE id = ?;")
+        .context("failed to prepare language change statement")?;
 
-    statement.bind(1, content.as_str())
-        .context("failed to bind content")?;
+    statement.bind(1, &optional_value(language))
+        .context("failed to bind language")?;
     statement.bind(2, util::get_utc_now())
         .context("failed to bind time")?;
     statement.bind(3, snippet_id)
@@ -213,13 +797,17 @@ pub fn change_snippet_content(conn: &Connection, snippet_id: i64, content: Strin
     Ok(())
 }
 
-pub fn rename_snippet(conn: &Connection, snippet_id: i64, name: String) -> Result<(), Error> {
+// `None` clears the description
+pub fn set_snippet_description(conn: &Connection, snippet_id: i64, description: Option<&str>) -> Result<(), Error> {
+    get_snippet(conn, snippet_id)
+        .context("failed to load snippet")?;
+
     let mut statement = conn.prepare(
-        "UPDATE `snippets` SET name = ?, last_updated = ? WHERE id = ?;")
-        .context("failed to prepare snippet rename statement")?;
+        "UPDATE `snippets` SET description = ?, last_updated = ?, version = version + 1 WHERE id = ?;")
+        .context("failed to prepare description change statement")?;
 
-    statement.bind(1, name.as_str())
-        .context("failed to bind name")?;
+    statement.bind(1, &optional_value(description))
+        .context("failed to bind description")?;
     statement.bind(2, util::get_utc_now())
         .context("failed to bind time")?;
     statement.bind(3, snippet_id)
@@ -231,13 +819,157 @@ pub fn rename_snippet(conn: &Connection, snippet_id: i64, name: String) -> Resul
     Ok(())
 }
 
-fn remove_tags_by_snippet_id(conn: &Connection, snippet_id: i64) -> Result<(), Error> {
+// `None` clears the source
+pub fn set_snippet_source(conn: &Connection, snippet_id: i64, source: Option<&str>) -> Result<(), Error> {
+    get_snippet(conn, snippet_id)
+        .context("failed to load snippet")?;
+
     let mut statement = conn.prepare(
-        "UPDATE `snippet_tags` SET deleted = 1, last_updated = ?
+        "UPDATE `snippets` SET source = ?, last_updated = ?, version = version + 1 WHERE id = ?;")
+        .context("failed to prepare source change statement")?;
+
+    statement.bind(1, &optional_value(source))
+        .context("failed to bind source")?;
+    statement.bind(2, util::get_utc_now())
+        .context("failed to bind time")?;
+    statement.bind(3, snippet_id)
+        .context("failed to bind id")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(())
+}
+
+// the creation time is part of the row, so it is synced with the next change
+pub fn set_snippet_created_at(conn: &Connection, snippet_id: i64, created_at: i64) -> Result<(), Error> {
+    get_snippet(conn, snippet_id)
+        .context("failed to load snippet")?;
+
+    let mut statement = conn.prepare(
+        "UPDATE `snippets` SET created_at = ? WHERE id = ?;")
+        .context("failed to prepare creation time statement")?;
+
+    statement.bind(1, created_at)
+        .context("failed to bind time")?;
+    statement.bind(2, snippet_id)
+        .context("failed to bind id")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(())
+}
+
+pub fn rename_snippet(conn: &Connection, snippet_id: i64, name: String) -> Result<(), Error> {
+    let snippet = get_snippet(conn, snippet_id)
+        .context("failed to load snippet")?;
+
+    if let Some((id, deleted)) = get_snippet_meta(&conn, name.as_str()).context("failed to look up snippet name")? {
+        if deleted {
+            return Err(ErrorKind::DeletedSnippetName.into());
+        }
+
+        if id != snippet_id {
+            return Err(ErrorKind::DupSnippetName.into());
+        }
+    }
+
+    transaction(conn, |conn| {
+        let mut statement = conn.prepare(
+            "UPDATE `snippets` SET name = ?, last_updated = ?, version = version + 1 WHERE id = ?;")
+            .context("failed to prepare snippet rename statement")?;
+
+        statement.bind(1, name.as_str())
+            .context("failed to bind name")?;
+        statement.bind(2, util::get_utc_now())
+            .context("failed to bind time")?;
+        statement.bind(3, snippet_id)
+            .context("failed to bind id")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        save_revision(conn, snippet_id, RevisionField::Name, snippet.name.as_str(), name.as_str())
+            .context("failed to save name revision")?;
+
+        Ok(())
+    })
+}
+
+fn save_revision(conn: &Connection, snippet_id: i64, field: RevisionField, old_value: &str, new_value: &str) -> Result<(), Error> {
+    let mut statement = conn.prepare(
+        "INSERT INTO `revisions` (snippet_id, revision, field, old_value, new_value, created)
+        SELECT ?, IFNULL(MAX(revision), 0) + 1, ?, ?, ?, ?
+        FROM `revisions`
         WHERE snippet_id = ?")
+        .context("failed to prepare revision save statement")?;
+
+    statement.bind(1, snippet_id)
+        .context("failed to bind snippet id")?;
+    statement.bind(2, field.as_str())
+        .context("failed to bind field")?;
+    statement.bind(3, old_value)
+        .context("failed to bind old value")?;
+    statement.bind(4, new_value)
+        .context("failed to bind new value")?;
+    statement.bind(5, util::get_utc_now())
+        .context("failed to bind time")?;
+    statement.bind(6, snippet_id)
+        .context("failed to bind snippet id")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(())
+}
+
+pub fn get_revisions(conn: &Connection, snippet_id: i64) -> Result<Vec<Revision>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT revision, field, old_value, new_value, created FROM `revisions`
+        WHERE snippet_id = ?
+        ORDER BY revision")
+        .context("failed to prepare load statement")?;
+
+    statement.bind(1, snippet_id)
+        .context("failed to bind snippet id")?;
+
+    let mut revisions = Vec::new();
+
+    while let State::Row = statement.next().context("failed to execute sql statement")? {
+        let revision = statement.read::<i64>(0)
+            .context("failed to read revision col")?;
+        let field = statement.read::<String>(1)
+            .context("failed to read field col")?;
+        let old_value = statement.read::<String>(2)
+            .context("failed to read old value col")?;
+        let new_value = statement.read::<String>(3)
+            .context("failed to read new value col")?;
+        let created = statement.read::<i64>(4)
+            .context("failed to read created col")?;
+
+        let field = RevisionField::from_str(field.as_str())
+            .context("failed to parse revision field")?;
+
+        revisions.push(Revision {
+            revision: revision,
+            field: field,
+            old_value: old_value,
+            new_value: new_value,
+            created: created,
+        });
+    }
+
+    Ok(revisions)
+}
+
+fn remove_tags_by_snippet_id(conn: &Connection, snippet_id: i64, now: i64) -> Result<(), Error> {
+    let mut statement = conn.prepare(
+        "UPDATE `snippet_tags` SET deleted = 1, last_updated = ?
+        WHERE deleted = 0 AND snippet_id = ?")
         .context("failed to prepare tag removal statement")?;
 
-    statement.bind(1, util::get_utc_now())
+    statement.bind(1, now)
         .context("failed to bind time")?;
     statement.bind(2, snippet_id)
         .context("failed to bind id")?;
@@ -322,31 +1054,47 @@ pub fn save_tags(conn: &Connection, snippet_id: i64, tags: Vec<&str>) -> Result<
     Ok(())
 }
 
-fn get_snippet_meta(conn: &Connection, name: &str) -> Result<(i64, i64), Error> {
+fn get_snippet_meta(conn: &Connection, name: &str) -> Result<Option<(i64, bool)>, Error> {
     let mut statement = conn.prepare(
-        "SELECT id, last_updated FROM `snippets` WHERE
-        deleted = 0 AND name = ?")
+        "SELECT id, deleted FROM `snippets` WHERE name = ?")
         .context("failed to prepare save statement")?;
 
     statement.bind(1, name)
         .context("failed to bind name")?;
 
-    statement.next()
+    let state = statement.next()
         .context("failed to execute sql statement")?;
 
+    if state == State::Done {
+        return Ok(None);
+    }
+
     let snippet_id = statement.read::<i64>(0)
         .context("failed to read id col")?;
-    let last_updated = statement.read::<i64>(1)
-        .context("failed to read update time col")?;
+    let deleted = statement.read::<i64>(1)
+        .context("failed to read deleted col")?;
 
-    Ok((snippet_id, last_updated))
+    Ok(Some((snippet_id, deleted == 1)))
 }
 
-fn get_snippet_tags(conn: &Connection, snippet_id: i64) -> Result<Vec<String>, Error> {
-    let mut statement = conn.prepare(
-        "SELECT name FROM `tags` AS T
-        INNER JOIN `snippet_tags` AS ST on T.id = ST.tag_id AND
-        ST.deleted = 0 AND ST.snippet_id = ?")
+fn get_changes(conn: &Connection) -> Result<i64, Error> {
+    let mut statement = conn.prepare("SELECT changes()")
+        .context("failed to prepare changes statement")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    let changes = statement.read::<i64>(0)
+        .context("failed to read changes col")?;
+
+    Ok(changes)
+}
+
+fn get_snippet_tags(conn: &Connection, snippet_id: i64) -> Result<Vec<String>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT name FROM `tags` AS T
+        INNER JOIN `snippet_tags` AS ST on T.id = ST.tag_id AND
+        ST.deleted = 0 AND ST.snippet_id = ?")
         .context("failed to prepare load statement")?;
 
     statement.bind(1, snippet_id)
@@ -364,10 +1112,28 @@ fn get_snippet_tags(conn: &Connection, snippet_id: i64) -> Result<Vec<String>, E
     Ok(tags)
 }
 
+// NULL columns are None
+fn read_optional_string(statement: &sqlite::Statement, index: usize) -> Result<Option<String>, Error> {
+    let value = match statement.read::<Value>(index)? {
+        Value::Null => None,
+        value => value.as_string().map(|x| x.to_string()),
+    };
+
+    Ok(value)
+}
+
+// NULL for `None`, the counterpart of `read_optional_string`
+fn optional_value(value: Option<&str>) -> Value {
+    match value {
+        Some(value) => Value::String(value.to_string()),
+        None => Value::Null,
+    }
+}
+
 #[cfg(feature = "sync")]
 fn parse_snippet_row(statement: &mut sqlite::Statement) -> Result<sync::SnippetRow, Error> {
-    let id = statement.read::<i64>(0)
-        .context("failed to read id col")?;
+    let uuid = statement.read::<String>(0)
+        .context("failed to read uuid col")?;
     let name = statement.read::<String>(1)
         .context("failed to read name col")?;
     let content = statement.read::<String>(2)
@@ -376,12 +1142,27 @@ fn parse_snippet_row(statement: &mut sqlite::Statement) -> Result<sync::SnippetR
         .context("failed to read deleted col")?;
     let last_updated = statement.read::<i64>(4)
         .context("failed to read last update col")?;
+    let description = read_optional_string(statement, 5)
+        .context("failed to read description col")?;
+    let source = read_optional_string(statement, 6)
+        .context("failed to read source col")?;
+    let created_at = statement.read::<i64>(7)
+        .context("failed to read creation time col")?;
+    let version = statement.read::<i64>(8)
+        .context("failed to read version col")?;
+    let language = read_optional_string(statement, 9)
+        .context("failed to read language col")?;
 
     let row = sync::SnippetRow {
-        id: id,
+        uuid: uuid,
         name: name,
         content: content,
+        language: language,
+        description: description,
+        source: source,
         deleted: deleted,
+        created_at: created_at,
+        version: version,
         last_updated: last_updated,
     };
 
@@ -390,17 +1171,14 @@ fn parse_snippet_row(statement: &mut sqlite::Statement) -> Result<sync::SnippetR
 
 #[cfg(feature = "sync")]
 fn parse_tag_row(statement: &mut sqlite::Statement) -> Result<sync::TagRow, Error> {
-    let id = statement.read::<i64>(0)
-        .context("failed to read id col")?;
-    let name = statement.read::<String>(1)
+    let name = statement.read::<String>(0)
         .context("failed to read name col")?;
-    let deleted = statement.read::<i64>(2)
+    let deleted = statement.read::<i64>(1)
         .context("failed to read deleted col")?;
-    let last_updated = statement.read::<i64>(3)
+    let last_updated = statement.read::<i64>(2)
         .context("failed to read last update col")?;
 
     let row = sync::TagRow {
-        id: id,
         name: name,
         deleted: deleted,
         last_updated: last_updated,
@@ -411,21 +1189,18 @@ fn parse_tag_row(statement: &mut sqlite::Statement) -> Result<sync::TagRow, Erro
 
 #[cfg(feature = "sync")]
 fn parse_snippet_tag_row(statement: &mut sqlite::Statement) -> Result<sync::SnippetTagRow, Error> {
-    let id = statement.read::<i64>(0)
-        .context("failed to read id col")?;
-    let snippet_id = statement.read::<i64>(1)
-        .context("failed to read snippet id col")?;
-    let tag_id = statement.read::<i64>(2)
-        .context("failed to read tag id col")?;
-    let deleted = statement.read::<i64>(3)
+    let snippet_uuid = statement.read::<String>(0)
+        .context("failed to read snippet uuid col")?;
+    let tag_name = statement.read::<String>(1)
+        .context("failed to read tag name col")?;
+    let deleted = statement.read::<i64>(2)
         .context("failed to read deleted col")?;
-    let last_updated = statement.read::<i64>(4)
+    let last_updated = statement.read::<i64>(3)
         .context("failed to read last update col")?;
 
     let row = sync::SnippetTagRow {
-        id: id,
-        snippet_id: snippet_id,
-        tag_id: tag_id,
+        snippet_uuid: snippet_uuid,
+        tag_name: tag_name,
         deleted: deleted,
         last_updated: last_updated,
     };
@@ -434,19 +1209,45 @@ fn parse_snippet_tag_row(statement: &mut sqlite::Statement) -> Result<sync::Snip
 }
 
 #[cfg(feature = "sync")]
-pub type SyncData = (Vec<sync::SnippetRow>, Vec<sync::TagRow>, Vec<sync::SnippetTagRow>);
+fn parse_tombstone_row(statement: &mut sqlite::Statement) -> Result<sync::TombstoneRow, Error> {
+    let snippet_uuid = statement.read::<String>(0)
+        .context("failed to read snippet uuid col")?;
+    let name = statement.read::<String>(1)
+        .context("failed to read name col")?;
+    let last_updated = statement.read::<i64>(2)
+        .context("failed to read last update col")?;
+
+    let row = sync::TombstoneRow {
+        snippet_uuid: snippet_uuid,
+        name: name,
+        last_updated: last_updated,
+    };
+
+    Ok(row)
+}
 
 #[cfg(feature = "sync")]
-pub fn get_sync_data(conn: &Connection, last_synced: i64) -> Result<SyncData, Error> {
+pub fn get_sync_data(conn: &Connection, last_synced: i64) -> Result<sync::SyncData, Error> {
     let mut snippet_data = Vec::new();
     let mut tag_data = Vec::new();
     let mut snippet_tag_data = Vec::new();
+    let mut tombstone_data = Vec::new();
+
+    let queries = [
+        "SELECT uuid, name, content, deleted, last_updated, description, source, created_at, version, language FROM `snippets`
+        WHERE last_updated > ?",
+        "SELECT name, deleted, last_updated FROM `tags`
+        WHERE last_updated > ?",
+        "SELECT S.uuid, T.name, ST.deleted, ST.last_updated FROM `snippet_tags` AS ST
+        INNER JOIN `snippets` AS S ON S.id = ST.snippet_id
+        INNER JOIN `tags` AS T ON T.id = ST.tag_id
+        WHERE ST.last_updated > ?",
+        // tombstones from before snippets had uuids can't be matched remotely
+        "SELECT snippet_uuid, name, last_updated FROM `tombstones`
+        WHERE snippet_uuid IS NOT NULL AND last_updated > ?",
+    ];
 
-    let tables = vec!["snippets", "tags", "snippet_tags"];
-
-    for (idx, table) in tables.iter().enumerate() {
-        let query = format!("SELECT * FROM `{}` WHERE last_updated > ?", table);
-
+    for (idx, query) in queries.iter().enumerate() {
         let mut statement = conn.prepare(query)
             .context("failed to prepare sync statement")?;
 
@@ -470,15 +1271,551 @@ pub fn get_sync_data(conn: &Connection, last_synced: i64) -> Result<SyncData, Er
                         .context("failed to parse snippet tag row")?;
                     snippet_tag_data.push(row);
                 },
+                3 => {
+                    let row = parse_tombstone_row(&mut statement)
+                        .context("failed to parse tombstone row")?;
+                    tombstone_data.push(row);
+                },
                 _ => panic!("unexpected error")
             };
         }
     }
 
-    Ok((snippet_data, tag_data, snippet_tag_data))
+    Ok((snippet_data, tag_data, snippet_tag_data, tombstone_data))
+}
+
+// Merge the changes of the sync server into the local tables. Rows only
+// replace local ones that were changed before them.
+#[cfg(feature = "sync")]
+pub fn apply_sync_data(conn: &Connection, data: sync::SyncData, last_synced: i64, strategy: ConflictStrategy) -> Result<usize, Error> {
+    let (snippets, tags, snippet_tags, tombstones) = data;
+    let mut conflicts = 0;
+    let mut collisions = Vec::new();
+
+    for row in snippets {
+        if apply_snippet_row(conn, row, last_synced, strategy, &mut collisions).context("failed to apply snippet row")? {
+            conflicts += 1;
+        }
+    }
+
+    // everything not in conflict is now known to the server
+    conn.execute(
+        "UPDATE `snippets` SET base_name = name, base_content = content
+        WHERE id NOT IN (SELECT snippet_id FROM `conflicts`)")
+        .context("failed to update sync base")?;
+
+    // new remote snippets named like a different local one are renamed. The
+    // base keeps the remote name so the rename is synced back
+    for row in collisions {
+        insert_snippet_row(conn, &row)
+            .context("failed to save snippet")?;
+    }
+
+    // tags are identified by their unique name
+    let mut tag_statement = conn.prepare(
+        "INSERT INTO `tags` (name, deleted, last_updated)
+        VALUES (?, ?, ?)
+        ON CONFLICT(name) DO UPDATE
+        SET deleted = excluded.deleted, last_updated = excluded.last_updated
+        WHERE excluded.last_updated > tags.last_updated")
+        .context("failed to prepare tag sync statement")?
+        .cursor();
+
+    for row in tags {
+        tag_statement.bind(&[Value::String(row.name),
+                             Value::Integer(row.deleted),
+                             Value::Integer(row.last_updated)])
+            .context("failed to bind values")?;
+
+        tag_statement.next()
+            .context("failed to execute sql statement")?;
+    }
+
+    // links can reference tags that were created before the last sync
+    let mut insert_tag = conn.prepare(
+       "INSERT INTO `tags` (name, last_updated)
+       VALUES (?, ?)")
+       .context("failed to prepare tag save statement")?
+       .cursor();
+
+    let mut snippet_tag_statement = conn.prepare(
+        "INSERT INTO `snippet_tags` (snippet_id, tag_id, deleted, last_updated)
+        SELECT S.id, T.id, ?, ?
+        FROM `snippets` AS S, `tags` AS T
+        WHERE S.uuid = ? AND T.name = ?
+        ON CONFLICT(snippet_id, tag_id) DO UPDATE
+        SET deleted = excluded.deleted, last_updated = excluded.last_updated
+        WHERE excluded.last_updated > snippet_tags.last_updated")
+        .context("failed to prepare snippet_tag sync statement")?
+        .cursor();
+
+    for row in snippet_tags {
+        insert_tag.bind(&[Value::String(row.tag_name.clone()),
+                          Value::Integer(row.last_updated)])
+            .context("failed to bind values")?;
+
+        insert_tag.next()
+            .context("failed to execute sql statement")?;
+
+        snippet_tag_statement.bind(&[Value::Integer(row.deleted),
+                                     Value::Integer(row.last_updated),
+                                     Value::String(row.snippet_uuid),
+                                     Value::String(row.tag_name)])
+            .context("failed to bind values")?;
+
+        snippet_tag_statement.next()
+            .context("failed to execute sql statement")?;
+    }
+
+    // snippets purged on another machine
+    for row in tombstones {
+        let tables = vec![("snippet_tags", "snippet_id"), ("revisions", "snippet_id"), ("conflicts", "snippet_id"), ("variables", "snippet_id"), ("snippets", "id")];
+
+        for (table, col) in tables {
+            let query = format!(
+                "DELETE FROM `{}` WHERE {} IN (
+                    SELECT id FROM `snippets` WHERE uuid = ?
+                )", table, col);
+
+            let mut statement = conn.prepare(query)
+                .context("failed to prepare purge statement")?;
+
+            statement.bind(1, row.snippet_uuid.as_str())
+                .context("failed to bind snippet uuid")?;
+
+            statement.next()
+                .context("failed to execute sql statement")?;
+        }
+    }
+
+    Ok(conflicts)
+}
+
+// Apply a remote snippet row and return whether it conflicted with a local
+// change. A side changed the snippet if it differs from the sync base, the
+// state both sides agreed on after the last sync. The server only replaces
+// its row with an upload of a higher version, so the remote row wins if its
+// version is at least the local one.
+#[cfg(feature = "sync")]
+fn apply_snippet_row(conn: &Connection, row: sync::SnippetRow, last_synced: i64, strategy: ConflictStrategy, collisions: &mut Vec<sync::SnippetRow>) -> Result<bool, Error> {
+    let mut statement = conn.prepare(
+        "SELECT id, name, content, deleted, last_updated, base_name, base_content, description, source, version,
+            language
+        FROM `snippets`
+        WHERE uuid = ?")
+        .context("failed to prepare load statement")?;
+
+    statement.bind(1, row.uuid.as_str())
+        .context("failed to bind snippet uuid")?;
+
+    let state = statement.next()
+        .context("failed to execute sql statement")?;
+
+    if state == State::Done {
+        if get_snippet_meta(conn, row.name.as_str()).context("failed to look up snippet name")?.is_some() {
+            collisions.push(row);
+        } else {
+            insert_snippet_row(conn, &row)
+                .context("failed to save snippet")?;
+        }
+
+        return Ok(false);
+    }
+
+    let snippet_id = statement.read::<i64>(0)
+        .context("failed to read snippet id")?;
+    let name = statement.read::<String>(1)
+        .context("failed to read snippet name")?;
+    let content = statement.read::<String>(2)
+        .context("failed to read snippet content")?;
+    let deleted = statement.read::<i64>(3)
+        .context("failed to read deleted col")?;
+    let last_updated = statement.read::<i64>(4)
+        .context("failed to read update time col")?;
+    // the base is unknown for snippets from before conflict detection
+    let base = match statement.read::<Value>(5).context("failed to read base name col")? {
+        Value::Null => None,
+        base_name => Some((base_name.as_string().unwrap_or("").to_string(),
+                           statement.read::<String>(6).context("failed to read base content col")?)),
+    };
+    let description = read_optional_string(&statement, 7)
+        .context("failed to read description col")?;
+    let source = read_optional_string(&statement, 8)
+        .context("failed to read source col")?;
+    let version = statement.read::<i64>(9)
+        .context("failed to read version col")?;
+    let language = read_optional_string(&statement, 10)
+        .context("failed to read language col")?;
+
+    let local_changed = match base {
+        Some(ref base) => base.0 != name || base.1 != content,
+        None => last_updated > last_synced,
+    };
+    let remote_changed = match base {
+        Some(ref base) => base.0 != row.name || base.1 != row.content,
+        None => name != row.name || content != row.content,
+    };
+
+    if !remote_changed {
+        // only the deletion state, language, description or source may have
+        // changed remotely, the higher version of those wins
+        let changed = row.deleted != deleted || row.language != language || row.description != description || row.source != source;
+
+        if changed && row.version >= version {
+            update_snippet_row(conn, snippet_id, &row)
+                .context("failed to update snippet")?;
+        } else {
+            raise_snippet_version(conn, snippet_id, row.version)
+                .context("failed to update snippet version")?;
+        }
+
+        return Ok(false);
+    }
+
+    if !local_changed || (name == row.name && content == row.content) {
+        update_snippet_row(conn, snippet_id, &row)
+            .context("failed to update snippet")?;
+        save_remote_revisions(conn, snippet_id, &row, name.as_str(), content.as_str())
+            .context("failed to save snippet revisions")?;
+
+        return Ok(false);
+    }
+
+    match strategy {
+        ConflictStrategy::LastWriterWins => {
+            // on equal versions the server wins so every machine agrees
+            if row.version >= version {
+                update_snippet_row(conn, snippet_id, &row)
+                    .context("failed to update snippet")?;
+                save_remote_revisions(conn, snippet_id, &row, name.as_str(), content.as_str())
+                    .context("failed to save snippet revisions")?;
+            }
+        },
+        ConflictStrategy::KeepBoth => {
+            let tags = get_snippet_tags(conn, snippet_id)
+                .context("failed to load snippet tags")?;
+
+            update_snippet_row(conn, snippet_id, &row)
+                .context("failed to update snippet")?;
+            save_remote_revisions(conn, snippet_id, &row, name.as_str(), content.as_str())
+                .context("failed to save snippet revisions")?;
+
+            // the server may have kept the local version uploaded in the same
+            // sync, the remote one is sent again to replace it
+            let mut statement = conn.prepare(
+                "UPDATE `snippets` SET last_updated = ?, version = ? WHERE id = ?")
+                .context("failed to prepare update statement")?;
+
+            statement.bind(1, util::get_utc_now())
+                .context("failed to bind time")?;
+            statement.bind(2, cmp::max(version, row.version) + 1)
+                .context("failed to bind version")?;
+            statement.bind(3, snippet_id)
+                .context("failed to bind snippet id")?;
+
+            statement.next()
+                .context("failed to execute sql statement")?;
+
+            let copy_name = format!("{} (conflict {})", name, util::format_time(util::get_utc_now()));
+            save_snippet(conn, copy_name, content, Some(tags.iter().map(|x| x.as_str()).collect()))
+                .context("failed to save local copy of snippet")?;
+        },
+        ConflictStrategy::Manual => {
+            let mut statement = conn.prepare(
+                "INSERT INTO `conflicts` (snippet_id, remote_name, remote_content, created)
+                VALUES (?, ?, ?, ?)")
+                .context("failed to prepare conflict statement")?;
+
+            statement.bind(1, snippet_id)
+                .context("failed to bind snippet id")?;
+            statement.bind(2, row.name.as_str())
+                .context("failed to bind name")?;
+            statement.bind(3, row.content.as_str())
+                .context("failed to bind content")?;
+            statement.bind(4, util::get_utc_now())
+                .context("failed to bind time")?;
+
+            statement.next()
+                .context("failed to execute sql statement")?;
+
+            // the resolution has to replace the remote version on the server
+            raise_snippet_version(conn, snippet_id, row.version)
+                .context("failed to update snippet version")?;
+        },
+    }
+
+    Ok(true)
+}
+
+#[cfg(feature = "sync")]
+fn insert_snippet_row(conn: &Connection, row: &sync::SnippetRow) -> Result<(), Error> {
+    // a different snippet may have been created with the same name locally
+    let mut name = row.name.clone();
+    let mut last_updated = row.last_updated;
+    let mut version = row.version;
+    let mut i = 2;
+
+    while get_snippet_meta(conn, name.as_str()).context("failed to look up snippet name")?.is_some() {
+        name = format!("{} ({})", row.name, i);
+        i += 1;
+    }
+
+    // the new name has to be synced back
+    if name != row.name {
+        last_updated = util::get_utc_now();
+        version += 1;
+    }
+
+    let mut statement = conn.prepare(
+        "INSERT INTO `snippets` (uuid, name, content, deleted, last_updated, base_name, base_content,
+            description, source, created_at, version, language)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .context("failed to prepare save statement")?;
+
+    statement.bind(1, row.uuid.as_str())
+        .context("failed to bind snippet uuid")?;
+    statement.bind(2, name.as_str())
+        .context("failed to bind name")?;
+    statement.bind(3, row.content.as_str())
+        .context("failed to bind content")?;
+    statement.bind(4, row.deleted)
+        .context("failed to bind deleted")?;
+    statement.bind(5, last_updated)
+        .context("failed to bind time")?;
+    statement.bind(6, row.name.as_str())
+        .context("failed to bind base name")?;
+    statement.bind(7, row.content.as_str())
+        .context("failed to bind base content")?;
+    statement.bind(8, &optional_value(row.description.as_deref()))
+        .context("failed to bind description")?;
+    statement.bind(9, &optional_value(row.source.as_deref()))
+        .context("failed to bind source")?;
+    statement.bind(10, row.created_at)
+        .context("failed to bind creation time")?;
+    statement.bind(11, version)
+        .context("failed to bind version")?;
+    statement.bind(12, &optional_value(row.language.as_deref()))
+        .context("failed to bind language")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(())
+}
+
+// machines may have guessed different creation times for snippets from
+// before they were recorded, the earliest one wins
+#[cfg(feature = "sync")]
+fn update_snippet_row(conn: &Connection, snippet_id: i64, row: &sync::SnippetRow) -> Result<(), Error> {
+    let mut statement = conn.prepare(
+        "UPDATE `snippets` SET name = ?, content = ?, deleted = ?, last_updated = ?,
+            base_name = ?, base_content = ?, description = ?, source = ?,
+            created_at = MIN(created_at, ?), version = ?, language = ?
+        WHERE id = ?")
+        .context("failed to prepare update statement")?;
+
+    statement.bind(1, row.name.as_str())
+        .context("failed to bind name")?;
+    statement.bind(2, row.content.as_str())
+        .context("failed to bind content")?;
+    statement.bind(3, row.deleted)
+        .context("failed to bind deleted")?;
+    statement.bind(4, row.last_updated)
+        .context("failed to bind time")?;
+    statement.bind(5, row.name.as_str())
+        .context("failed to bind base name")?;
+    statement.bind(6, row.content.as_str())
+        .context("failed to bind base content")?;
+    statement.bind(7, &optional_value(row.description.as_deref()))
+        .context("failed to bind description")?;
+    statement.bind(8, &optional_value(row.source.as_deref()))
+        .context("failed to bind source")?;
+    statement.bind(9, row.created_at)
+        .context("failed to bind creation time")?;
+    statement.bind(10, row.version)
+        .context("failed to bind version")?;
+    statement.bind(11, &optional_value(row.language.as_deref()))
+        .context("failed to bind language")?;
+    statement.bind(12, snippet_id)
+        .context("failed to bind snippet id")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(())
+}
+
+// local changes have to be numbered above versions other machines uploaded
+#[cfg(feature = "sync")]
+fn raise_snippet_version(conn: &Connection, snippet_id: i64, version: i64) -> Result<(), Error> {
+    let mut statement = conn.prepare(
+        "UPDATE `snippets` SET version = MAX(version, ?) WHERE id = ?")
+        .context("failed to prepare update statement")?;
+
+    statement.bind(1, version)
+        .context("failed to bind version")?;
+    statement.bind(2, snippet_id)
+        .context("failed to bind snippet id")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    Ok(())
+}
+
+// remote changes end up in the history like local ones
+#[cfg(feature = "sync")]
+fn save_remote_revisions(conn: &Connection, snippet_id: i64, row: &sync::SnippetRow, name: &str, content: &str) -> Result<(), Error> {
+    if name != row.name {
+        save_revision(conn, snippet_id, RevisionField::Name, name, row.name.as_str())
+            .context("failed to save name revision")?;
+    }
+
+    if content != row.content {
+        save_revision(conn, snippet_id, RevisionField::Content, content, row.content.as_str())
+            .context("failed to save content revision")?;
+    }
+
+    Ok(())
+}
+
+pub fn get_conflicts(conn: &Connection) -> Result<Vec<Conflict>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT C.snippet_id, S.name, S.content, C.remote_name, C.remote_content, C.created
+        FROM `conflicts` AS C
+        INNER JOIN `snippets` AS S ON S.id = C.snippet_id
+        ORDER BY C.created")
+        .context("failed to prepare load statement")?;
+
+    let mut conflicts = Vec::new();
+
+    while let State::Row = statement.next().context("failed to execute sql statement")? {
+        let snippet_id = statement.read::<i64>(0)
+            .context("failed to read snippet id")?;
+        let name = statement.read::<String>(1)
+            .context("failed to read snippet name")?;
+        let content = statement.read::<String>(2)
+            .context("failed to read snippet content")?;
+        let remote_name = statement.read::<String>(3)
+            .context("failed to read remote name")?;
+        let remote_content = statement.read::<String>(4)
+            .context("failed to read remote content")?;
+        let created = statement.read::<i64>(5)
+            .context("failed to read created col")?;
+
+        conflicts.push(Conflict {
+            snippet_id: snippet_id,
+            name: name,
+            content: content,
+            remote_name: remote_name,
+            remote_content: remote_content,
+            created: created,
+        });
+    }
+
+    Ok(conflicts)
+}
+
+// Replace the local version of a conflicting snippet with the merged one.
+// The remote version becomes the sync base so the merge is uploaded as a
+// regular local change.
+pub fn resolve_conflict(conn: &Connection, conflict: &Conflict, name: String, content: String) -> Result<(), Error> {
+    transaction(conn, |conn| {
+        if name != conflict.name {
+            rename_snippet(conn, conflict.snippet_id, name)
+                .context("failed to rename snippet")?;
+        }
+
+        if content != conflict.content {
+            change_snippet_content(conn, conflict.snippet_id, content)
+                .context("failed to change snippet content")?;
+        }
+
+        let mut statement = conn.prepare(
+            "UPDATE `snippets` SET base_name = ?, base_content = ?, last_updated = ?, version = version + 1
+            WHERE id = ?")
+            .context("failed to prepare update statement")?;
+
+        statement.bind(1, conflict.remote_name.as_str())
+            .context("failed to bind base name")?;
+        statement.bind(2, conflict.remote_content.as_str())
+            .context("failed to bind base content")?;
+        statement.bind(3, util::get_utc_now())
+            .context("failed to bind time")?;
+        statement.bind(4, conflict.snippet_id)
+            .context("failed to bind snippet id")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        let mut statement = conn.prepare(
+            "DELETE FROM `conflicts` WHERE snippet_id = ?")
+            .context("failed to prepare conflict removal statement")?;
+
+        statement.bind(1, conflict.snippet_id)
+            .context("failed to bind snippet id")?;
+
+        statement.next()
+            .context("failed to execute sql statement")?;
+
+        Ok(())
+    })
 }
 
 pub fn get_metadata_value(conn: &Connection, key: &str) -> Result<String, Error> {
+    match find_metadata_value(conn, key)? {
+        Some(value) => Ok(value),
+        None => Err(ErrorKind::UnknownMetaKey.into()),
+    }
+}
+
+pub fn get_variables(conn: &Connection, snippet_id: i64) -> Result<HashMap<String, String>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT name, value FROM `variables`
+        WHERE snippet_id = ?")
+        .context("failed to prepare variables statement")?;
+
+    statement.bind(1, snippet_id)
+        .context("failed to bind snippet id")?;
+
+    let mut variables = HashMap::new();
+
+    while let State::Row = statement.next().context("failed to execute sql statement")? {
+        let name = statement.read::<String>(0)
+            .context("failed to read variable name")?;
+        let value = statement.read::<String>(1)
+            .context("failed to read variable value")?;
+
+        variables.insert(name, value);
+    }
+
+    Ok(variables)
+}
+
+pub fn save_variables(conn: &Connection, snippet_id: i64, variables: &HashMap<String, String>) -> Result<(), Error> {
+    transaction(conn, |conn| {
+        for (name, value) in variables {
+            let mut statement = conn.prepare(
+                "INSERT INTO `variables` (snippet_id, name, value)
+                VALUES (?, ?, ?)")
+                .context("failed to prepare variables statement")?;
+
+            statement.bind(1, snippet_id)
+                .context("failed to bind snippet id")?;
+            statement.bind(2, name.as_str())
+                .context("failed to bind variable name")?;
+            statement.bind(3, value.as_str())
+                .context("failed to bind variable value")?;
+
+            statement.next()
+                .context("failed to execute sql statement")?;
+        }
+
+        Ok(())
+    })
+}
+
+pub fn find_metadata_value(conn: &Connection, key: &str) -> Result<Option<String>, Error> {
     let mut statement = conn.prepare("SELECT value FROM metadata WHERE key = ?")
         .context("failed to prepare meta data statement")?;
 
@@ -489,13 +1826,13 @@ pub fn get_metadata_value(conn: &Connection, key: &str) -> Result<String, Error>
         .context("failed to execute sql statement")?;
 
     if state == State::Done {
-        bail!(error::UnknownMetaKey);
+        return Ok(None);
     }
 
     let value = statement.read::<String>(0)
         .context("failed to read value col")?;
 
-    Ok(value)
+    Ok(Some(value))
 }
 
 pub fn set_metadata_value(conn: &Connection, key: &str, value: &str) -> Result<(), Error> {
@@ -522,56 +1859,112 @@ pub fn set_metadata_value(conn: &Connection, key: &str, value: &str) -> Result<(
     Ok(())
 }
 
-fn get_db_path() -> Result<PathBuf, Error> {
-    let mut path = PathBuf::from(env::var("HOME")
-        .context("failed to get HOME directory")?);
-
-    path.push(DB_PATH);
-
-    Ok(path)
-}
-
-fn init(db_file: &PathBuf) -> Result<Connection, Error> {
+fn init(db_file: &Path) -> Result<Connection, Error> {
     let path = db_file.parent().unwrap();
 
-    fs::create_dir(path)
+    fs::create_dir_all(path)
         .context(format!("failed to create database directory: {:?}", path))?;
 
+    // only the owner may read the database since it holds the sync credentials
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(db_file)
+        .context(format!("failed to create database file: {:?}", db_file))?;
+
+    // the tables are created by the migrations
     let conn = sqlite::open(db_file.to_str().unwrap())
         .map_err::<Error, _>(|e| e.into())?;
 
-    // initialize database tables
-    conn.execute(
-        "CREATE TABLE snippets(
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name VARCHAR(64) UNIQUE,
-            content TEXT,
-            deleted INTEGER DEFAULT 0,
-            last_updated INTEGER NOT NULL
-        );
-        CREATE TABLE tags(
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name VARCHAR(64),
-            deleted INTEGER DEFAULT 0,
-            last_updated INTEGER NOT NULL,
-            UNIQUE(name) ON CONFLICT IGNORE
-        );
-        CREATE TABLE snippet_tags(
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            snippet_id INTEGER REFERENCES snippets(id),
-            tag_id INTEGER REFERENCES tags(id),
-            deleted INTEGER DEFAULT 0,
-            last_updated INTEGER NOT NULL,
-            UNIQUE(snippet_id, tag_id)
-        );
-        CREATE TABLE metadata(
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            key VARCHAR(32),
-            value TEXT,
-            UNIQUE(key) ON CONFLICT REPLACE
-        );
-        INSERT INTO `metadata` (key, value) VALUES ('last_synced', 0);"
-        ).context("failed to create database tables")?;
-
     Ok(conn)
 }
+
+// databases created before the sync credentials were stored may be world readable
+fn restrict_permissions(db_file: &Path) -> Result<(), Error> {
+    let mut permissions = fs::metadata(db_file)
+        .context(format!("failed to read database file metadata: {:?}", db_file))?
+        .permissions();
+
+    if permissions.mode() & 0o077 != 0 {
+        permissions.set_mode(0o600);
+
+        match fs::set_permissions(db_file, permissions) {
+            // a database of another user, shared through its group
+            Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => (),
+            x => x.context(format!("failed to change database file permissions: {:?}", db_file))?,
+        }
+    }
+
+    Ok(())
+}
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool, Error> {
+    let mut statement = conn.prepare(
+        "SELECT COUNT(*) FROM `sqlite_master`
+        WHERE type = 'table' AND name = ?")
+        .context("failed to prepare table lookup statement")?;
+
+    statement.bind(1, table)
+        .context("failed to bind table name")?;
+
+    statement.next()
+        .context("failed to execute sql statement")?;
+
+    let count = statement.read::<i64>(0)
+        .context("failed to read count col")?;
+
+    Ok(count > 0)
+}
+
+fn get_schema_version(conn: &Connection) -> Result<i64, Error> {
+    // a fresh database without any tables
+    if !table_exists(conn, "metadata").context("failed to check for metadata table")? {
+        return Ok(0);
+    }
+
+    let mut statement = conn.prepare(
+        "SELECT value FROM `metadata` WHERE key = 'schema_version'")
+        .context("failed to prepare schema version statement")?;
+
+    let state = statement.next()
+        .context("failed to execute sql statement")?;
+
+    // databases created before versioning have the initial schema
+    if state == State::Done {
+        return Ok(1);
+    }
+
+    let value = statement.read::<String>(0)
+        .context("failed to read value col")?;
+    let version = i64::from_str(value.as_str())
+        .context("failed to parse schema version")?;
+
+    Ok(version)
+}
+
+fn migrate(conn: &Connection) -> Result<(), Error> {
+    let version = get_schema_version(conn)
+        .context("failed to get schema version")?;
+    let latest = MIGRATIONS.len() as i64;
+
+    if version > latest {
+        return Err(ErrorKind::UnsupportedSchemaVersion(version, latest).into());
+    }
+
+    if version == latest {
+        return Ok(());
+    }
+
+    transaction(conn, |conn| {
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            conn.execute(migration)
+                .context(format!("failed to apply migration {}", i + 1))?;
+        }
+
+        set_metadata_value(conn, "schema_version", latest.to_string().as_str())
+            .context("failed to update schema version")?;
+
+        Ok(())
+    })
+}
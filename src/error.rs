@@ -1,18 +1,169 @@
 use failure::*;
+use sqlite;
 
-#[derive(Fail, Debug)]
-#[fail(display = "duplicate snippet name")]
-pub struct DupSnippetName;
+// Errors callers can tell apart. Each kind has a stable name and exit code
+// for scripts wrapping rsm, every other error exits with status 1.
+//
+//   2   invalid_argument            command line could not be parsed
+//   10  unknown_snippet_id          no such snippet
+//   11  duplicate_name              a snippet with this name exists
+//   12  deleted_name                a deleted snippet with this name exists
+//   13  unknown_revision            no such revision of the snippet
+//   14  unknown_conflict            the snippet has no sync conflict
+//   15  unresolved_conflict         merged content contains conflict markers
+//   16  ambiguous_name              several snippets match the given name
+//   17  missing_variable            no value for a placeholder of a template
+//   20  database_corrupt            the database file is damaged
+//   21  unsupported_schema_version  the database was created by a newer rsm
+//   22  unknown_metadata_key        missing database metadata
+//   23  unknown_revision_field      invalid revision in the database
+//   30  editor_aborted              the editor failed or was killed
+//   31  pick_aborted                the picker was closed without a choice
+//   35  invalid_import              the data to import could not be read
+//   40  syncing_not_enabled         rsm was built without sync support
+//   41  not_logged_in               no sync credentials, run `rsm login`
+//   42  sync_auth_failed            the server rejected the credentials
+//   43  token_expired               the server rejected the sync token
+//   44  pin_mismatch                the server certificate is not pinned
+//   45  sync_server_error           the server reported an error
+//   46  sync_connection_failed      the server could not be reached
+#[derive(Fail, Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    #[fail(display = "{}", _0)]
+    InvalidArgument(String),
+    #[fail(display = "unknown snippet id or name")]
+    UnknownSnippetId,
+    #[fail(display = "duplicate snippet name")]
+    DupSnippetName,
+    #[fail(display = "a deleted snippet with this name exists, restore or purge it first")]
+    DeletedSnippetName,
+    #[fail(display = "unknown snippet revision")]
+    UnknownRevision,
+    #[fail(display = "no conflict for this snippet")]
+    UnknownConflict,
+    #[fail(display = "merged content still contains conflict markers")]
+    UnresolvedConflict,
+    #[fail(display = "'{}' matches several snippets: {}", _0, _1)]
+    AmbiguousSnippetName(String, String),
+    #[fail(display = "no value for placeholder '{}'", _0)]
+    MissingVariable(String),
+    #[fail(display = "database file is corrupt or not a rsm database")]
+    DatabaseCorrupt,
+    #[fail(display = "database schema version {} is newer than the supported version {}", _0, _1)]
+    UnsupportedSchemaVersion(i64, i64),
+    #[fail(display = "unknown metadata key")]
+    UnknownMetaKey,
+    #[fail(display = "unknown revision field")]
+    UnknownRevisionField,
+    #[fail(display = "editor exited unexpectedly")]
+    EditorAborted,
+    #[fail(display = "no snippet picked")]
+    PickAborted,
+    #[fail(display = "invalid import data: {}", _0)]
+    InvalidImport(String),
+    #[fail(display = "syncing is not enabled")]
+    SyncingNotEnabled,
+    #[fail(display = "not logged in to the sync server, run 'rsm login'")]
+    NotLoggedIn,
+    #[fail(display = "sync server rejected the credentials, run 'rsm login'")]
+    SyncAuthFailed,
+    #[fail(display = "sync token expired")]
+    TokenExpired,
+    #[fail(display = "server certificate does not match the pinned fingerprint, got {}", _0)]
+    PinMismatch(String),
+    #[fail(display = "sync server error: {}", _0)]
+    SyncServerError(String),
+    #[fail(display = "connection to the sync server failed")]
+    SyncConnectionFailed,
+}
 
-#[derive(Fail, Debug)]
-#[fail(display = "unknown snippet id")]
-pub struct UnknownSnippetId;
+impl ErrorKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ErrorKind::InvalidArgument(_) => "invalid_argument",
+            ErrorKind::UnknownSnippetId => "unknown_snippet_id",
+            ErrorKind::DupSnippetName => "duplicate_name",
+            ErrorKind::DeletedSnippetName => "deleted_name",
+            ErrorKind::UnknownRevision => "unknown_revision",
+            ErrorKind::UnknownConflict => "unknown_conflict",
+            ErrorKind::UnresolvedConflict => "unresolved_conflict",
+            ErrorKind::AmbiguousSnippetName(..) => "ambiguous_name",
+            ErrorKind::MissingVariable(_) => "missing_variable",
+            ErrorKind::DatabaseCorrupt => "database_corrupt",
+            ErrorKind::UnsupportedSchemaVersion(..) => "unsupported_schema_version",
+            ErrorKind::UnknownMetaKey => "unknown_metadata_key",
+            ErrorKind::UnknownRevisionField => "unknown_revision_field",
+            ErrorKind::EditorAborted => "editor_aborted",
+            ErrorKind::PickAborted => "pick_aborted",
+            ErrorKind::InvalidImport(_) => "invalid_import",
+            ErrorKind::SyncingNotEnabled => "syncing_not_enabled",
+            ErrorKind::NotLoggedIn => "not_logged_in",
+            ErrorKind::SyncAuthFailed => "sync_auth_failed",
+            ErrorKind::TokenExpired => "token_expired",
+            ErrorKind::PinMismatch(_) => "pin_mismatch",
+            ErrorKind::SyncServerError(_) => "sync_server_error",
+            ErrorKind::SyncConnectionFailed => "sync_connection_failed",
+        }
+    }
 
-#[derive(Fail, Debug)]
-#[fail(display = "unknown metadata key")]
-pub struct UnknownMetaKey;
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ErrorKind::InvalidArgument(_) => 2,
+            ErrorKind::UnknownSnippetId => 10,
+            ErrorKind::DupSnippetName => 11,
+            ErrorKind::DeletedSnippetName => 12,
+            ErrorKind::UnknownRevision => 13,
+            ErrorKind::UnknownConflict => 14,
+            ErrorKind::UnresolvedConflict => 15,
+            ErrorKind::AmbiguousSnippetName(..) => 16,
+            ErrorKind::MissingVariable(_) => 17,
+            ErrorKind::DatabaseCorrupt => 20,
+            ErrorKind::UnsupportedSchemaVersion(..) => 21,
+            ErrorKind::UnknownMetaKey => 22,
+            ErrorKind::UnknownRevisionField => 23,
+            ErrorKind::EditorAborted => 30,
+            ErrorKind::PickAborted => 31,
+            ErrorKind::InvalidImport(_) => 35,
+            ErrorKind::SyncingNotEnabled => 40,
+            ErrorKind::NotLoggedIn => 41,
+            ErrorKind::SyncAuthFailed => 42,
+            ErrorKind::TokenExpired => 43,
+            ErrorKind::PinMismatch(_) => 44,
+            ErrorKind::SyncServerError(_) => 45,
+            ErrorKind::SyncConnectionFailed => 46,
+        }
+    }
+}
 
-#[derive(Fail, Debug)]
-#[fail(display = "syncing is not enabled")]
-#[cfg(not(feature = "sync"))]
-pub struct SyncingNotEnabled;
+// exit code of errors without a kind
+pub const DEFAULT_EXIT_CODE: i32 = 1;
+
+// SQLITE_CORRUPT and SQLITE_NOTADB
+const SQLITE_CORRUPT_CODES: &[isize] = &[11, 26];
+
+// The first kind in the chain of causes, either returned directly or used as
+// the context of another error. SQLite reporting a damaged file counts as
+// `DatabaseCorrupt` wherever it happened.
+pub fn kind(e: &Error) -> Option<ErrorKind> {
+    for cause in e.iter_chain() {
+        if let Some(kind) = cause.downcast_ref::<ErrorKind>() {
+            return Some(kind.clone());
+        }
+
+        if let Some(context) = cause.downcast_ref::<Context<ErrorKind>>() {
+            return Some(context.get_context().clone());
+        }
+
+        if let Some(e) = cause.downcast_ref::<sqlite::Error>() {
+            if e.code.is_some_and(|x| SQLITE_CORRUPT_CODES.contains(&x)) {
+                return Some(ErrorKind::DatabaseCorrupt);
+            }
+        }
+    }
+
+    None
+}
+
+pub fn exit_code(e: &Error) -> i32 {
+    kind(e).map_or(DEFAULT_EXIT_CODE, |x| x.exit_code())
+}
@@ -0,0 +1,164 @@
+// The directory layout keeps every snippet in a file of its own, the content
+// preceded by TOML front matter:
+//
+//   +++
+//   name = "git log"
+//   tags = ["git"]
+//   language = "sh"
+//   description = "the history in one line per commit"
+//   created_at = 1538300000
+//   updated_at = 1538400000
+//   +++
+//   git log --oneline
+//
+// File names are derived from the snippet names but only the front matter
+// counts on import. Files without front matter are imported under their
+// file name, hidden files are skipped.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::fs;
+use failure::*;
+use toml;
+use exchange::{self, Entry};
+use error::ErrorKind;
+
+static FRONT_MATTER_FENCE: &str = "+++\n";
+static EXTENSION: &str = ".txt";
+
+#[derive(Serialize, Deserialize)]
+struct FrontMatter {
+    name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated_at: Option<i64>,
+}
+
+pub fn write(dir: &Path, entries: &[Entry]) -> Result<(), Error> {
+    fs::create_dir_all(dir)
+        .context(format!("failed to create directory {:?}", dir))?;
+
+    // names that only differ in characters not allowed in file names, or
+    // in case on some file systems, must not overwrite each other
+    let mut used = HashSet::new();
+
+    for entry in entries {
+        let file_name = exchange::unique_file_name(&mut used, file_stem(entry.name.as_str()).as_str(), EXTENSION);
+
+        let front_matter = FrontMatter {
+            name: Some(entry.name.clone()),
+            tags: entry.tags.clone(),
+            language: entry.language.clone(),
+            description: entry.description.clone(),
+            source: entry.source.clone(),
+            created_at: entry.created_at,
+            updated_at: entry.updated_at,
+        };
+
+        let front_matter = toml::to_string(&front_matter)
+            .context("failed to serialize front matter")?;
+
+        let data = format!("{}{}{}{}", FRONT_MATTER_FENCE, front_matter, FRONT_MATTER_FENCE, entry.content);
+
+        fs::write(dir.join(file_name.as_str()), data)
+            .context(format!("failed to write snippet file {}", file_name))?;
+    }
+
+    Ok(())
+}
+
+pub fn read(dir: &Path) -> Result<Vec<Entry>, Error> {
+    let mut paths = Vec::new();
+
+    for dir_entry in fs::read_dir(dir).context(format!("failed to read directory {:?}", dir))? {
+        let path = dir_entry.context("failed to read directory entry")?.path();
+        let hidden = path.file_name().and_then(|x| x.to_str()).is_none_or(|x| x.starts_with('.'));
+
+        if path.is_file() && !hidden {
+            paths.push(path);
+        }
+    }
+
+    // the order of read_dir is arbitrary
+    paths.sort();
+
+    let mut entries = Vec::new();
+
+    for path in paths {
+        let data = fs::read_to_string(&path)
+            .context(format!("failed to read snippet file {:?}", path))?;
+
+        let file_name = path.file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or("")
+            .to_string();
+
+        entries.push(parse_file(data.as_str(), file_name)
+            .context(format!("failed to parse snippet file {:?}", path))?);
+    }
+
+    Ok(entries)
+}
+
+fn parse_file(data: &str, file_name: String) -> Result<Entry, Error> {
+    if !data.starts_with(FRONT_MATTER_FENCE) {
+        return Ok(Entry {
+            name: file_name,
+            tags: Vec::new(),
+            content: data.to_string(),
+            language: None,
+            description: None,
+            source: None,
+            created_at: None,
+            updated_at: None,
+        });
+    }
+
+    let rest = &data[FRONT_MATTER_FENCE.len()..];
+
+    // the closing fence is on a line of its own
+    let end = if rest.starts_with(FRONT_MATTER_FENCE) {
+        0
+    } else {
+        rest.find(format!("\n{}", FRONT_MATTER_FENCE).as_str())
+            .map(|x| x + 1)
+            .ok_or(ErrorKind::InvalidImport("unclosed front matter".to_string()))?
+    };
+
+    let front_matter: FrontMatter = toml::from_str(&rest[..end])
+        .map_err(|e| ErrorKind::InvalidImport(e.to_string()))?;
+
+    Ok(Entry {
+        name: front_matter.name.unwrap_or(file_name),
+        tags: front_matter.tags,
+        content: rest[end + FRONT_MATTER_FENCE.len()..].to_string(),
+        language: front_matter.language,
+        description: front_matter.description,
+        source: front_matter.source,
+        created_at: front_matter.created_at,
+        updated_at: front_matter.updated_at,
+    })
+}
+
+// the name with characters that are trouble in file names replaced
+fn file_stem(name: &str) -> String {
+    let stem = name.trim()
+        .chars()
+        .map(|x| if x == '/' || x == '\\' || x == ':' || x.is_control() { '_' } else { x })
+        .collect::<String>();
+
+    match stem.as_str() {
+        "" => "snippet".to_string(),
+        _ if stem.starts_with('.') => format!("_{}", &stem[1..]),
+        _ => stem,
+    }
+}
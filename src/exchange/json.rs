@@ -0,0 +1,59 @@
+// The JSON format holds the whole collection in one document:
+//
+//   {
+//     "version": 1,
+//     "snippets": [
+//       {
+//         "name": "git log",
+//         "tags": ["git"],
+//         "content": "git log --oneline\n",
+//         "language": "bash",
+//         "description": "short history",
+//         "source": "man git-log",
+//         "created_at": 1538300000,
+//         "updated_at": 1538400000
+//       }
+//     ]
+//   }
+//
+// Only `name` and `content` are required, every other field may be left out.
+// Timestamps are seconds since the epoch, `created_at` is restored on import
+// while `updated_at` is informational. Readers reject documents with a higher
+// version than they know.
+
+use failure::*;
+use serde_json;
+use exchange::Entry;
+use error::ErrorKind;
+
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u32,
+    snippets: Vec<Entry>,
+}
+
+pub fn to_string(entries: &[Entry]) -> Result<String, Error> {
+    let document = Document {
+        version: VERSION,
+        snippets: entries.to_vec(),
+    };
+
+    let mut data = serde_json::to_string_pretty(&document)
+        .context("failed to serialize snippets")?;
+    data.push('\n');
+
+    Ok(data)
+}
+
+pub fn from_str(data: &str) -> Result<Vec<Entry>, Error> {
+    let document: Document = serde_json::from_str(data)
+        .map_err(|e| ErrorKind::InvalidImport(e.to_string()))?;
+
+    if document.version > VERSION {
+        return Err(ErrorKind::InvalidImport(format!("unsupported version {}", document.version)).into());
+    }
+
+    Ok(document.snippets)
+}
@@ -0,0 +1,142 @@
+// The Markdown bundle has a section per snippet, meant to be read by people
+// as much as by `rsm import`:
+//
+//   ## git log
+//
+//   Tags: git, vcs
+//
+//   Description: the history in one line per commit
+//
+//   Source: https://git-scm.com/docs/git-log
+//
+//   ```sh
+//   git log --oneline
+//   ```
+//
+// The language of a snippet is the info string of the fence, which grows
+// longer than any run of backticks in the content. Text
+// outside of the headings, tag, description and source lines and first code
+// blocks is ignored, as are sections without a code block.
+
+use std::cmp;
+use failure::*;
+use exchange::Entry;
+use error::ErrorKind;
+
+static TAGS_PREFIX: &str = "Tags: ";
+static DESCRIPTION_PREFIX: &str = "Description: ";
+static SOURCE_PREFIX: &str = "Source: ";
+
+pub fn to_string(entries: &[Entry]) -> String {
+    let mut data = String::from("# Snippets\n");
+
+    for entry in entries {
+        data.push_str(format!("\n## {}\n\n", entry.name).as_str());
+
+        if !entry.tags.is_empty() {
+            data.push_str(format!("{}{}\n\n", TAGS_PREFIX, entry.tags.as_slice().join(", ")).as_str());
+        }
+
+        if let Some(ref description) = entry.description {
+            data.push_str(format!("{}{}\n\n", DESCRIPTION_PREFIX, description).as_str());
+        }
+
+        if let Some(ref source) = entry.source {
+            data.push_str(format!("{}{}\n\n", SOURCE_PREFIX, source).as_str());
+        }
+
+        let fence = "`".repeat(cmp::max(3, longest_backtick_run(entry.content.as_str()) + 1));
+
+        data.push_str(fence.as_str());
+        data.push_str(entry.language.as_ref().map_or("", |x| x.as_str()));
+        data.push('\n');
+        data.push_str(entry.content.as_str());
+
+        if !entry.content.is_empty() && !entry.content.ends_with('\n') {
+            data.push('\n');
+        }
+
+        data.push_str(fence.as_str());
+        data.push('\n');
+    }
+
+    data
+}
+
+pub fn from_str(data: &str) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    let mut lines = data.lines();
+
+    // the snippet whose section is being read
+    let mut current: Option<Entry> = None;
+
+    while let Some(line) = lines.next() {
+        // sections without a code block are not snippets
+        if let Some(name) = line.strip_prefix("## ") {
+            current = Some(Entry {
+                name: name.trim().to_string(),
+                tags: Vec::new(),
+                content: String::new(),
+                language: None,
+                description: None,
+                source: None,
+                created_at: None,
+                updated_at: None,
+            });
+
+            continue;
+        }
+
+        let entry = match current {
+            Some(ref mut entry) => entry,
+            None => continue,
+        };
+
+        if let Some(tags) = line.strip_prefix(TAGS_PREFIX) {
+            entry.tags = tags.split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect();
+        } else if let Some(description) = line.strip_prefix(DESCRIPTION_PREFIX) {
+            entry.description = Some(description.trim().to_string());
+        } else if let Some(source) = line.strip_prefix(SOURCE_PREFIX) {
+            entry.source = Some(source.trim().to_string());
+        } else if line.starts_with("```") {
+            let fence_len = line.chars().take_while(|&x| x == '`').count();
+            let info = line[fence_len..].trim();
+            let mut closed = false;
+
+            if !info.is_empty() {
+                entry.language = Some(info.to_string());
+            }
+
+            for line in lines.by_ref() {
+                let trimmed = line.trim();
+
+                if trimmed.len() >= fence_len && trimmed.chars().all(|x| x == '`') {
+                    closed = true;
+                    break;
+                }
+
+                entry.content.push_str(line);
+                entry.content.push('\n');
+            }
+
+            if !closed {
+                return Err(ErrorKind::InvalidImport(format!("unclosed code block in section '{}'", entry.name)).into());
+            }
+
+            // only the first code block of a section is the snippet
+            entries.push(current.take().unwrap());
+        }
+    }
+
+    Ok(entries)
+}
+
+fn longest_backtick_run(content: &str) -> usize {
+    content.split(|x| x != '`')
+        .map(|x| x.len())
+        .max()
+        .unwrap_or(0)
+}
@@ -0,0 +1,381 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::fs;
+use failure::*;
+use snippet::Snippet;
+use template;
+use error::ErrorKind;
+
+pub mod json;
+pub mod directory;
+pub mod markdown;
+pub mod pet;
+pub mod navi;
+pub mod vscode;
+pub mod tldr;
+pub mod ultisnips;
+pub mod yasnippet;
+pub mod sublime;
+
+// A snippet on its way in or out of the database. Ids are local to a
+// database and not part of it. The creation time is restored on import, the
+// update time is informational only so imported snippets are picked up by
+// the next sync.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<i64>,
+}
+
+impl<'a> From<&'a Snippet> for Entry {
+    fn from(snippet: &'a Snippet) -> Entry {
+        Entry {
+            name: snippet.name.clone(),
+            tags: snippet.tags.clone(),
+            content: snippet.content.clone(),
+            language: snippet.language.clone(),
+            description: snippet.description.clone(),
+            source: snippet.source.clone(),
+            created_at: Some(snippet.created_at),
+            updated_at: Some(snippet.updated_at),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    // a single JSON document, see `json`
+    Json,
+    // one file per snippet with TOML front matter, see `directory`
+    Directory,
+    // a Markdown document with a section per snippet, see `markdown`
+    Markdown,
+}
+
+impl FromStr for Format {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Format, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "dir" => Ok(Format::Directory),
+            "markdown" => Ok(Format::Markdown),
+            _ => Err(ErrorKind::InvalidArgument(format!("unknown format '{}', expected json, dir or markdown", s))),
+        }
+    }
+}
+
+impl Format {
+    // the format a path most likely is in
+    pub fn detect(path: &Path) -> Format {
+        if path.is_dir() {
+            return Format::Directory;
+        }
+
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("md") | Some("markdown") => Format::Markdown,
+            _ => Format::Json,
+        }
+    }
+
+    // whether the format is written to a single file or stream
+    pub fn is_single_file(&self) -> bool {
+        *self != Format::Directory
+    }
+
+    pub fn serialize(&self, entries: &[Entry]) -> Result<String, Error> {
+        match *self {
+            Format::Json => json::to_string(entries),
+            Format::Markdown => Ok(markdown::to_string(entries)),
+            Format::Directory => bail!("the directory format has no single file"),
+        }
+    }
+
+    pub fn parse(&self, data: &str) -> Result<Vec<Entry>, Error> {
+        match *self {
+            Format::Json => json::from_str(data),
+            Format::Markdown => markdown::from_str(data),
+            Format::Directory => bail!("the directory format has no single file"),
+        }
+    }
+}
+
+// snippet managers whose data can be imported
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Source {
+    // pet TOML files, see `pet`
+    Pet,
+    // navi cheatsheets, see `navi`
+    Navi,
+    // VS Code snippet files, see `vscode`
+    Vscode,
+    // tldr-style pages as used by cheat sheet tools, see `tldr`
+    Cheat,
+}
+
+impl FromStr for Source {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Source, Self::Err> {
+        match s {
+            "pet" => Ok(Source::Pet),
+            "navi" => Ok(Source::Navi),
+            "vscode" => Ok(Source::Vscode),
+            "cheat" => Ok(Source::Cheat),
+            _ => Err(ErrorKind::InvalidArgument(format!("unknown source '{}', expected pet, navi, vscode or cheat", s))),
+        }
+    }
+}
+
+impl Source {
+    // files read when importing a whole directory
+    fn extensions(&self) -> &'static [&'static str] {
+        match *self {
+            Source::Pet => &["toml"],
+            Source::Navi => &["cheat"],
+            Source::Vscode => &["code-snippets", "json"],
+            Source::Cheat => &["md"],
+        }
+    }
+
+    // The snippets in a file or every matching file below a directory, with
+    // notes on what could not be imported.
+    pub fn read(&self, path: &Path) -> Result<(Vec<Entry>, Vec<String>), Error> {
+        let paths = if path.is_dir() {
+            let mut paths = Vec::new();
+            find_files(path, self.extensions(), &mut paths)?;
+            paths.sort();
+            paths
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let mut entries = Vec::new();
+        let mut skipped = Vec::new();
+
+        for path in paths {
+            let data = fs::read_to_string(&path)
+                .context(format!("failed to read {:?}", path))?;
+
+            // VS Code names its per language files after the language
+            let language = match path.extension().and_then(|x| x.to_str()) {
+                Some("json") => path.file_stem().and_then(|x| x.to_str()),
+                _ => None,
+            };
+
+            let mut file_skipped = Vec::new();
+
+            let file_entries = match *self {
+                Source::Pet => pet::from_str(data.as_str(), &mut file_skipped),
+                Source::Navi => navi::from_str(data.as_str(), &mut file_skipped),
+                Source::Vscode => vscode::from_str(data.as_str(), language, &mut file_skipped),
+                Source::Cheat => tldr::from_str(data.as_str(), &mut file_skipped),
+            }
+            .context(format!("failed to parse {:?}", path))?;
+
+            entries.extend(file_entries);
+            skipped.extend(file_skipped.into_iter().map(|x| format!("{}: {}", path.display(), x)));
+        }
+
+        Ok((entries, skipped))
+    }
+}
+
+fn find_files(dir: &Path, extensions: &[&str], paths: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for dir_entry in fs::read_dir(dir).context(format!("failed to read directory {:?}", dir))? {
+        let path = dir_entry.context("failed to read directory entry")?.path();
+
+        if path.is_dir() {
+            find_files(&path, extensions, paths)?;
+        } else if path.to_str().is_some_and(|x| extensions.iter().any(|ext| x.ends_with(format!(".{}", ext).as_str()))) {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+// editors whose snippet files can be exported
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Editor {
+    // a `.code-snippets` file, see `vscode`
+    Vscode,
+    // a `.snippets` file, see `ultisnips`
+    Ultisnips,
+    // a directory with a file per snippet, see `yasnippet`
+    Yasnippet,
+    // a directory of `.sublime-snippet` files, see `sublime`
+    Sublime,
+}
+
+impl FromStr for Editor {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Editor, Self::Err> {
+        match s {
+            "vscode" => Ok(Editor::Vscode),
+            "ultisnips" => Ok(Editor::Ultisnips),
+            "yasnippet" => Ok(Editor::Yasnippet),
+            "sublime" => Ok(Editor::Sublime),
+            _ => Err(ErrorKind::InvalidArgument(format!("unknown editor '{}', expected vscode, ultisnips, yasnippet or sublime", s))),
+        }
+    }
+}
+
+impl Editor {
+    // whether the snippets are written to a single file or stream
+    pub fn is_single_file(&self) -> bool {
+        *self == Editor::Vscode || *self == Editor::Ultisnips
+    }
+
+    pub fn serialize(&self, entries: &[Entry]) -> Result<String, Error> {
+        match *self {
+            Editor::Vscode => vscode::to_string(entries),
+            Editor::Ultisnips => Ok(ultisnips::to_string(entries)),
+            Editor::Yasnippet | Editor::Sublime => bail!("{:?} snippets have no single file", self),
+        }
+    }
+
+    pub fn write(&self, dir: &Path, entries: &[Entry]) -> Result<(), Error> {
+        match *self {
+            Editor::Yasnippet => yasnippet::write(dir, entries),
+            Editor::Sublime => sublime::write(dir, entries),
+            Editor::Vscode | Editor::Ultisnips => bail!("{:?} snippets have no directory", self),
+        }
+    }
+}
+
+// A file name that is not in `used` yet, numbered from 2 on collisions.
+// Case is ignored as some file systems do.
+fn unique_file_name(used: &mut HashSet<String>, stem: &str, extension: &str) -> String {
+    let mut file_name = format!("{}{}", stem, extension);
+    let mut n = 2;
+
+    while !used.insert(file_name.to_lowercase()) {
+        file_name = format!("{}-{}{}", stem, n, extension);
+        n += 1;
+    }
+
+    file_name
+}
+
+// what to type in an editor to expand the snippet
+fn trigger(name: &str) -> String {
+    let trigger = name.to_lowercase()
+        .split(|x: char| !x.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
+
+    match trigger.as_str() {
+        "" => "snippet".to_string(),
+        _ => trigger,
+    }
+}
+
+// Turn placeholders into numbered TextMate-style tab stops, which all the
+// supported editors understand. Later occurrences of a name mirror the
+// first, names without a default become the text to overwrite. `special`
+// are the characters an editor wants escaped with a backslash.
+fn tab_stops(content: &str, special: &[char]) -> String {
+    let placeholders = template::placeholders(content);
+    let mut seen = HashSet::new();
+
+    template::transform(content, |text| escape(text, special, false), |placeholder| {
+        let (n, first) = placeholders.iter()
+            .enumerate()
+            .find(|&(_, x)| x.name == placeholder.name)
+            .map(|(i, x)| (i + 1, x))
+            .unwrap();
+
+        if !seen.insert(n) {
+            return format!("${}", n);
+        }
+
+        let default = first.default.as_ref().unwrap_or(&first.name);
+
+        format!("${{{}:{}}}", n, escape(default.as_str(), special, true))
+    })
+}
+
+// closing braces end tab stops and need escaping within them
+fn escape(text: &str, special: &[char], in_tab_stop: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if special.contains(&c) || (in_tab_stop && c == '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+// an rsm placeholder for a variable of another format, other tools are less
+// strict about names
+fn placeholder(name: &str, default: Option<&str>) -> String {
+    let name = name.chars()
+        .map(|x| if x.is_alphanumeric() || x == '_' || x == '-' { x } else { '_' })
+        .collect::<String>();
+
+    match default {
+        Some(default) => format!("{{{{{}:{}}}}}", name, default),
+        None => format!("{{{{{}}}}}", name),
+    }
+}
+
+// Replace `<name>` and `<name=default>` variables as used by pet and navi.
+// Names cannot contain whitespace, which keeps redirections like
+// `sort < in > out` intact.
+fn convert_angle_variables(command: &str) -> String {
+    let mut converted = String::with_capacity(command.len());
+    let mut rest = command;
+
+    while let Some(start) = rest.find('<') {
+        converted.push_str(&rest[..start]);
+        let inner = &rest[start + 1..];
+
+        let variable = inner.find(['>', '<'])
+            .filter(|&end| inner[end..].starts_with('>'))
+            .map(|end| (end, &inner[..end]))
+            .and_then(|(end, variable)| {
+                let mut split = variable.splitn(2, '=');
+                let name = split.next().unwrap_or("");
+
+                if name.is_empty() || name.contains(char::is_whitespace) {
+                    return None;
+                }
+
+                Some((end, placeholder(name, split.next())))
+            });
+
+        match variable {
+            Some((end, placeholder)) => {
+                converted.push_str(placeholder.as_str());
+                rest = &inner[end + 1..];
+            },
+            None => {
+                converted.push('<');
+                rest = inner;
+            },
+        }
+    }
+
+    converted.push_str(rest);
+
+    converted
+}
@@ -0,0 +1,89 @@
+// navi cheatsheets are plain text files of described commands, grouped by
+// the tags on the `%` line above them:
+//
+//   % git, vcs
+//
+//   # Change branch
+//   git checkout <branch>
+//
+//   $ branch: git branch | awk '{print $NF}'
+//
+// The description becomes the name and `<name>` variables become
+// placeholders. The `$` lines suggest values through a command, rsm has no
+// such thing and drops them along with `;` comments and `@` references to
+// other cheatsheets.
+
+use failure::*;
+use exchange::{self, Entry};
+
+pub fn from_str(data: &str, skipped: &mut Vec<String>) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+    let mut description: Option<String> = None;
+    let mut command: Vec<&str> = Vec::new();
+
+    for line in data.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with(';') {
+            continue;
+        }
+
+        // a command ends with the first line that is not part of it
+        let is_command = !trimmed.is_empty()
+            && !trimmed.starts_with('%')
+            && !trimmed.starts_with('#')
+            && !trimmed.starts_with('$')
+            && !trimmed.starts_with('@');
+
+        if is_command {
+            command.push(line);
+            continue;
+        }
+
+        push_command(&mut entries, &tags, &mut description, &mut command, skipped);
+
+        if let Some(tag_list) = trimmed.strip_prefix('%') {
+            tags = tag_list.split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect();
+            description = None;
+        } else if let Some(comment) = trimmed.strip_prefix('#') {
+            description = Some(comment.trim().to_string());
+        }
+    }
+
+    push_command(&mut entries, &tags, &mut description, &mut command, skipped);
+
+    Ok(entries)
+}
+
+// a description is used up by the command following it
+fn push_command(entries: &mut Vec<Entry>, tags: &[String], description: &mut Option<String>,
+                command: &mut Vec<&str>, skipped: &mut Vec<String>) {
+    if command.is_empty() {
+        return;
+    }
+
+    match description.take() {
+        Some(name) => {
+            let mut content = exchange::convert_angle_variables(command.join("\n").as_str());
+            content.push('\n');
+
+            entries.push(Entry {
+                name: name,
+                tags: tags.to_vec(),
+                content: content,
+                language: None,
+                description: None,
+                source: None,
+                created_at: None,
+                updated_at: None,
+            });
+        },
+        None => skipped.push(format!("'{}' has no description", command[0].trim())),
+    }
+
+    command.clear();
+}
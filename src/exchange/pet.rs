@@ -0,0 +1,70 @@
+// pet keeps its snippets in a single TOML file:
+//
+//   [[snippets]]
+//     description = "ping a host"
+//     command = "ping -c <count=3> <host>"
+//     tag = ["network"]
+//     output = ""
+//
+// The description becomes the name, `<name>` and `<name=default>` parameters
+// become placeholders. The output of a command is not kept.
+
+use failure::*;
+use toml;
+use exchange::{self, Entry};
+use error::ErrorKind;
+
+#[derive(Deserialize)]
+struct Document {
+    #[serde(default)]
+    snippets: Vec<Snippet>,
+}
+
+#[derive(Deserialize)]
+struct Snippet {
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    command: String,
+    #[serde(default)]
+    tag: Vec<String>,
+}
+
+pub fn from_str(data: &str, skipped: &mut Vec<String>) -> Result<Vec<Entry>, Error> {
+    let document: Document = toml::from_str(data)
+        .map_err(|e| ErrorKind::InvalidImport(e.to_string()))?;
+
+    let mut entries = Vec::new();
+
+    for snippet in document.snippets {
+        if snippet.command.trim().is_empty() {
+            skipped.push(format!("'{}' has no command", snippet.description));
+            continue;
+        }
+
+        // pet does not require a description but rsm requires a name
+        let name = match snippet.description.trim() {
+            "" => snippet.command.trim().to_string(),
+            description => description.to_string(),
+        };
+
+        let mut content = exchange::convert_angle_variables(snippet.command.as_str());
+
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+
+        entries.push(Entry {
+            name: name,
+            tags: snippet.tag,
+            content: content,
+            language: None,
+            description: None,
+            source: None,
+            created_at: None,
+            updated_at: None,
+        });
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,49 @@
+// Sublime Text loads every `.sublime-snippet` file of its packages, the
+// directory is meant to go into `Packages/User`:
+//
+//   <snippet>
+//       <content><![CDATA[git log --oneline ${1:HEAD}]]></content>
+//       <tabTrigger>git-log</tabTrigger>
+//       <description>git log</description>
+//   </snippet>
+//
+// File names and triggers are derived from the snippet names.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::fs;
+use failure::*;
+use exchange::{self, Entry};
+
+static EXTENSION: &str = ".sublime-snippet";
+
+pub fn write(dir: &Path, entries: &[Entry]) -> Result<(), Error> {
+    fs::create_dir_all(dir)
+        .context(format!("failed to create directory {:?}", dir))?;
+
+    let mut used = HashSet::new();
+
+    for entry in entries {
+        let trigger = exchange::trigger(entry.name.as_str());
+        let file_name = exchange::unique_file_name(&mut used, trigger.as_str(), EXTENSION);
+
+        let content = exchange::tab_stops(entry.content.trim_end_matches('\n'), &['\\', '$']);
+
+        // CDATA sections end at the first `]]>`
+        let data = format!("<snippet>\n    <content><![CDATA[{}]]></content>\n    <tabTrigger>{}</tabTrigger>\n    <description>{}</description>\n</snippet>\n",
+                           content.replace("]]>", "]]]]><![CDATA[>"),
+                           trigger,
+                           escape_xml(entry.name.as_str()));
+
+        fs::write(dir.join(file_name.as_str()), data)
+            .context(format!("failed to write snippet file {}", file_name))?;
+    }
+
+    Ok(())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
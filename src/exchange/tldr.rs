@@ -0,0 +1,107 @@
+// tldr pages, which cheat sheet tools like cheat.sh serve as well, describe
+// the examples of a command in Markdown:
+//
+//   # tar
+//
+//   > Archiving utility.
+//   > More information: <https://www.gnu.org/software/tar>.
+//
+//   - Create an archive from files:
+//
+//   `tar cf {{target.tar}} {{file1}} {{file2}}`
+//
+// Every example becomes a snippet named after the command and its
+// description, tagged with the command. The summary of the page becomes the
+// description of the snippets and the link to more information their source.
+// The example values between braces become placeholders, named after the
+// value with anything but letters, digits, `_` and `-` replaced.
+
+use failure::*;
+use exchange::{self, Entry};
+
+static MORE_INFORMATION_PREFIX: &str = "More information:";
+
+pub fn from_str(data: &str, skipped: &mut Vec<String>) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    let mut title: Option<String> = None;
+    let mut description: Option<String> = None;
+    let mut summary: Vec<&str> = Vec::new();
+    let mut source: Option<String> = None;
+
+    for line in data.lines() {
+        let line = line.trim();
+
+        if let Some(heading) = line.strip_prefix("# ") {
+            title = Some(heading.trim().to_string());
+        } else if let Some(quote) = line.strip_prefix('>') {
+            let text = quote.trim();
+
+            if let Some(link) = text.strip_prefix(MORE_INFORMATION_PREFIX) {
+                let link = link.trim().trim_end_matches('.');
+                source = Some(link.trim_start_matches('<').trim_end_matches('>').to_string());
+            } else if !text.is_empty() {
+                summary.push(text);
+            }
+        } else if let Some(item) = line.strip_prefix("- ") {
+            description = Some(item.trim().trim_end_matches(':').to_string());
+        } else if line.len() > 1 && line.starts_with('`') && line.ends_with('`') {
+            let command = &line[1..line.len() - 1];
+
+            let description = match description.take() {
+                Some(description) => description,
+                None => {
+                    skipped.push(format!("'{}' has no description", command));
+                    continue;
+                },
+            };
+
+            let (name, tags) = match title {
+                Some(ref title) => (format!("{}: {}", title, description), vec![title.clone()]),
+                None => (description, Vec::new()),
+            };
+
+            entries.push(Entry {
+                name: name,
+                tags: tags,
+                content: format!("{}\n", convert_placeholders(command)),
+                language: None,
+                description: if summary.is_empty() { None } else { Some(summary.join(" ")) },
+                source: source.clone(),
+                created_at: None,
+                updated_at: None,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+fn convert_placeholders(command: &str) -> String {
+    let mut converted = String::with_capacity(command.len());
+    let mut rest = command;
+
+    while let Some(start) = rest.find("{{") {
+        let inner = &rest[start + 2..];
+
+        let end = match inner.find("}}") {
+            Some(end) => end,
+            None => break,
+        };
+
+        let name = inner[..end].trim_matches(|x: char| !x.is_alphanumeric());
+
+        converted.push_str(&rest[..start]);
+
+        if name.is_empty() {
+            converted.push_str(&rest[start..start + 2 + end + 2]);
+        } else {
+            converted.push_str(exchange::placeholder(name, None).as_str());
+        }
+
+        rest = &inner[end + 2..];
+    }
+
+    converted.push_str(rest);
+
+    converted
+}
@@ -0,0 +1,30 @@
+// UltiSnips keeps the snippets for a file type in a `.snippets` file:
+//
+//   snippet git-log "git log"
+//   git log --oneline ${1:HEAD}
+//   endsnippet
+//
+// Exported snippets belong in `all.snippets` to be available everywhere.
+// Triggers are derived from the names, backticks and dollar signs are
+// escaped as they would be interpolated.
+
+use exchange::{self, Entry};
+
+pub fn to_string(entries: &[Entry]) -> String {
+    let mut data = String::new();
+
+    for entry in entries {
+        let body = exchange::tab_stops(entry.content.as_str(), &['\\', '$', '`']);
+
+        data.push_str(format!("snippet {} \"{}\"\n", exchange::trigger(entry.name.as_str()), entry.name.replace('"', "'")).as_str());
+        data.push_str(body.as_str());
+
+        if !body.is_empty() && !body.ends_with('\n') {
+            data.push('\n');
+        }
+
+        data.push_str("endsnippet\n\n");
+    }
+
+    data
+}
@@ -0,0 +1,326 @@
+// VS Code snippet files map names to snippets, in JSON with comments and
+// trailing commas allowed:
+//
+//   {
+//     "Print to console": {
+//       "scope": "javascript,typescript",
+//       "prefix": "log",
+//       "body": ["console.log('${1:message}');", "$0"],
+//       "description": "Log output to console"
+//     }
+//   }
+//
+// Files named after a language like `rust.json` instead of `*.code-snippets`
+// have no scope, the language is taken from the file name. Scopes become
+// tags, tab stops and variables become placeholders named after their number
+// or variable and the final cursor position `$0` is dropped. Descriptions
+// are kept in both directions.
+//
+// Exports are `*.code-snippets` files scoped to the language of a snippet,
+// with the prefix derived from the name and placeholders as tab stops.
+
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::str::Chars;
+use failure::*;
+use serde_json;
+use exchange::{self, Entry};
+use error::ErrorKind;
+use language;
+
+// what VS Code calls shell scripts
+static SHELL_SCOPE: &str = "shellscript";
+
+#[derive(Deserialize)]
+struct Snippet {
+    #[serde(default)]
+    scope: Option<String>,
+    #[serde(default)]
+    body: Option<Body>,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Body {
+    Line(String),
+    Lines(Vec<String>),
+}
+
+#[derive(Serialize)]
+struct ExportedSnippet {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    prefix: String,
+    body: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+pub fn to_string(entries: &[Entry]) -> Result<String, Error> {
+    let snippets = entries.iter()
+        .map(|entry| {
+            let body = exchange::tab_stops(entry.content.as_str(), &['\\', '$'])
+                .lines()
+                .map(|x| x.to_string())
+                .collect();
+
+            (entry.name.as_str(), ExportedSnippet {
+                scope: entry.language.as_ref().map(|x| to_scope(x.as_str())),
+                prefix: exchange::trigger(entry.name.as_str()),
+                body: body,
+                description: entry.description.clone(),
+            })
+        })
+        .collect::<BTreeMap<&str, ExportedSnippet>>();
+
+    let mut data = serde_json::to_string_pretty(&snippets)
+        .context("failed to serialize snippets")?;
+    data.push('\n');
+
+    Ok(data)
+}
+
+pub fn from_str(data: &str, language: Option<&str>, skipped: &mut Vec<String>) -> Result<Vec<Entry>, Error> {
+    // keyed by name, a BTreeMap keeps the order stable
+    let snippets: BTreeMap<String, Snippet> = serde_json::from_str(strip_comments(data).as_str())
+        .map_err(|e| ErrorKind::InvalidImport(e.to_string()))?;
+
+    let mut entries = Vec::new();
+
+    for (name, snippet) in snippets {
+        let body = match snippet.body {
+            Some(Body::Line(line)) => line,
+            Some(Body::Lines(lines)) => lines.join("\n"),
+            None => {
+                skipped.push(format!("'{}' has no body", name));
+                continue;
+            },
+        };
+
+        let tags: Vec<String> = match snippet.scope {
+            Some(scope) => scope.split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect(),
+            None => language.into_iter().map(|x| x.to_string()).collect(),
+        };
+
+        // snippets for several languages have none in rsm
+        let language = if tags.len() == 1 {
+            from_scope(tags[0].as_str())
+        } else {
+            None
+        };
+
+        let mut content = convert_body(body.as_str());
+
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+
+        entries.push(Entry {
+            name: name,
+            tags: tags,
+            content: content,
+            language: language,
+            description: snippet.description.filter(|x| !x.trim().is_empty()),
+            source: None,
+            created_at: None,
+            updated_at: None,
+        });
+    }
+
+    Ok(entries)
+}
+
+// language ids of VS Code mostly match the names in rsm
+fn to_scope(language: &str) -> String {
+    match language {
+        "sh" | "bash" | "zsh" => SHELL_SCOPE.to_string(),
+        _ => language.to_string(),
+    }
+}
+
+fn from_scope(scope: &str) -> Option<String> {
+    if scope == SHELL_SCOPE {
+        return Some("sh".to_string());
+    }
+
+    language::find(scope).map(|x| x.name.to_string())
+}
+
+// Remove `//` and `/* */` comments and trailing commas, which VS Code
+// accepts and serde_json does not.
+fn strip_comments(data: &str) -> String {
+    let mut stripped = String::with_capacity(data.len());
+    let mut chars = data.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                stripped.push(c);
+
+                while let Some(c) = chars.next() {
+                    stripped.push(c);
+
+                    match c {
+                        '\\' => stripped.extend(chars.next()),
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+            },
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&x| x != '\n') {
+                    chars.next();
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            },
+            '}' | ']' => {
+                let len = stripped.trim_end().len();
+
+                if stripped[..len].ends_with(',') {
+                    stripped.truncate(len - 1);
+                }
+
+                stripped.push(c);
+            },
+            _ => stripped.push(c),
+        }
+    }
+
+    stripped
+}
+
+// turn tab stops, placeholders, choices and variables into rsm placeholders
+fn convert_body(body: &str) -> String {
+    let mut converted = String::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+
+    convert(&mut chars, &mut converted, false, false);
+
+    converted
+}
+
+// Convert up to the end or, when `nested`, the closing brace of a
+// placeholder. Placeholders within a default cannot be nested in rsm, in
+// `plain` mode they are replaced with their default or name.
+fn convert(chars: &mut Peekable<Chars>, converted: &mut String, nested: bool, plain: bool) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                match chars.peek() {
+                    Some(&x) if x == '$' || x == '}' || x == '\\' => {
+                        converted.push(x);
+                        chars.next();
+                    },
+                    _ => converted.push(c),
+                }
+            },
+            '}' if nested => return,
+            '$' => {
+                let braced = chars.peek() == Some(&'{');
+
+                if braced {
+                    chars.next();
+                }
+
+                let name = take_name(chars);
+
+                if name.is_empty() {
+                    converted.push('$');
+
+                    if braced {
+                        converted.push('{');
+                    }
+
+                    continue;
+                }
+
+                let mut default = None;
+
+                if braced {
+                    match chars.next() {
+                        Some(':') => {
+                            let mut text = String::new();
+                            convert(chars, &mut text, true, true);
+                            default = Some(text);
+                        },
+                        // the first of a choice is the default
+                        Some('|') => {
+                            let mut choices = String::new();
+
+                            while let Some(c) = chars.next() {
+                                if c == '|' && chars.peek() == Some(&'}') {
+                                    chars.next();
+                                    break;
+                                }
+                                choices.push(c);
+                            }
+
+                            default = choices.split(',').next().map(|x| x.to_string());
+                        },
+                        // transformations of variables are not supported
+                        Some('/') => {
+                            let mut text = String::new();
+                            convert(chars, &mut text, true, true);
+                        },
+                        _ => (),
+                    }
+                }
+
+                if name == "0" {
+                    if let Some(default) = default {
+                        converted.push_str(default.as_str());
+                    }
+                } else if plain {
+                    converted.push_str(default.as_ref().unwrap_or(&name));
+                } else {
+                    converted.push_str(exchange::placeholder(name.as_str(), default.as_deref()).as_str());
+                }
+            },
+            _ => converted.push(c),
+        }
+    }
+}
+
+// a tab stop number or a variable name
+fn take_name(chars: &mut Peekable<Chars>) -> String {
+    let mut name = String::new();
+
+    match chars.peek() {
+        Some(&x) if x.is_ascii_digit() => {
+            while let Some(&x) = chars.peek() {
+                if !x.is_ascii_digit() {
+                    break;
+                }
+                name.push(x);
+                chars.next();
+            }
+        },
+        Some(&x) if x.is_ascii_alphabetic() || x == '_' => {
+            while let Some(&x) = chars.peek() {
+                if !x.is_ascii_alphanumeric() && x != '_' {
+                    break;
+                }
+                name.push(x);
+                chars.next();
+            }
+        },
+        _ => (),
+    }
+
+    name
+}
@@ -0,0 +1,39 @@
+// yasnippet wants a file per snippet in a directory named after the major
+// mode, export into `fundamental-mode` to have them available everywhere:
+//
+//   # -*- mode: snippet -*-
+//   # name: git log
+//   # key: git-log
+//   # --
+//   git log --oneline ${1:HEAD}
+//
+// File names and keys are derived from the snippet names, backticks and
+// dollar signs are escaped as they would be evaluated.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::fs;
+use failure::*;
+use exchange::{self, Entry};
+
+pub fn write(dir: &Path, entries: &[Entry]) -> Result<(), Error> {
+    fs::create_dir_all(dir)
+        .context(format!("failed to create directory {:?}", dir))?;
+
+    let mut used = HashSet::new();
+
+    for entry in entries {
+        let key = exchange::trigger(entry.name.as_str());
+        let file_name = exchange::unique_file_name(&mut used, key.as_str(), "");
+
+        let data = format!("# -*- mode: snippet -*-\n# name: {}\n# key: {}\n# --\n{}",
+                           entry.name.replace('\n', " "),
+                           key,
+                           exchange::tab_stops(entry.content.as_str(), &['\\', '$', '`']));
+
+        fs::write(dir.join(file_name.as_str()), data)
+            .context(format!("failed to write snippet file {}", file_name))?;
+    }
+
+    Ok(())
+}
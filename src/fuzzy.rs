@@ -0,0 +1,59 @@
+// Fuzzy matching of names, the characters of the pattern have to appear in
+// the text in the same order but not necessarily next to each other.
+
+// bonus for a matched character directly following the previous match
+const CONSECUTIVE_BONUS: i64 = 5;
+// bonus for a matched character starting a word
+const WORD_START_BONUS: i64 = 10;
+// penalty for every skipped character after the first match
+const GAP_PENALTY: i64 = 1;
+
+// None if `text` does not contain the pattern, otherwise a score that is
+// higher for tighter matches. Case and whitespace in the pattern are ignored
+pub fn score(pattern: &str, text: &str) -> Option<i64> {
+    let mut pattern = pattern.chars()
+        .filter(|x| !x.is_whitespace())
+        .flat_map(|x| x.to_lowercase())
+        .peekable();
+
+    let mut score = 0;
+    let mut started = false;
+    let mut consecutive = false;
+    let mut prev: Option<char> = None;
+
+    for c in text.chars() {
+        let wanted = match pattern.peek() {
+            Some(&x) => x,
+            None => break,
+        };
+
+        if c.to_lowercase().eq(Some(wanted)) {
+            pattern.next();
+            score += 1;
+
+            if consecutive {
+                score += CONSECUTIVE_BONUS;
+            }
+
+            if prev.is_none_or(|x| !x.is_alphanumeric()) {
+                score += WORD_START_BONUS;
+            }
+
+            started = true;
+            consecutive = true;
+        } else {
+            if started {
+                score -= GAP_PENALTY;
+            }
+
+            consecutive = false;
+        }
+
+        prev = Some(c);
+    }
+
+    match pattern.peek() {
+        Some(_) => None,
+        None => Some(score),
+    }
+}
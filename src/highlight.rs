@@ -0,0 +1,103 @@
+use ansi_term::{Style, Colour};
+use rsm::language::Language;
+
+// A rough highlighter for the content of snippets. It knows comments,
+// strings, numbers and keywords, which covers most of what snippets are
+// and keeps rsm free of a full grammar engine.
+pub fn highlight(content: &str, language: &Language) -> String {
+    let keyword = Colour::Blue.bold();
+    let string = Style::new().fg(Colour::Green);
+    let comment = Style::new().fg(Colour::Fixed(244));
+    let number = Style::new().fg(Colour::Yellow);
+
+    let mut highlighted = String::with_capacity(content.len());
+    let mut rest = content;
+    // `#` only starts a comment after whitespace, think of `$#` in sh
+    let mut after_space = true;
+
+    while let Some(c) = rest.chars().next() {
+        let (len, style) = if let Some(len) = line_comment(rest, language, after_space) {
+            (len, Some(comment))
+        } else if let Some(len) = block_comment(rest, language) {
+            (len, Some(comment))
+        } else if language.quotes.contains(c) {
+            (string_len(rest, c), Some(string))
+        } else if c.is_alphanumeric() || c == '_' {
+            let len = rest.find(|x: char| !x.is_alphanumeric() && x != '_').unwrap_or(rest.len());
+            let word = &rest[..len];
+
+            if c.is_ascii_digit() {
+                (len, Some(number))
+            } else if language.keywords.contains(&word) {
+                (len, Some(keyword))
+            } else {
+                (len, None)
+            }
+        } else {
+            (c.len_utf8(), None)
+        };
+
+        match style {
+            Some(style) => paint(&mut highlighted, style, &rest[..len]),
+            None => highlighted.push_str(&rest[..len]),
+        }
+
+        after_space = c.is_whitespace() && len == c.len_utf8();
+        rest = &rest[len..];
+    }
+
+    highlighted
+}
+
+fn line_comment(text: &str, language: &Language, after_space: bool) -> Option<usize> {
+    let prefix = language.line_comment?;
+
+    if !text.starts_with(prefix) || (prefix == "#" && !after_space) {
+        return None;
+    }
+
+    Some(text.find('\n').unwrap_or(text.len()))
+}
+
+fn block_comment(text: &str, language: &Language) -> Option<usize> {
+    let (start, end) = language.block_comment?;
+
+    if !text.starts_with(start) {
+        return None;
+    }
+
+    let len = text[start.len()..].find(end)
+        .map_or(text.len(), |x| start.len() + x + end.len());
+
+    Some(len)
+}
+
+// up to and including the closing quote, backslashes escape it
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return i + c.len_utf8();
+        }
+    }
+
+    text.len()
+}
+
+// styled line by line so pagers and terminals do not carry the style over
+fn paint(highlighted: &mut String, style: Style, text: &str) {
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            highlighted.push('\n');
+        }
+
+        if !line.is_empty() {
+            highlighted.push_str(style.paint(line).to_string().as_str());
+        }
+    }
+}
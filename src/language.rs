@@ -0,0 +1,347 @@
+use std::path::Path;
+
+// What rsm knows about the language of a snippet: how to recognize it and
+// enough of its syntax for highlighting. Languages it does not know can
+// still be set, they are just displayed as plain text.
+pub struct Language {
+    pub name: &'static str,
+    // the first one is used for temporary files
+    pub extensions: &'static [&'static str],
+    // programs in shebang lines, without version numbers
+    pub interpreters: &'static [&'static str],
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    // characters that start and end strings
+    pub quotes: &'static str,
+    pub keywords: &'static [&'static str],
+}
+
+static SHELL_KEYWORDS: &[&str] = &[
+    "case", "do", "done", "elif", "else", "esac", "exit", "export", "fi", "for", "function",
+    "if", "in", "local", "readonly", "return", "select", "set", "shift", "then", "trap",
+    "unset", "until", "while",
+];
+
+static C_KEYWORDS: &[&str] = &[
+    "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum",
+    "extern", "float", "for", "goto", "if", "int", "long", "return", "short", "signed",
+    "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void",
+    "volatile", "while",
+];
+
+static DATA_KEYWORDS: &[&str] = &["false", "null", "true"];
+
+pub static LANGUAGES: &[Language] = &[
+    Language {
+        name: "sh",
+        extensions: &["sh"],
+        interpreters: &["sh", "dash", "ksh"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'`",
+        keywords: SHELL_KEYWORDS,
+    },
+    Language {
+        name: "bash",
+        extensions: &["bash"],
+        interpreters: &["bash"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'`",
+        keywords: SHELL_KEYWORDS,
+    },
+    Language {
+        name: "zsh",
+        extensions: &["zsh"],
+        interpreters: &["zsh"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'`",
+        keywords: SHELL_KEYWORDS,
+    },
+    Language {
+        name: "fish",
+        extensions: &["fish"],
+        interpreters: &["fish"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'",
+        keywords: &["and", "begin", "case", "else", "end", "for", "function", "if", "in",
+                    "not", "or", "return", "set", "switch", "while"],
+    },
+    Language {
+        name: "powershell",
+        extensions: &["ps1"],
+        interpreters: &["pwsh", "powershell"],
+        line_comment: Some("#"),
+        block_comment: Some(("<#", "#>")),
+        quotes: "\"'",
+        keywords: &["begin", "break", "catch", "continue", "do", "else", "elseif", "end",
+                    "exit", "finally", "for", "foreach", "function", "if", "in", "param",
+                    "process", "return", "switch", "throw", "try", "until", "while"],
+    },
+    Language {
+        name: "python",
+        extensions: &["py"],
+        interpreters: &["python"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'",
+        keywords: &["False", "None", "True", "and", "as", "assert", "async", "await", "break",
+                    "class", "continue", "def", "del", "elif", "else", "except", "finally",
+                    "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal",
+                    "not", "or", "pass", "raise", "return", "try", "while", "with", "yield"],
+    },
+    Language {
+        name: "ruby",
+        extensions: &["rb"],
+        interpreters: &["ruby"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'`",
+        keywords: &["begin", "class", "def", "do", "else", "elsif", "end", "ensure", "false",
+                    "for", "if", "in", "module", "next", "nil", "require", "rescue", "return",
+                    "self", "then", "true", "unless", "until", "when", "while", "yield"],
+    },
+    Language {
+        name: "perl",
+        extensions: &["pl"],
+        interpreters: &["perl"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'`",
+        keywords: &["else", "elsif", "for", "foreach", "if", "last", "local", "my", "next",
+                    "our", "package", "return", "sub", "unless", "until", "use", "while"],
+    },
+    Language {
+        name: "awk",
+        extensions: &["awk"],
+        interpreters: &["awk", "gawk", "mawk"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"",
+        keywords: &["BEGIN", "END", "break", "continue", "delete", "else", "exit", "for",
+                    "function", "getline", "if", "in", "next", "print", "printf", "return",
+                    "while"],
+    },
+    Language {
+        name: "lua",
+        extensions: &["lua"],
+        interpreters: &["lua", "luajit"],
+        line_comment: Some("--"),
+        block_comment: None,
+        quotes: "\"'",
+        keywords: &["and", "break", "do", "else", "elseif", "end", "false", "for", "function",
+                    "if", "in", "local", "nil", "not", "or", "repeat", "return", "then", "true",
+                    "until", "while"],
+    },
+    Language {
+        name: "php",
+        extensions: &["php"],
+        interpreters: &["php"],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'",
+        keywords: &["as", "break", "class", "continue", "echo", "else", "elseif", "false",
+                    "for", "foreach", "function", "if", "new", "null", "public", "private",
+                    "return", "static", "true", "use", "while"],
+    },
+    Language {
+        name: "javascript",
+        extensions: &["js", "mjs"],
+        interpreters: &["node", "nodejs"],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'`",
+        keywords: &["async", "await", "break", "case", "catch", "class", "const", "continue",
+                    "default", "else", "export", "false", "for", "function", "if", "import",
+                    "in", "let", "new", "null", "of", "return", "switch", "this", "throw",
+                    "true", "try", "typeof", "undefined", "var", "while"],
+    },
+    Language {
+        name: "typescript",
+        extensions: &["ts"],
+        interpreters: &["ts-node", "deno"],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'`",
+        keywords: &["async", "await", "break", "case", "catch", "class", "const", "continue",
+                    "default", "else", "enum", "export", "false", "for", "function", "if",
+                    "import", "in", "interface", "let", "new", "null", "of", "return", "switch",
+                    "this", "throw", "true", "try", "type", "typeof", "undefined", "var",
+                    "while"],
+    },
+    Language {
+        name: "rust",
+        extensions: &["rs"],
+        interpreters: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"",
+        keywords: &["as", "break", "const", "continue", "crate", "else", "enum", "extern",
+                    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+                    "move", "mut", "pub", "ref", "return", "self", "static", "struct", "trait",
+                    "true", "type", "unsafe", "use", "where", "while"],
+    },
+    Language {
+        name: "go",
+        extensions: &["go"],
+        interpreters: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'`",
+        keywords: &["break", "case", "chan", "const", "continue", "default", "defer", "else",
+                    "for", "func", "go", "if", "import", "interface", "map", "package", "range",
+                    "return", "select", "struct", "switch", "type", "var"],
+    },
+    Language {
+        name: "c",
+        extensions: &["c", "h"],
+        interpreters: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'",
+        keywords: C_KEYWORDS,
+    },
+    Language {
+        name: "cpp",
+        extensions: &["cpp", "cc", "hpp"],
+        interpreters: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'",
+        keywords: C_KEYWORDS,
+    },
+    Language {
+        name: "java",
+        extensions: &["java"],
+        interpreters: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'",
+        keywords: &["break", "case", "catch", "class", "continue", "default", "else", "extends",
+                    "false", "final", "for", "if", "implements", "import", "new", "null",
+                    "package", "private", "protected", "public", "return", "static", "switch",
+                    "this", "throw", "throws", "true", "try", "void", "while"],
+    },
+    Language {
+        name: "sql",
+        extensions: &["sql"],
+        interpreters: &[],
+        line_comment: Some("--"),
+        block_comment: Some(("/*", "*/")),
+        quotes: "'\"",
+        keywords: &["AND", "AS", "BY", "CREATE", "DELETE", "DROP", "FROM", "GROUP", "INSERT",
+                    "INTO", "JOIN", "LIMIT", "NOT", "NULL", "ON", "OR", "ORDER", "SELECT", "SET",
+                    "TABLE", "UPDATE", "VALUES", "WHERE",
+                    "and", "as", "by", "create", "delete", "drop", "from", "group", "insert",
+                    "into", "join", "limit", "not", "null", "on", "or", "order", "select", "set",
+                    "table", "update", "values", "where"],
+    },
+    Language {
+        name: "json",
+        extensions: &["json"],
+        interpreters: &[],
+        line_comment: None,
+        block_comment: None,
+        quotes: "\"",
+        keywords: DATA_KEYWORDS,
+    },
+    Language {
+        name: "yaml",
+        extensions: &["yaml", "yml"],
+        interpreters: &[],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'",
+        keywords: DATA_KEYWORDS,
+    },
+    Language {
+        name: "toml",
+        extensions: &["toml"],
+        interpreters: &[],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: "\"'",
+        keywords: DATA_KEYWORDS,
+    },
+    Language {
+        name: "html",
+        extensions: &["html", "htm", "xml"],
+        interpreters: &[],
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+        quotes: "\"",
+        keywords: &[],
+    },
+    Language {
+        name: "css",
+        extensions: &["css"],
+        interpreters: &[],
+        line_comment: None,
+        block_comment: Some(("/*", "*/")),
+        quotes: "\"'",
+        keywords: &["important"],
+    },
+    Language {
+        name: "markdown",
+        extensions: &["md"],
+        interpreters: &[],
+        line_comment: None,
+        block_comment: None,
+        quotes: "",
+        keywords: &[],
+    },
+];
+
+// a language by its name or one of its extensions
+pub fn find(name: &str) -> Option<&'static Language> {
+    let name = name.trim().to_lowercase();
+
+    LANGUAGES.iter()
+        .find(|x| x.name == name || x.extensions.contains(&name.as_str()))
+}
+
+// the file extension for a language, editors pick their syntax by it
+pub fn extension(name: &str) -> Option<&'static str> {
+    find(name).and_then(|x| x.extensions.first().cloned())
+}
+
+// The language of a snippet as far as its shebang line or an extension at
+// the end of its name like `backup.sh` give it away.
+pub fn detect(name: &str, content: &str) -> Option<&'static Language> {
+    if content.starts_with("#!") {
+        let shebang = content.lines().next().unwrap_or("");
+        let mut words = shebang[2..].split_whitespace().map(file_name);
+        let mut program = words.next();
+
+        // `#!/usr/bin/env -S VAR=x python3 -u`
+        if program == Some("env") {
+            program = words.find(|x| !x.starts_with('-') && !x.contains('='));
+        }
+
+        // python3.11 is just python
+        let language = program
+            .map(|x| x.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+            .and_then(|program| LANGUAGES.iter().find(|x| x.interpreters.contains(&program)));
+
+        if language.is_some() {
+            return language;
+        }
+    }
+
+    let extension = name.rsplit('.').next().unwrap_or("");
+
+    if extension.len() == name.len() || extension.is_empty() || extension.contains(char::is_whitespace) {
+        return None;
+    }
+
+    LANGUAGES.iter()
+        .find(|x| x.extensions.contains(&extension.to_lowercase().as_str()))
+}
+
+fn file_name(path: &str) -> &str {
+    Path::new(path).file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or(path)
+}
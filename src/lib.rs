@@ -0,0 +1,39 @@
+extern crate failure;
+extern crate sqlite;
+extern crate ansi_term;
+extern crate time;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+
+#[cfg(feature = "sync")]
+extern crate bufstream;
+#[cfg(feature = "sync")]
+extern crate native_tls;
+#[cfg(feature = "sync")]
+#[macro_use]
+extern crate json;
+#[cfg(feature = "sync")]
+extern crate sha2;
+
+pub mod snippet;
+pub mod error;
+pub mod config;
+pub mod util;
+pub mod fuzzy;
+pub mod template;
+pub mod language;
+pub mod exchange;
+pub mod store;
+pub mod storage;
+mod db;
+
+#[cfg(feature = "sync")]
+mod sync;
+
+pub use store::{SnippetStore, Modification, Duplicates, Imported};
+pub use storage::{Storage, SqliteStorage, MemoryStorage};
+#[cfg(feature = "sync")]
+pub use store::SyncSummary;
@@ -3,37 +3,47 @@ extern crate log;
 extern crate libc;
 extern crate clap;
 extern crate failure;
-extern crate sqlite;
 extern crate mktemp;
 extern crate ansi_term;
-extern crate time;
-
-#[cfg(feature = "sync")]
-extern crate bufstream;
-#[cfg(feature = "sync")]
-extern crate native_tls;
-#[cfg(feature = "sync")]
+extern crate termion;
 #[macro_use]
-extern crate json;
+extern crate serde_json;
+extern crate rsm;
 
-mod snippet;
 mod commands;
 mod content;
-mod error;
-mod util;
-mod db;
-
-#[cfg(feature = "sync")]
-mod sync;
+mod highlight;
+mod output;
+mod picker;
+mod term;
 
 use clap::{Arg, App, ArgGroup, ArgMatches, AppSettings, SubCommand};
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::env;
 use failure::*;
+use rsm::{config, exchange, Duplicates};
+use rsm::error::{self, ErrorKind};
+use output::Format;
+
+static EXIT_CODES: &str = "EXIT CODES:
+     1  other errors                  2  invalid arguments
+    10  unknown snippet              11  duplicate snippet name
+    12  name of a deleted snippet    13  unknown revision
+    14  no conflict for the snippet  15  unresolved conflict
+    16  ambiguous snippet name       17  missing placeholder value
+    20  database corrupt             21  database from a newer rsm
+    22  missing metadata             23  invalid revision
+    30  editor aborted               31  nothing picked
+    35  invalid import data          40  syncing not enabled
+    41  not logged in                42  sync authentication failed
+    43  sync token expired           44  certificate pin mismatch
+    45  sync server error            46  sync connection failed";
 
 // process cli arguments with clap
-fn process_cli<'a>() -> ArgMatches<'a> {
+fn process_cli<'a>() -> clap::Result<ArgMatches<'a>> {
     let id_arg = Arg::with_name("id")
-        .help("id of the snippet")
+        .help("id or name of the snippet, unique prefixes and fuzzy matches of names work too")
         .required(true);
 
     let name_arg = Arg::with_name("name")
@@ -47,24 +57,88 @@ fn process_cli<'a>() -> ArgMatches<'a> {
         .takes_value(true)
         .multiple(true);
 
+    let lang_arg = Arg::with_name("lang")
+        .help("language of the snippet, detected from a shebang or the name if not given")
+        .short("-l")
+        .long("--lang")
+        .takes_value(true);
+
+    let description_arg = Arg::with_name("description")
+        .help("what the snippet does")
+        .short("-d")
+        .long("--description")
+        .takes_value(true);
+
+    let source_arg = Arg::with_name("source")
+        .help("where the snippet comes from, like a URL")
+        .short("-s")
+        .long("--source")
+        .takes_value(true);
+
+    let format_arg = Arg::with_name("format")
+        .help("output format, everything but text is meant for scripts")
+        .short("-f")
+        .long("--format")
+        .possible_values(output::FORMATS)
+        .default_value("text");
+
     App::new("rsm")
         .version("0.1")
         .author("Cristian Kubis <cristian.kubis@tsunix.de>")
         .about("Multi-user snippet manager")
         .setting(AppSettings::DeriveDisplayOrder)
         .setting(AppSettings::SubcommandRequired)
+        .after_help(EXIT_CODES)
+        .arg(Arg::with_name("config")
+            .help("path of the config file")
+            .short("-c")
+            .long("--config")
+            .takes_value(true)
+            .global(true))
+        .arg(Arg::with_name("db")
+            .help("path of the database file")
+            .long("--db")
+            .takes_value(true)
+            .global(true))
+        .arg(Arg::with_name("error-format")
+            .help("format of error messages")
+            .long("--error-format")
+            .env("RSM_ERROR_FORMAT")
+            .possible_values(&["text", "json"])
+            .default_value("text")
+            .global(true))
         .subcommand(
             SubCommand::with_name("add")
                 .about("Used to add a new snippet")
                 .setting(AppSettings::TrailingVarArg)
                 .arg(&tag_arg)
+                .arg(&lang_arg)
+                .arg(&description_arg)
+                .arg(&source_arg)
                 .arg(&name_arg
                     .clone()
                     .required(true)))
         .subcommand(
             SubCommand::with_name("show")
                 .about("Used to display a snippet")
+                .arg(Arg::with_name("rev")
+                    .help("revision to display")
+                    .short("-r")
+                    .long("--rev")
+                    .takes_value(true))
+                .arg(&format_arg)
                 .arg(&id_arg))
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Used to list the revisions of a snippet")
+                .arg(&id_arg))
+        .subcommand(
+            SubCommand::with_name("revert")
+                .about("Used to revert a snippet to an older revision")
+                .arg(&id_arg)
+                .arg(Arg::with_name("rev")
+                    .help("revision to revert to")
+                    .required(true)))
         .subcommand(
             SubCommand::with_name("modify")
                 .about("Used to modify a snippet")
@@ -87,6 +161,18 @@ fn process_cli<'a>() -> ArgMatches<'a> {
                     .takes_value(true)
                     .multiple(true)
                     .conflicts_with_all(&["modifier", "tags"]))
+                .arg(&lang_arg
+                    .clone()
+                    .help("new language of the snippet, none to remove it")
+                    .conflicts_with_all(&["name", "tags"]))
+                .arg(&description_arg
+                    .clone()
+                    .help("new description of the snippet, empty to remove it")
+                    .conflicts_with_all(&["name", "tags", "lang"]))
+                .arg(&source_arg
+                    .clone()
+                    .help("new source of the snippet, empty to remove it")
+                    .conflicts_with_all(&["name", "tags", "lang", "description"]))
                 .group(ArgGroup::with_name("modifier")
                     .args(&["add", "remove"])
                     .conflicts_with("name")
@@ -100,44 +186,242 @@ fn process_cli<'a>() -> ArgMatches<'a> {
                     .short("-y")
                     .long("--yes"))
                 .arg(&id_arg))
+        .subcommand(
+            SubCommand::with_name("trash")
+                .about("Used to list deleted snippets"))
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Used to restore a deleted snippet")
+                .arg(&id_arg))
+        .subcommand(
+            SubCommand::with_name("purge")
+                .about("Used to permanently remove deleted snippets")
+                .arg(Arg::with_name("confirm")
+                    .help("don't ask for confirmation")
+                    .short("-y")
+                    .long("--yes"))
+                .arg(Arg::with_name("older-than")
+                    .help("only purge snippets deleted more than this many days ago")
+                    .long("--older-than")
+                    .takes_value(true)))
         .subcommand(
             SubCommand::with_name("list")
                 .about("Used to list snippets")
                 .setting(AppSettings::TrailingVarArg)
+                .arg(&format_arg)
                 .arg(&tag_arg)
                 .arg(&name_arg))
+        .subcommand(
+            SubCommand::with_name("pick")
+                .about("Used to interactively pick a snippet and print its content")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(Arg::with_name("copy")
+                    .help("copy the content to the clipboard instead")
+                    .long("--copy"))
+                .arg(Arg::with_name("edit")
+                    .help("open the snippet in the editor instead")
+                    .long("--edit")
+                    .conflicts_with("copy"))
+                .arg(&tag_arg
+                    .clone()
+                    .help("only pick from snippets with these tags"))
+                .arg(Arg::with_name("query")
+                    .help("initial filter")
+                    .multiple(true)))
+        .subcommand(
+            SubCommand::with_name("use")
+                .about("Used to fill in the placeholders of a snippet and print it")
+                .arg(Arg::with_name("var")
+                    .help("value of a placeholder as name=value")
+                    .long("--var")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1))
+                .arg(&id_arg))
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Used to run a snippet as a shell command")
+                .arg(Arg::with_name("confirm")
+                    .help("don't ask for confirmation")
+                    .short("-y")
+                    .long("--yes"))
+                .arg(Arg::with_name("var")
+                    .help("value of a placeholder as name=value")
+                    .long("--var")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1))
+                .arg(&id_arg)
+                .arg(Arg::with_name("args")
+                    .help("arguments passed to the snippet as $1, $2, ...")
+                    .multiple(true)
+                    .last(true)))
+        .subcommand(
+            SubCommand::with_name("search")
+                .about("Used to search the content of snippets")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(Arg::with_name("query")
+                    .help("full-text query supporting \"phrases\", prefix* and AND/OR/NOT")
+                    .required(true)
+                    .multiple(true)))
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Used to export snippets for rsm or as editor snippets")
+                .arg(Arg::with_name("format")
+                    .help("json document, dir with a file per snippet or markdown bundle")
+                    .short("-f")
+                    .long("--format")
+                    .possible_values(&["json", "dir", "markdown"])
+                    .default_value("json"))
+                .arg(Arg::with_name("to")
+                    .help("editor to write snippet files for, yasnippet and sublime need an output directory")
+                    .long("--to")
+                    .possible_values(&["vscode", "ultisnips", "yasnippet", "sublime"])
+                    .conflicts_with("format")
+                    .takes_value(true))
+                .arg(Arg::with_name("search")
+                    .help("only export snippets with a matching name, % matches anything")
+                    .short("-s")
+                    .long("--search")
+                    .takes_value(true))
+                .arg(&tag_arg
+                    .clone()
+                    .help("only export snippets with these tags"))
+                .arg(Arg::with_name("output")
+                    .help("file or directory to write to, stdout if not given")
+                    .short("-o")
+                    .long("--output")
+                    .takes_value(true)))
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Used to import snippets exported by rsm or other snippet managers")
+                .arg(Arg::with_name("format")
+                    .help("format of the input, guessed from the path if not given")
+                    .short("-f")
+                    .long("--format")
+                    .possible_values(&["json", "dir", "markdown"])
+                    .takes_value(true))
+                .arg(Arg::with_name("from")
+                    .help("snippet manager whose file or directory of files to import, cheat reads tldr-style pages")
+                    .long("--from")
+                    .possible_values(&["pet", "navi", "vscode", "cheat"])
+                    .conflicts_with("format")
+                    .requires("path")
+                    .takes_value(true))
+                .arg(Arg::with_name("on-duplicate")
+                    .help("what to do with snippets whose name is taken")
+                    .long("--on-duplicate")
+                    .possible_values(&["skip", "rename", "overwrite"])
+                    .default_value("skip"))
+                .arg(Arg::with_name("path")
+                    .help("file or directory to read, stdin if not given")))
+        .subcommand(
+            SubCommand::with_name("conflicts")
+                .about("Used to list sync conflicts or merge one")
+                .arg(Arg::with_name("id")
+                    .help("id or name of the snippet to merge")))
+        .subcommand(
+            SubCommand::with_name("login")
+                .about("Used to log in to the sync server")
+                .arg(Arg::with_name("user")
+                    .help("user name on the sync server")
+                    .short("-u")
+                    .long("--user")
+                    .takes_value(true))
+                .arg(Arg::with_name("insecure")
+                    .help("disable TLS certificate verification (local development only)")
+                    .long("--insecure")))
         .subcommand(
             SubCommand::with_name("sync")
-                .about("Used to sync data"))
-        .get_matches()
+                .about("Used to sync data")
+                .arg(Arg::with_name("insecure")
+                    .help("disable TLS certificate verification (local development only)")
+                    .long("--insecure")))
+        .get_matches_safe()
+}
+
+// numbers clap does not check
+fn parse_number(value: &str, what: &str) -> Result<i64, Error> {
+    i64::from_str(value)
+        .map_err(|_| ErrorKind::InvalidArgument(format!("invalid {}: '{}'", what, value)).into())
 }
 
-fn run() -> Result<(), Error> {
-    let app_matches = process_cli();
+// `--var name=value` arguments
+fn parse_variables(values: Option<clap::Values>) -> Result<HashMap<String, String>, Error> {
+    let mut variables = HashMap::new();
+
+    for value in values.into_iter().flatten() {
+        let mut split = value.splitn(2, '=');
+
+        match (split.next(), split.next()) {
+            (Some(name), Some(value)) if !name.is_empty() => {
+                variables.insert(name.to_string(), value.to_string());
+            },
+            _ => return Err(ErrorKind::InvalidArgument(format!("invalid variable '{}', expected name=value", value)).into()),
+        }
+    }
+
+    Ok(variables)
+}
+
+fn run(app_matches: &ArgMatches) -> Result<(), Error> {
+
+    // the display and sync settings are adjusted to the environment and flags
+    let mut config = config::load(app_matches.value_of("config"), app_matches.value_of("db"))
+        .context("failed to load configuration")?;
+
+    // escape codes end up in files and pipes unless colors were asked for
+    if env::var_os("RSM_COLOR").is_none() && !term::stdout_is_a_tty() {
+        config.display.color = false;
+    }
 
     match app_matches.subcommand() {
         ("add", Some(sub_matches)) => {
             let name = sub_matches.values_of("name").unwrap().collect::<Vec<&str>>().as_slice().join(" ");
             let tags = sub_matches.values_of("tags").map(|x| x.collect::<Vec<&str>>());
+            let language = sub_matches.value_of("lang").and_then(commands::parse_language);
+            let description = sub_matches.value_of("description").and_then(commands::parse_optional);
+            let source = sub_matches.value_of("source").and_then(commands::parse_optional);
 
-            commands::add_snippet(name, tags)
+            commands::add_snippet(&config, name, tags, language, description, source)
         },
         ("show", Some(sub_matches)) => {
-            let id_str = sub_matches.value_of("id").unwrap();
-            let snippet_id = i64::from_str(id_str)
-                .context("failed to parse snippet id")?;
+            let id = sub_matches.value_of("id").unwrap();
+
+            let revision = match sub_matches.value_of("rev") {
+                Some(rev_str) => Some(parse_number(rev_str, "revision")?),
+                None => None,
+            };
 
-            commands::show_snippet(snippet_id)
+            let format = Format::from_str(sub_matches.value_of("format").unwrap())?;
+
+            commands::show_snippet(&config, id, revision, format)
+        },
+        ("history", Some(sub_matches)) => {
+            let id = sub_matches.value_of("id").unwrap();
+
+            commands::show_history(&config, id)
+        },
+        ("revert", Some(sub_matches)) => {
+            let id = sub_matches.value_of("id").unwrap();
+            let rev_str = sub_matches.value_of("rev").unwrap();
+            let revision = parse_number(rev_str, "revision")?;
+
+            commands::revert_snippet(&config, id, revision)
         },
         ("modify", Some(sub_matches)) => {
             let name = sub_matches.values_of("name").map(|x| x.collect::<Vec<&str>>().as_slice().join(" "));
             let tags = sub_matches.values_of("tags").map(|x| x.collect::<Vec<&str>>());
-            let id_str = sub_matches.value_of("id").unwrap();
-            let snippet_id = i64::from_str(id_str)
-                .context("failed to parse snippet id")?;
+            let id = sub_matches.value_of("id").unwrap();
 
             let op = if let Some(x) = name {
                 commands::ModifyOperation::Name(x)
+            } else if let Some(x) = sub_matches.value_of("lang") {
+                commands::ModifyOperation::Language(commands::parse_language(x))
+            } else if let Some(x) = sub_matches.value_of("description") {
+                commands::ModifyOperation::Description(commands::parse_optional(x))
+            } else if let Some(x) = sub_matches.value_of("source") {
+                commands::ModifyOperation::Source(commands::parse_optional(x))
             } else if sub_matches.is_present("tags") {
                 if sub_matches.is_present("add") {
                     commands::ModifyOperation::Add(tags.unwrap())
@@ -148,62 +432,205 @@ fn run() -> Result<(), Error> {
                 commands::ModifyOperation::Content
             };
 
-            commands::modify_snippet(snippet_id, op)
+            commands::modify_snippet(&config, id, op)
         },
         ("delete", Some(sub_matches)) => {
             let confirmation = sub_matches.is_present("confirm");
-            let id_str = sub_matches.value_of("id").unwrap();
-            let snippet_id = i64::from_str(id_str)
-                .context("failed to parse snippet id")?;
+            let id = sub_matches.value_of("id").unwrap();
+
+            commands::delete_snippet(&config, id, confirmation)
+        },
+        ("trash", Some(_)) => {
+            commands::list_trash(&config)
+        },
+        ("restore", Some(sub_matches)) => {
+            let id = sub_matches.value_of("id").unwrap();
+
+            commands::restore_snippet(&config, id)
+        },
+        ("purge", Some(sub_matches)) => {
+            let confirmation = sub_matches.is_present("confirm");
+            let older_than = match sub_matches.value_of("older-than") {
+                Some(days_str) => Some(parse_number(days_str, "number of days")?),
+                None => None,
+            };
 
-            commands::delete_snippet(snippet_id, confirmation)
+            commands::purge_snippets(&config, older_than, confirmation)
         },
         ("list", Some(sub_matches)) => {
             let name = sub_matches.values_of("name").map(|x| x.collect::<Vec<&str>>().as_slice().join(" "));
             let tags = sub_matches.values_of("tags").map(|x| x.collect::<Vec<&str>>());
+            let format = Format::from_str(sub_matches.value_of("format").unwrap())?;
+
+            commands::list_snippets(&config, name, tags, format)
+        },
+        ("pick", Some(sub_matches)) => {
+            let query = sub_matches.values_of("query").map_or(String::new(), |x| x.collect::<Vec<&str>>().as_slice().join(" "));
+            let tags = sub_matches.values_of("tags").map(|x| x.collect::<Vec<&str>>());
+
+            let action = if sub_matches.is_present("copy") {
+                picker::Action::Copy
+            } else if sub_matches.is_present("edit") {
+                picker::Action::Edit
+            } else {
+                picker::Action::Print
+            };
+
+            commands::pick_snippet(&config, query, tags, action)
+        },
+        ("use", Some(sub_matches)) => {
+            let id = sub_matches.value_of("id").unwrap();
+            let values = parse_variables(sub_matches.values_of("var"))?;
+
+            commands::use_snippet(&config, id, values)
+        },
+        ("run", Some(sub_matches)) => {
+            let confirmation = sub_matches.is_present("confirm");
+            let id = sub_matches.value_of("id").unwrap();
+            let args = sub_matches.values_of("args").map_or(Vec::new(), |x| x.collect::<Vec<&str>>());
+            let values = parse_variables(sub_matches.values_of("var"))?;
+
+            let status = commands::run_snippet(&config, id, args, values, confirmation)?;
+
+            // the snippet decides how rsm exits
+            ::std::process::exit(status)
+        },
+        ("search", Some(sub_matches)) => {
+            let query = sub_matches.values_of("query").unwrap().collect::<Vec<&str>>().as_slice().join(" ");
+
+            commands::search_snippets(&config, query)
+        },
+        ("export", Some(sub_matches)) => {
+            let name = sub_matches.value_of("search").map(|x| x.to_string());
+            let tags = sub_matches.values_of("tags").map(|x| x.collect::<Vec<&str>>());
+
+            match sub_matches.value_of("to") {
+                Some(editor) => {
+                    let editor = exchange::Editor::from_str(editor)?;
+
+                    commands::export_editor_snippets(&config, editor, sub_matches.value_of("output"), name, tags)
+                },
+                None => {
+                    let format = exchange::Format::from_str(sub_matches.value_of("format").unwrap())?;
+
+                    commands::export_snippets(&config, format, sub_matches.value_of("output"), name, tags)
+                },
+            }
+        },
+        ("import", Some(sub_matches)) => {
+            let format = match sub_matches.value_of("format") {
+                Some(format) => Some(exchange::Format::from_str(format)?),
+                None => None,
+            };
+
+            let duplicates = match sub_matches.value_of("on-duplicate").unwrap() {
+                "rename" => Duplicates::Rename,
+                "overwrite" => Duplicates::Overwrite,
+                _ => Duplicates::Skip,
+            };
+
+            match sub_matches.value_of("from") {
+                Some(source) => {
+                    let source = exchange::Source::from_str(source)?;
 
-            commands::list_snippets(name, tags)
+                    commands::import_foreign_snippets(&config, source, sub_matches.value_of("path").unwrap(), duplicates)
+                },
+                None => commands::import_snippets(&config, format, sub_matches.value_of("path"), duplicates),
+            }
+        },
+        ("conflicts", Some(sub_matches)) => {
+            match sub_matches.value_of("id") {
+                Some(id) => commands::merge_conflict(&config, id),
+                None => commands::list_conflicts(&config),
+            }
         },
         #[cfg(feature = "sync")]
-        ("sync", Some(_)) => {
-            commands::sync_data()
+        ("login", Some(sub_matches)) => {
+            config.sync.insecure = sub_matches.is_present("insecure");
+
+            commands::login(&config, sub_matches.value_of("user"))
+        },
+        #[cfg(not(feature = "sync"))]
+        ("login", Some(_)) => {
+            Err(ErrorKind::SyncingNotEnabled.into())
+        },
+        #[cfg(feature = "sync")]
+        ("sync", Some(sub_matches)) => {
+            config.sync.insecure = sub_matches.is_present("insecure");
+
+            commands::sync_data(&config)
         },
         #[cfg(not(feature = "sync"))]
         ("sync", Some(_)) => {
-            bail!(error::SyncingNotEnabled)
+            Err(ErrorKind::SyncingNotEnabled.into())
         },
         _ => panic!("unexpected error"),
     }
 }
 
 fn main() {
-    // failure crate boilerplate
-    if let Err(e) = run() {
-        use std::io::Write;
-        let mut stderr = std::io::stderr();
-        let got_logger = log_enabled!(log::Level::Error);
+    let app_matches = match process_cli() {
+        Ok(x) => x,
+        // help and version are not errors
+        Err(ref e) if !e.use_stderr() => e.exit(),
+        // without parsed arguments the error format is unknown
+        Err(e) => exit_with_error(ErrorKind::InvalidArgument(e.message).into(), false),
+    };
+
+    if let Err(e) = run(&app_matches) {
+        exit_with_error(e, app_matches.value_of("error-format") == Some("json"));
+    }
+}
+
+fn exit_with_error(e: Error, json: bool) -> ! {
+    if json {
+        print_json_error(&e);
+    } else {
+        print_error(&e);
+    }
+
+    ::std::process::exit(error::exit_code(&e));
+}
+
+// a single line like {"causes":["..."],"exit_code":10,"kind":"unknown_snippet_id","message":"..."}
+fn print_json_error(e: &Error) {
+    let kind = error::kind(e);
+
+    let value = json!({
+        "kind": kind.as_ref().map(|x| x.name()),
+        "exit_code": error::exit_code(e),
+        "message": e.to_string(),
+        "causes": e.iter_causes().map(|x| x.to_string()).collect::<Vec<String>>(),
+    });
+
+    eprintln!("{}", value);
+}
+
+// failure crate boilerplate
+fn print_error(e: &Error) {
+    use std::io::Write;
+    let mut stderr = std::io::stderr();
+    let got_logger = log_enabled!(log::Level::Error);
 
-        let mut fail: &Fail = e.as_fail();
+    let mut fail: &Fail = e.as_fail();
+    if got_logger {
+        error!("{}", fail);
+    } else {
+        writeln!(&mut stderr, "{}", fail).ok();
+    }
+
+    while let Some(cause) = fail.cause() {
         if got_logger {
-            error!("{}", fail);
+            error!("caused by: {}", cause);
         } else {
-            writeln!(&mut stderr, "{}", fail).ok();
+            writeln!(&mut stderr, "caused by: {}", cause).ok();
         }
 
-        while let Some(cause) = fail.cause() {
-            if got_logger {
-                error!("caused by: {}", cause);
-            } else {
-                writeln!(&mut stderr, "caused by: {}", cause).ok();
-            }
-
-            if let Some(bt) = cause.backtrace() {
-                error!("backtrace: {}", bt)
-            }
-            fail = cause;
+        if let Some(bt) = cause.backtrace() {
+            error!("backtrace: {}", bt)
         }
-
-        stderr.flush().ok();
-        ::std::process::exit(1);
+        fail = cause;
     }
+
+    stderr.flush().ok();
 }
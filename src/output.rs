@@ -0,0 +1,129 @@
+use std::io::prelude::*;
+use std::io;
+use std::str::FromStr;
+use failure::*;
+use serde_json;
+use rsm::snippet::Snippet;
+use rsm::error::ErrorKind;
+
+// output formats of commands printing snippets, `text` is meant for humans
+// and may change, the others keep their field names stable for scripts
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Jsonl,
+    Tsv,
+    Csv,
+}
+
+pub static FORMATS: &[&str] = &["text", "json", "jsonl", "tsv", "csv"];
+
+// columns of the tsv and csv formats, the metadata after the content and
+// both timestamps last
+static COLUMNS: &[&str] = &["id", "name", "tags", "content", "language", "description", "source",
+                            "created_at", "updated_at"];
+
+impl FromStr for Format {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Format, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "tsv" => Ok(Format::Tsv),
+            "csv" => Ok(Format::Csv),
+            _ => Err(ErrorKind::InvalidArgument(format!("invalid format: '{}'", s))),
+        }
+    }
+}
+
+// print snippets in one of the machine-readable formats, `json` prints a
+// single array and every other format one snippet per line
+pub fn print_snippets(format: Format, snippets: &[Snippet]) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, snippets)
+                .context("failed to serialize snippets")?;
+            writeln!(writer)
+                .context("failed to write to stdout")?;
+        },
+        Format::Jsonl => {
+            for snippet in snippets {
+                serde_json::to_writer(&mut writer, snippet)
+                    .context("failed to serialize snippet")?;
+                writeln!(writer)
+                    .context("failed to write to stdout")?;
+            }
+        },
+        Format::Tsv | Format::Csv => {
+            let escape = if format == Format::Tsv { escape_tsv } else { escape_csv };
+
+            writeln!(writer, "{}", join_row(format, COLUMNS.iter().map(|x| x.to_string()).collect()))
+                .context("failed to write to stdout")?;
+
+            for snippet in snippets {
+                let row = vec![
+                    snippet.id.to_string(),
+                    escape(snippet.name.as_str()),
+                    escape(snippet.tags.as_slice().join(",").as_str()),
+                    escape(snippet.content.as_str()),
+                    escape(snippet.language.as_ref().map_or("", |x| x.as_str())),
+                    escape(snippet.description.as_ref().map_or("", |x| x.as_str())),
+                    escape(snippet.source.as_ref().map_or("", |x| x.as_str())),
+                    snippet.created_at.to_string(),
+                    snippet.updated_at.to_string(),
+                ];
+
+                writeln!(writer, "{}", join_row(format, row))
+                    .context("failed to write to stdout")?;
+            }
+        },
+        Format::Text => bail!("text output is printed by the commands"),
+    }
+
+    Ok(())
+}
+
+// a single snippet is printed as an object instead of an array in json
+pub fn print_snippet(format: Format, snippet: &Snippet) -> Result<(), Error> {
+    if format != Format::Json {
+        return print_snippets(format, std::slice::from_ref(snippet));
+    }
+
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+
+    serde_json::to_writer_pretty(&mut writer, snippet)
+        .context("failed to serialize snippet")?;
+    writeln!(writer)
+        .context("failed to write to stdout")?;
+
+    Ok(())
+}
+
+fn join_row(format: Format, row: Vec<String>) -> String {
+    row.as_slice().join(if format == Format::Tsv { "\t" } else { "," })
+}
+
+// tabs and line breaks would split the row, so they are written as escape
+// sequences like postgres and mysql do
+fn escape_tsv(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+// quote fields as described in RFC 4180
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
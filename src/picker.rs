@@ -0,0 +1,227 @@
+use std::io::prelude::*;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::cmp;
+use failure::*;
+use termion;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+use termion::{clear, cursor, style};
+use rsm::snippet::Snippet;
+use rsm::fuzzy;
+use term;
+
+// what happens with the picked snippet
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Print,
+    Copy,
+    Edit,
+}
+
+// matches in names and tags rank above matches in the content
+const NAME_BONUS: i64 = 20;
+const TAG_BONUS: i64 = 10;
+
+// prompt and separator lines
+const CHROME_LINES: usize = 2;
+
+struct Picker {
+    snippets: Vec<Snippet>,
+    query: String,
+    // indices into `snippets`, best match first
+    matches: Vec<usize>,
+    selected: usize,
+    // first visible row of the list
+    offset: usize,
+    color: bool,
+    // the terminal drawn on
+    fd: RawFd,
+}
+
+// Let the user choose a snippet. The picker is drawn on the controlling
+// terminal so stdout can be piped somewhere else. Enter chooses `action`,
+// Ctrl-Y and Ctrl-E copy and edit instead. None if the picker was closed.
+pub fn pick(snippets: Vec<Snippet>, query: String, action: Action, color: bool) -> Result<Option<(Snippet, Action)>, Error> {
+    let tty = termion::get_tty()
+        .context("failed to open terminal")?;
+    let fd = tty.as_raw_fd();
+    let keys = tty.try_clone()
+        .context("failed to open terminal")?
+        .keys();
+
+    // both are restored when the screen is dropped
+    let raw_tty = tty.into_raw_mode()
+        .context("failed to enable raw mode")?;
+    let mut screen = AlternateScreen::from(raw_tty);
+
+    let mut picker = Picker {
+        snippets: snippets,
+        query: query,
+        matches: Vec::new(),
+        selected: 0,
+        offset: 0,
+        color: color,
+        fd: fd,
+    };
+
+    picker.filter();
+    picker.draw(&mut screen)?;
+
+    for key in keys {
+        let key = key.context("failed to read key")?;
+        let page = list_height(fd)?;
+
+        match key {
+            Key::Char('\n') => return Ok(picker.take_selected(action)),
+            Key::Ctrl('y') => return Ok(picker.take_selected(Action::Copy)),
+            Key::Ctrl('e') => return Ok(picker.take_selected(Action::Edit)),
+            Key::Esc | Key::Ctrl('c') | Key::Ctrl('g') => return Ok(None),
+            Key::Up | Key::Ctrl('p') => picker.move_selection(-1),
+            Key::Down | Key::Ctrl('n') => picker.move_selection(1),
+            Key::PageUp => picker.move_selection(-(page as isize)),
+            Key::PageDown => picker.move_selection(page as isize),
+            Key::Backspace => {
+                picker.query.pop();
+                picker.filter();
+            },
+            Key::Ctrl('u') => {
+                picker.query.clear();
+                picker.filter();
+            },
+            Key::Char(c) if !c.is_control() => {
+                picker.query.push(c);
+                picker.filter();
+            },
+            _ => (),
+        }
+
+        picker.draw(&mut screen)?;
+    }
+
+    Ok(None)
+}
+
+impl Picker {
+    fn filter(&mut self) {
+        let mut matches = self.snippets.iter()
+            .enumerate()
+            .filter_map(|(i, x)| score(self.query.as_str(), x).map(|score| (score, i)))
+            .collect::<Vec<(i64, usize)>>();
+
+        // the sort is stable, equal matches keep the listing order
+        matches.sort_by_key(|x| cmp::Reverse(x.0));
+
+        self.matches = matches.into_iter().map(|x| x.1).collect();
+        self.selected = 0;
+        self.offset = 0;
+    }
+
+    fn move_selection(&mut self, rows: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+
+        let last = self.matches.len() as isize - 1;
+        self.selected = cmp::min(cmp::max(self.selected as isize + rows, 0), last) as usize;
+    }
+
+    fn take_selected(&mut self, action: Action) -> Option<(Snippet, Action)> {
+        match self.matches.get(self.selected) {
+            Some(&i) => Some((self.snippets.swap_remove(i), action)),
+            None => None,
+        }
+    }
+
+    fn draw<W: Write>(&mut self, screen: &mut W) -> Result<(), Error> {
+        let (width, height) = term::size(self.fd)?;
+        let list_rows = list_height(self.fd)?;
+
+        // keep the selection in view
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + list_rows {
+            self.offset = self.selected + 1 - list_rows;
+        }
+
+        write!(screen, "{}{}", clear::All, cursor::Goto(1, 1))
+            .context("failed to draw picker")?;
+
+        for (row, &i) in self.matches.iter().enumerate().skip(self.offset).take(list_rows) {
+            let snippet = &self.snippets[i];
+
+            let mut line = format!("{:>4} {}", snippet.id, snippet.name);
+            if !snippet.tags.is_empty() {
+                line.push_str(format!(" [{}]", snippet.tags.as_slice().join(", ")).as_str());
+            }
+
+            let line = truncate(line.as_str(), width.saturating_sub(2));
+            let line_row = (row - self.offset + 1) as u16;
+
+            let marker = if row == self.selected { ">" } else { " " };
+            let highlight = if row == self.selected && self.color {
+                style::Invert.to_string()
+            } else {
+                String::new()
+            };
+
+            write!(screen, "{}{} {}{}{}", cursor::Goto(1, line_row), marker, highlight, line, style::Reset)
+                .context("failed to draw picker")?;
+        }
+
+        // preview of the selected snippet below the list
+        let separator_row = list_rows + 1;
+        let status = format!(" {}/{} ", self.matches.len(), self.snippets.len());
+        let separator = format!("{}{}", status, "-".repeat(width.saturating_sub(status.len())));
+
+        write!(screen, "{}{}", cursor::Goto(1, separator_row as u16), truncate(separator.as_str(), width))
+            .context("failed to draw picker")?;
+
+        if let Some(&i) = self.matches.get(self.selected) {
+            let preview_rows = height.saturating_sub(separator_row + 1);
+            let content = self.snippets[i].content.replace('\t', "    ");
+
+            for (row, line) in content.lines().take(preview_rows).enumerate() {
+                write!(screen, "{}{}", cursor::Goto(1, (separator_row + row + 1) as u16), truncate(line, width))
+                    .context("failed to draw picker")?;
+            }
+        }
+
+        // the prompt goes last so the cursor stays behind the query
+        let prompt = truncate(format!("> {}", self.query).as_str(), width);
+        write!(screen, "{}{}", cursor::Goto(1, height as u16), prompt)
+            .context("failed to draw picker")?;
+
+        screen.flush()
+            .context("failed to draw picker")?;
+
+        Ok(())
+    }
+}
+
+// rows left for the list, about half of the terminal
+fn list_height(fd: RawFd) -> Result<usize, Error> {
+    let (_, height) = term::size(fd)?;
+
+    Ok(cmp::max(1, height.saturating_sub(CHROME_LINES) / 2))
+}
+
+// the best of the name, tag and content matches
+fn score(query: &str, snippet: &Snippet) -> Option<i64> {
+    let scores = vec![
+        fuzzy::score(query, snippet.name.as_str()).map(|x| x + NAME_BONUS),
+        fuzzy::score(query, snippet.tags.as_slice().join(" ").as_str()).map(|x| x + TAG_BONUS),
+        fuzzy::score(query, snippet.content.as_str()),
+    ];
+
+    scores.into_iter().flatten().max()
+}
+
+// cut a line to the terminal width, control characters would mess up the screen
+fn truncate(line: &str, width: usize) -> String {
+    line.chars()
+        .filter(|x| !x.is_control())
+        .take(width)
+        .collect()
+}
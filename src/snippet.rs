@@ -1,21 +1,122 @@
 use std::fmt;
+use std::str::FromStr;
 use ansi_term;
+use error::ErrorKind;
+use util;
 
+// field names are part of the machine-readable output of the cli
+#[derive(Clone, Debug, Serialize)]
 pub struct Snippet {
     pub id: i64,
     pub name: String,
     pub tags: Vec<String>,
-    pub content: String
+    pub content: String,
+    // name of a `language::Language` or whatever the user set
+    pub language: Option<String>,
+    // what the snippet does, for people and the full-text search
+    pub description: Option<String>,
+    // where the snippet came from, usually a URL
+    pub source: Option<String>,
+    pub created_at: i64,
+    // last change of anything but the tags
+    pub updated_at: i64,
 }
 
+// the alternate form `{:#}` prints the snippet without any styling
 impl fmt::Display for Snippet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {}\n", ansi_term::Style::new().bold().paint("Name:"), self.name)?;
+        let style = if f.alternate() {
+            ansi_term::Style::new()
+        } else {
+            ansi_term::Style::new().bold()
+        };
+
+        write!(f, "{} {}\n", style.paint("Name:"), self.name)?;
+
+        if let Some(ref description) = self.description {
+            write!(f, "{} {}\n", style.paint("Description:"), description)?;
+        }
 
         if !self.tags.is_empty() {
-            write!(f, "{} {}\n", ansi_term::Style::new().bold().paint("Tags:"), self.tags.as_slice().join(", "))?;
+            write!(f, "{} {}\n", style.paint("Tags:"), self.tags.as_slice().join(", "))?;
+        }
+
+        if let Some(ref language) = self.language {
+            write!(f, "{} {}\n", style.paint("Language:"), language)?;
+        }
+
+        if let Some(ref source) = self.source {
+            write!(f, "{} {}\n", style.paint("Source:"), source)?;
         }
 
+        write!(f, "{} {}\n", style.paint("Created:"), util::format_time(self.created_at))?;
+
         write!(f, "\n{}", self.content)
     }
 }
+
+// markers around matched terms in full-text search excerpts
+pub static MATCH_START: &str = "\u{2}";
+pub static MATCH_END: &str = "\u{3}";
+
+pub struct SearchMatch {
+    pub snippet: Snippet,
+    pub excerpt: String,
+}
+
+#[derive(Clone, Copy)]
+pub enum RevisionField {
+    Name,
+    Content,
+}
+
+impl RevisionField {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            RevisionField::Name => "name",
+            RevisionField::Content => "content",
+        }
+    }
+}
+
+impl FromStr for RevisionField {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<RevisionField, Self::Err> {
+        match s {
+            "name" => Ok(RevisionField::Name),
+            "content" => Ok(RevisionField::Content),
+            _ => Err(ErrorKind::UnknownRevisionField),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Revision {
+    pub revision: i64,
+    pub field: RevisionField,
+    pub old_value: String,
+    pub new_value: String,
+    pub created: i64,
+}
+
+impl Revision {
+    // short one line description of the change
+    pub fn summary(&self) -> String {
+        match self.field {
+            RevisionField::Name => format!("renamed '{}' to '{}'", self.old_value, self.new_value),
+            RevisionField::Content => format!("changed content ({} to {} lines)",
+                                              self.old_value.lines().count(),
+                                              self.new_value.lines().count()),
+        }
+    }
+}
+
+pub struct Conflict {
+    pub snippet_id: i64,
+    pub name: String,
+    pub content: String,
+    pub remote_name: String,
+    pub remote_content: String,
+    pub created: i64,
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use failure::*;
+use snippet::{Snippet, SearchMatch, Revision, RevisionField, Conflict};
+use error::ErrorKind;
+#[cfg(feature = "sync")]
+use config::ConflictStrategy;
+#[cfg(feature = "sync")]
+use sync::SyncData;
+
+mod sqlite;
+
+pub use self::sqlite::SqliteStorage;
+
+// Storage that only lives as long as the value, for tests and throwaway
+// stores. It is an SQLite database in memory, so it behaves exactly like a
+// database file.
+pub type MemoryStorage = SqliteStorage;
+
+// Everything `SnippetStore` needs to persist snippets. Deleted snippets stay
+// in the storage until they are purged and keep their names taken.
+pub trait Storage {
+    // run `f` so that either all or none of its changes are kept. Transactions
+    // may be nested
+    fn transaction<T, F>(&self, f: F) -> Result<T, Error>
+        where F: FnOnce(&Self) -> Result<T, Error>;
+
+    // returns the id of the new snippet
+    fn save_snippet(&self, name: String, content: String, tags: Option<Vec<&str>>) -> Result<i64, Error>;
+
+    fn get_snippet(&self, snippet_id: i64) -> Result<Snippet, Error>;
+
+    // the snippet not in the trash with the id or else the name `query`
+    fn find_snippet_id(&self, query: &str) -> Result<Option<i64>, Error>;
+
+    // ids and names of the snippets not in the trash, without loading the rest
+    fn get_snippet_names(&self) -> Result<Vec<(i64, String)>, Error>;
+
+    // snippets whose name is LIKE `name` or that have a tag LIKE all of
+    // `tags`, every snippet without a filter
+    fn search_snippets(&self, name: Option<String>, tags: Option<Vec<&str>>) -> Result<Vec<Snippet>, Error>;
+
+    // best matches first, excerpts mark matched terms with
+    // `snippet::MATCH_START` and `snippet::MATCH_END`
+    fn full_text_search(&self, query: &str) -> Result<Vec<SearchMatch>, Error>;
+
+    fn rename_snippet(&self, snippet_id: i64, name: String) -> Result<(), Error>;
+
+    // `None` clears the language
+    fn set_snippet_language(&self, snippet_id: i64, language: Option<&str>) -> Result<(), Error>;
+
+    // `None` clears the description
+    fn set_snippet_description(&self, snippet_id: i64, description: Option<&str>) -> Result<(), Error>;
+
+    // `None` clears the source
+    fn set_snippet_source(&self, snippet_id: i64, source: Option<&str>) -> Result<(), Error>;
+
+    // restores the creation time of an imported snippet
+    fn set_snippet_created_at(&self, snippet_id: i64, created_at: i64) -> Result<(), Error>;
+
+    fn change_snippet_content(&self, snippet_id: i64, content: String) -> Result<(), Error>;
+
+    // oldest revision first
+    fn get_revisions(&self, snippet_id: i64) -> Result<Vec<Revision>, Error>;
+
+    fn get_snippet_revision(&self, snippet_id: i64, revision: i64) -> Result<Snippet, Error> {
+        let mut snippet = self.get_snippet(snippet_id)
+            .context("failed to load snippet")?;
+
+        let revisions = self.get_revisions(snippet_id)
+            .context("failed to load snippet revisions")?;
+
+        // revision 0 is the snippet as it was created
+        if revision < 0 || revision > revisions.len() as i64 {
+            return Err(ErrorKind::UnknownRevision.into());
+        }
+
+        // undo every change made after the requested revision, newest first
+        for rev in revisions.iter().rev().take_while(|x| x.revision > revision) {
+            match rev.field {
+                RevisionField::Name => snippet.name = rev.old_value.clone(),
+                RevisionField::Content => snippet.content = rev.old_value.clone(),
+            }
+        }
+
+        Ok(snippet)
+    }
+
+    fn save_tags(&self, snippet_id: i64, tags: Vec<&str>) -> Result<(), Error>;
+
+    fn remove_tags_by_name(&self, snippet_id: i64, tags: Vec<&str>) -> Result<(), Error>;
+
+    fn delete_snippet(&self, snippet_id: i64) -> Result<(), Error>;
+
+    // deleted snippets with their deletion time, most recent first
+    fn get_deleted_snippets(&self) -> Result<Vec<(Snippet, i64)>, Error>;
+
+    fn restore_snippet(&self, snippet_id: i64) -> Result<(), Error>;
+
+    // returns the number of purged snippets and of those held back until
+    // their deletion is synced
+    fn purge_snippets(&self, older_than: Option<i64>) -> Result<(usize, usize), Error>;
+
+    fn get_conflicts(&self) -> Result<Vec<Conflict>, Error>;
+
+    fn resolve_conflict(&self, conflict: &Conflict, name: String, content: String) -> Result<(), Error>;
+
+    // last used values of the template placeholders of a snippet
+    fn get_variables(&self, snippet_id: i64) -> Result<HashMap<String, String>, Error>;
+
+    // replaces the values of the given variables only
+    fn save_variables(&self, snippet_id: i64, variables: &HashMap<String, String>) -> Result<(), Error>;
+
+    fn find_metadata_value(&self, key: &str) -> Result<Option<String>, Error>;
+
+    fn get_metadata_value(&self, key: &str) -> Result<String, Error> {
+        match self.find_metadata_value(key)? {
+            Some(value) => Ok(value),
+            None => Err(ErrorKind::UnknownMetaKey.into()),
+        }
+    }
+
+    fn set_metadata_value(&self, key: &str, value: &str) -> Result<(), Error>;
+
+    // rows changed after `last_synced`
+    #[cfg(feature = "sync")]
+    fn get_sync_data(&self, last_synced: i64) -> Result<SyncData, Error>;
+
+    // merge the changes of the sync server, returns the number of conflicts
+    #[cfg(feature = "sync")]
+    fn apply_sync_data(&self, data: SyncData, last_synced: i64, strategy: ConflictStrategy) -> Result<usize, Error>;
+}
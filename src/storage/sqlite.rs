@@ -0,0 +1,153 @@
+use std::path::Path;
+use std::collections::HashMap;
+use sqlite::Connection;
+use failure::*;
+use snippet::{Snippet, SearchMatch, Revision, Conflict};
+use storage::Storage;
+use db;
+#[cfg(feature = "sync")]
+use config::ConflictStrategy;
+#[cfg(feature = "sync")]
+use sync::SyncData;
+
+// storage in a SQLite database file, see `db` for the queries
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    // open the database, creating and migrating it as needed
+    pub fn open(db_path: &Path) -> Result<SqliteStorage, Error> {
+        let conn = db::connect(db_path)?;
+
+        Ok(SqliteStorage { conn: conn })
+    }
+
+    // a database in memory, see `MemoryStorage`
+    pub fn in_memory() -> Result<SqliteStorage, Error> {
+        let conn = db::connect_in_memory()?;
+
+        Ok(SqliteStorage { conn: conn })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn transaction<T, F>(&self, f: F) -> Result<T, Error>
+        where F: FnOnce(&SqliteStorage) -> Result<T, Error> {
+        db::transaction(&self.conn, |_| f(self))
+    }
+
+    fn save_snippet(&self, name: String, content: String, tags: Option<Vec<&str>>) -> Result<i64, Error> {
+        db::save_snippet(&self.conn, name, content, tags)
+    }
+
+    fn get_snippet(&self, snippet_id: i64) -> Result<Snippet, Error> {
+        db::get_snippet(&self.conn, snippet_id)
+    }
+
+    fn find_snippet_id(&self, query: &str) -> Result<Option<i64>, Error> {
+        db::find_snippet_id(&self.conn, query)
+    }
+
+    fn get_snippet_names(&self) -> Result<Vec<(i64, String)>, Error> {
+        db::get_snippet_names(&self.conn)
+    }
+
+    fn search_snippets(&self, name: Option<String>, tags: Option<Vec<&str>>) -> Result<Vec<Snippet>, Error> {
+        db::search_snippets(&self.conn, name, tags)
+    }
+
+    fn full_text_search(&self, query: &str) -> Result<Vec<SearchMatch>, Error> {
+        db::full_text_search(&self.conn, query)
+    }
+
+    fn rename_snippet(&self, snippet_id: i64, name: String) -> Result<(), Error> {
+        db::rename_snippet(&self.conn, snippet_id, name)
+    }
+
+    fn set_snippet_language(&self, snippet_id: i64, language: Option<&str>) -> Result<(), Error> {
+        db::set_snippet_language(&self.conn, snippet_id, language)
+    }
+
+    fn set_snippet_description(&self, snippet_id: i64, description: Option<&str>) -> Result<(), Error> {
+        db::set_snippet_description(&self.conn, snippet_id, description)
+    }
+
+    fn set_snippet_source(&self, snippet_id: i64, source: Option<&str>) -> Result<(), Error> {
+        db::set_snippet_source(&self.conn, snippet_id, source)
+    }
+
+    fn set_snippet_created_at(&self, snippet_id: i64, created_at: i64) -> Result<(), Error> {
+        db::set_snippet_created_at(&self.conn, snippet_id, created_at)
+    }
+
+    fn change_snippet_content(&self, snippet_id: i64, content: String) -> Result<(), Error> {
+        db::change_snippet_content(&self.conn, snippet_id, content)
+    }
+
+    fn get_revisions(&self, snippet_id: i64) -> Result<Vec<Revision>, Error> {
+        db::get_revisions(&self.conn, snippet_id)
+    }
+
+    fn save_tags(&self, snippet_id: i64, tags: Vec<&str>) -> Result<(), Error> {
+        db::save_tags(&self.conn, snippet_id, tags)
+    }
+
+    fn remove_tags_by_name(&self, snippet_id: i64, tags: Vec<&str>) -> Result<(), Error> {
+        db::remove_tags_by_name(&self.conn, snippet_id, tags)
+    }
+
+    fn delete_snippet(&self, snippet_id: i64) -> Result<(), Error> {
+        db::delete_snippet(&self.conn, snippet_id)
+    }
+
+    fn get_deleted_snippets(&self) -> Result<Vec<(Snippet, i64)>, Error> {
+        db::get_deleted_snippets(&self.conn)
+    }
+
+    fn restore_snippet(&self, snippet_id: i64) -> Result<(), Error> {
+        db::restore_snippet(&self.conn, snippet_id)
+    }
+
+    fn purge_snippets(&self, older_than: Option<i64>) -> Result<(usize, usize), Error> {
+        db::purge_snippets(&self.conn, older_than)
+    }
+
+    fn get_conflicts(&self) -> Result<Vec<Conflict>, Error> {
+        db::get_conflicts(&self.conn)
+    }
+
+    fn resolve_conflict(&self, conflict: &Conflict, name: String, content: String) -> Result<(), Error> {
+        db::resolve_conflict(&self.conn, conflict, name, content)
+    }
+
+    fn get_variables(&self, snippet_id: i64) -> Result<HashMap<String, String>, Error> {
+        db::get_variables(&self.conn, snippet_id)
+    }
+
+    fn save_variables(&self, snippet_id: i64, variables: &HashMap<String, String>) -> Result<(), Error> {
+        db::save_variables(&self.conn, snippet_id, variables)
+    }
+
+    fn find_metadata_value(&self, key: &str) -> Result<Option<String>, Error> {
+        db::find_metadata_value(&self.conn, key)
+    }
+
+    fn get_metadata_value(&self, key: &str) -> Result<String, Error> {
+        db::get_metadata_value(&self.conn, key)
+    }
+
+    fn set_metadata_value(&self, key: &str, value: &str) -> Result<(), Error> {
+        db::set_metadata_value(&self.conn, key, value)
+    }
+
+    #[cfg(feature = "sync")]
+    fn get_sync_data(&self, last_synced: i64) -> Result<SyncData, Error> {
+        db::get_sync_data(&self.conn, last_synced)
+    }
+
+    #[cfg(feature = "sync")]
+    fn apply_sync_data(&self, data: SyncData, last_synced: i64, strategy: ConflictStrategy) -> Result<usize, Error> {
+        db::apply_sync_data(&self.conn, data, last_synced, strategy)
+    }
+}
@@ -0,0 +1,657 @@
+use std::path::Path;
+use std::cmp;
+use std::collections::HashMap;
+use failure::*;
+use snippet::{Snippet, SearchMatch, Revision, Conflict};
+use storage::{Storage, SqliteStorage, MemoryStorage};
+use error::{self, ErrorKind};
+use exchange::Entry;
+use fuzzy;
+use language;
+#[cfg(feature = "sync")]
+use config::SyncConfig;
+#[cfg(feature = "sync")]
+use util;
+
+// change applied to a snippet by `SnippetStore::modify`
+pub enum Modification<'a> {
+    Name(String),
+    Content(String),
+    AddTags(Vec<&'a str>),
+    RemoveTags(Vec<&'a str>),
+    // `None` clears the language
+    Language(Option<String>),
+    // `None` clears the description
+    Description(Option<String>),
+    // `None` clears the source
+    Source(Option<String>),
+}
+
+// what `SnippetStore::import` does with snippets whose name is taken
+#[derive(Clone, Copy, PartialEq)]
+pub enum Duplicates {
+    Skip,
+    // import under the name with a number appended
+    Rename,
+    // replace the content and tags of the existing snippet
+    Overwrite,
+}
+
+// outcome of importing a single snippet
+#[derive(Debug, PartialEq)]
+pub enum Imported {
+    Added(i64),
+    Renamed(i64, String),
+    Replaced(i64),
+    // the name is taken, either `DupSnippetName` or `DeletedSnippetName`
+    Skipped(ErrorKind),
+}
+
+// outcome of a sync with the server
+#[cfg(feature = "sync")]
+#[derive(Debug)]
+pub struct SyncSummary {
+    // snippets sent to the server
+    pub uploaded: usize,
+    // snippets received from the server
+    pub received: usize,
+    // snippets changed on both sides, handled by the conflict strategy
+    pub conflicts: usize,
+}
+
+// Snippets of a single user, kept in a SQLite database unless another
+// storage is given. Nothing is printed or read from the terminal.
+pub struct SnippetStore<S: Storage = SqliteStorage> {
+    storage: S,
+}
+
+impl SnippetStore {
+    // open the database, creating and migrating it as needed
+    pub fn open(db_path: &Path) -> Result<SnippetStore, Error> {
+        let storage = SqliteStorage::open(db_path)
+            .context("failed to connect to database")?;
+
+        Ok(SnippetStore::new(storage))
+    }
+}
+
+impl SnippetStore<MemoryStorage> {
+    // an empty store that is gone once dropped
+    pub fn in_memory() -> Result<SnippetStore<MemoryStorage>, Error> {
+        let storage = MemoryStorage::in_memory()
+            .context("failed to create database")?;
+
+        Ok(SnippetStore::new(storage))
+    }
+}
+
+impl<S: Storage> SnippetStore<S> {
+    pub fn new(storage: S) -> SnippetStore<S> {
+        SnippetStore { storage: storage }
+    }
+
+    // the language is detected if not given, see `language::detect`
+    pub fn add(&self, name: String, content: String, tags: Option<Vec<&str>>, language: Option<String>, description: Option<String>, source: Option<String>) -> Result<i64, Error> {
+        let language = language.or_else(|| language::detect(name.as_str(), content.as_str()).map(|x| x.name.to_string()));
+
+        self.storage.transaction(|storage| {
+            let snippet_id = storage.save_snippet(name, content, tags)
+                .context("failed to save snippet")?;
+
+            if language.is_some() {
+                storage.set_snippet_language(snippet_id, language.as_deref())
+                    .context("failed to set snippet language")?;
+            }
+
+            if description.is_some() {
+                storage.set_snippet_description(snippet_id, description.as_deref())
+                    .context("failed to set snippet description")?;
+            }
+
+            if source.is_some() {
+                storage.set_snippet_source(snippet_id, source.as_deref())
+                    .context("failed to set snippet source")?;
+            }
+
+            Ok(snippet_id)
+        })
+    }
+
+    pub fn get(&self, snippet_id: i64) -> Result<Snippet, Error> {
+        let snippet = self.storage.get_snippet(snippet_id)
+            .context("failed to load snippet")?;
+
+        Ok(snippet)
+    }
+
+    // the id of the snippet `query` refers to, see `find_snippet_id`
+    pub fn lookup(&self, query: &str) -> Result<i64, Error> {
+        // most queries are an id or a whole name
+        if let Some(snippet_id) = self.storage.find_snippet_id(query).context("failed to look up snippet")? {
+            return Ok(snippet_id);
+        }
+
+        let names = self.storage.get_snippet_names()
+            .context("failed to load snippet names")?;
+
+        find_snippet_id(query, names.iter().map(|x| (x.0, x.1.as_str())).collect())
+    }
+
+    // like `lookup` but for snippets in the trash
+    pub fn lookup_deleted(&self, query: &str) -> Result<i64, Error> {
+        let snippets = self.trash()?;
+
+        find_snippet_id(query, snippets.iter().map(|x| (x.0.id, x.0.name.as_str())).collect())
+    }
+
+    // the snippet as it was after the given revision, 0 is the original
+    pub fn get_revision(&self, snippet_id: i64, revision: i64) -> Result<Snippet, Error> {
+        let snippet = self.storage.get_snippet_revision(snippet_id, revision)
+            .context("failed to load snippet revision")?;
+
+        Ok(snippet)
+    }
+
+    pub fn history(&self, snippet_id: i64) -> Result<Vec<Revision>, Error> {
+        // make sure the snippet exists
+        self.get(snippet_id)?;
+
+        let revisions = self.storage.get_revisions(snippet_id)
+            .context("failed to load snippet revisions")?;
+
+        Ok(revisions)
+    }
+
+    // reverting is recorded as new revisions so it can be undone as well
+    pub fn revert(&self, snippet_id: i64, revision: i64) -> Result<(), Error> {
+        let snippet = self.get(snippet_id)?;
+        let old_snippet = self.get_revision(snippet_id, revision)?;
+
+        self.storage.transaction(|storage| {
+            if snippet.name != old_snippet.name {
+                storage.rename_snippet(snippet_id, old_snippet.name)
+                    .context("failed to rename snippet")?;
+            }
+
+            if snippet.content != old_snippet.content {
+                storage.change_snippet_content(snippet_id, old_snippet.content)
+                    .context("failed to change snippet content")?;
+            }
+
+            Ok(())
+        })
+    }
+
+    // snippets matching the name or all of the tags, everything without a filter
+    pub fn list(&self, name: Option<String>, tags: Option<Vec<&str>>) -> Result<Vec<Snippet>, Error> {
+        let snippets = self.storage.search_snippets(name, tags)
+            .context("failed to search snippets")?;
+
+        Ok(snippets)
+    }
+
+    // full-text search over names, contents, descriptions and sources, best
+    // matches first
+    pub fn search(&self, query: &str) -> Result<Vec<SearchMatch>, Error> {
+        let matches = self.storage.full_text_search(query)
+            .context("failed to search snippets")?;
+
+        Ok(matches)
+    }
+
+    pub fn modify(&self, snippet_id: i64, modification: Modification) -> Result<(), Error> {
+        self.get(snippet_id)?;
+
+        match modification {
+            Modification::Name(name) => {
+                self.storage.rename_snippet(snippet_id, name)
+                    .context("failed to rename snippet")?;
+            },
+            Modification::Content(content) => {
+                self.storage.change_snippet_content(snippet_id, content)
+                    .context("failed to change snippet content")?;
+            },
+            Modification::AddTags(tags) => {
+                self.storage.save_tags(snippet_id, tags)
+                    .context("failed to add tags to snippet")?;
+            },
+            Modification::RemoveTags(tags) => {
+                self.storage.remove_tags_by_name(snippet_id, tags)
+                    .context("failed to remove tags from snippet")?;
+            },
+            Modification::Language(language) => {
+                self.storage.set_snippet_language(snippet_id, language.as_deref())
+                    .context("failed to set snippet language")?;
+            },
+            Modification::Description(description) => {
+                self.storage.set_snippet_description(snippet_id, description.as_deref())
+                    .context("failed to set snippet description")?;
+            },
+            Modification::Source(source) => {
+                self.storage.set_snippet_source(snippet_id, source.as_deref())
+                    .context("failed to set snippet source")?;
+            },
+        }
+
+        Ok(())
+    }
+
+    // move a snippet to the trash
+    pub fn delete(&self, snippet_id: i64) -> Result<(), Error> {
+        self.storage.delete_snippet(snippet_id)
+            .context("failed to delete snippet")?;
+
+        Ok(())
+    }
+
+    // deleted snippets with their deletion time, most recent first
+    pub fn trash(&self) -> Result<Vec<(Snippet, i64)>, Error> {
+        let snippets = self.storage.get_deleted_snippets()
+            .context("failed to load deleted snippets")?;
+
+        Ok(snippets)
+    }
+
+    pub fn restore(&self, snippet_id: i64) -> Result<(), Error> {
+        self.storage.restore_snippet(snippet_id)
+            .context("failed to restore snippet")?;
+
+        Ok(())
+    }
+
+    // last used values of the template placeholders of a snippet
+    pub fn variables(&self, snippet_id: i64) -> Result<HashMap<String, String>, Error> {
+        let variables = self.storage.get_variables(snippet_id)
+            .context("failed to load variables")?;
+
+        Ok(variables)
+    }
+
+    pub fn remember_variables(&self, snippet_id: i64, variables: &HashMap<String, String>) -> Result<(), Error> {
+        self.storage.save_variables(snippet_id, variables)
+            .context("failed to save variables")?;
+
+        Ok(())
+    }
+
+    // the snippets `list` would show for `exchange`, in listing order
+    pub fn export(&self, name: Option<String>, tags: Option<Vec<&str>>) -> Result<Vec<Entry>, Error> {
+        let snippets = self.list(name, tags)?;
+
+        Ok(snippets.iter().map(Entry::from).collect())
+    }
+
+    // Save the entries with the outcome for each of them. Names taken by
+    // snippets in the trash are only ever renamed around, never overwritten.
+    // Nothing is imported if any entry fails.
+    pub fn import(&self, entries: Vec<Entry>, duplicates: Duplicates) -> Result<Vec<(String, Imported)>, Error> {
+        self.storage.transaction(|storage| {
+            let mut outcomes = Vec::new();
+
+            for entry in entries {
+                let name = entry.name.clone();
+                let outcome = import_entry(storage, entry, duplicates)
+                    .context(format!("failed to import snippet '{}'", name))?;
+
+                outcomes.push((name, outcome));
+            }
+
+            Ok(outcomes)
+        })
+    }
+
+    // permanently remove deleted snippets, optionally only those deleted
+    // more than `older_than` seconds ago. Returns the number of snippets removed
+    // and of those kept until their deletion is synced
+    pub fn purge(&self, older_than: Option<i64>) -> Result<(usize, usize), Error> {
+        let purged = self.storage.purge_snippets(older_than)
+            .context("failed to purge snippets")?;
+
+        Ok(purged)
+    }
+
+    pub fn conflicts(&self) -> Result<Vec<Conflict>, Error> {
+        let conflicts = self.storage.get_conflicts()
+            .context("failed to load conflicts")?;
+
+        Ok(conflicts)
+    }
+
+    pub fn conflict(&self, snippet_id: i64) -> Result<Conflict, Error> {
+        let conflict = self.conflicts()?
+            .into_iter()
+            .find(|x| x.snippet_id == snippet_id)
+            .ok_or(ErrorKind::UnknownConflict)?;
+
+        Ok(conflict)
+    }
+
+    // replace both versions of a conflicting snippet with the merged one
+    pub fn resolve_conflict(&self, conflict: &Conflict, name: String, content: String) -> Result<(), Error> {
+        self.storage.resolve_conflict(conflict, name, content)
+            .context("failed to resolve conflict")?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "sync")]
+    pub fn login(&self, config: &SyncConfig, user: &str, password: &str) -> Result<(), Error> {
+        use sync;
+
+        let credentials = sync::login(config, user, password)
+            .context("failed to log in to the sync server")?;
+
+        self.storage.set_metadata_value("sync_user", user)
+            .context("failed to save user name")?;
+        self.save_credentials(&credentials)
+            .context("failed to save credentials")?;
+
+        Ok(())
+    }
+
+    // time of the last successful sync, 0 if there was none
+    #[cfg(feature = "sync")]
+    pub fn last_synced(&self) -> Result<i64, Error> {
+        let value = self.storage.get_metadata_value("last_synced")
+            .context("failed to get last synced time")?;
+
+        let last_synced = value.parse::<i64>()
+            .context("failed to parse last synced string")?;
+
+        Ok(last_synced)
+    }
+
+    // upload local changes and apply the remote ones
+    #[cfg(feature = "sync")]
+    pub fn sync(&self, config: &SyncConfig) -> Result<SyncSummary, Error> {
+        use sync;
+        use json;
+
+        // the server is asked for changes by its own clock, local changes
+        // are picked by the local one
+        let last_synced = self.last_synced()?;
+        let last_uploaded = self.storage.get_metadata_value("last_uploaded")
+            .context("failed to get last upload time")?
+            .parse::<i64>()
+            .context("failed to parse last upload string")?;
+
+        // changes made from this second on, including renames while applying
+        // remote data, are sent again on the next sync
+        let upload_time = util::get_utc_now() - 1;
+
+        let sync_data = self.storage.get_sync_data(last_uploaded)
+            .context("failed to get sync data")?;
+        let uploaded = sync_data.0.len();
+
+        let json_data = object!{
+            "snippets" => sync_data.0,
+            "tags" => sync_data.1,
+            "snippet_tags" => sync_data.2,
+            "tombstones" => sync_data.3
+        };
+
+        let serialized_data = json::stringify(json_data);
+
+        let token = self.get_token(config)
+            .context("failed to get sync token")?;
+
+        let result = sync::sync_data(config, token.as_str(), last_synced, serialized_data.as_str());
+
+        // the server may still consider the token expired, retry once with a fresh one
+        let result = match result {
+            Err(ref e) if e.downcast_ref::<ErrorKind>() == Some(&ErrorKind::TokenExpired) => {
+                let token = self.refresh_token(config)
+                    .context("failed to get sync token")?;
+
+                sync::sync_data(config, token.as_str(), last_synced, serialized_data.as_str())
+            },
+            x => x,
+        };
+
+        let (remote_data, sync_time) = result.context("failed to sync data")?;
+        let received = remote_data.0.len();
+
+        // the sync time only moves forward if all remote changes were applied
+        let conflicts = self.storage.transaction(|storage| {
+            let conflicts = storage.apply_sync_data(remote_data, last_uploaded, config.conflict)
+                .context("failed to apply remote changes")?;
+
+            storage.set_metadata_value("last_synced", sync_time.unwrap_or(upload_time).to_string().as_str())
+                .context("failed to update sync time")?;
+            storage.set_metadata_value("last_uploaded", upload_time.to_string().as_str())
+                .context("failed to update upload time")?;
+
+            Ok(conflicts)
+        })?;
+
+        let summary = SyncSummary {
+            uploaded: uploaded,
+            received: received,
+            conflicts: conflicts,
+        };
+
+        Ok(summary)
+    }
+
+    #[cfg(feature = "sync")]
+    fn save_credentials(&self, credentials: &::sync::Credentials) -> Result<(), Error> {
+        self.storage.set_metadata_value("sync_token", credentials.token.as_str())
+            .context("failed to save token")?;
+        self.storage.set_metadata_value("sync_refresh_token", credentials.refresh_token.as_str())
+            .context("failed to save refresh token")?;
+        self.storage.set_metadata_value("sync_token_expires", credentials.expires.to_string().as_str())
+            .context("failed to save token expiry time")?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "sync")]
+    fn refresh_token(&self, config: &SyncConfig) -> Result<String, Error> {
+        use sync;
+
+        let refresh_token = self.storage.find_metadata_value("sync_refresh_token")
+            .context("failed to get refresh token")?
+            .ok_or(ErrorKind::NotLoggedIn)?;
+
+        let credentials = sync::refresh(config, refresh_token.as_str())
+            .context("failed to refresh sync token")?;
+
+        self.save_credentials(&credentials)
+            .context("failed to save credentials")?;
+
+        Ok(credentials.token)
+    }
+
+    // get a sync token that is not known to be expired
+    #[cfg(feature = "sync")]
+    fn get_token(&self, config: &SyncConfig) -> Result<String, Error> {
+        let token = self.storage.find_metadata_value("sync_token")
+            .context("failed to get sync token")?
+            .ok_or(ErrorKind::NotLoggedIn)?;
+
+        let expires = self.storage.get_metadata_value("sync_token_expires")
+            .context("failed to get token expiry time")?;
+        let expires = expires.parse::<i64>()
+            .context("failed to parse token expiry time")?;
+
+        if expires <= util::get_utc_now() {
+            return self.refresh_token(config);
+        }
+
+        Ok(token)
+    }
+}
+
+fn import_entry<S: Storage>(storage: &S, entry: Entry, duplicates: Duplicates) -> Result<Imported, Error> {
+    let e = match save_entry(storage, entry.name.clone(), &entry) {
+        Ok(snippet_id) => return Ok(Imported::Added(snippet_id)),
+        Err(e) => e,
+    };
+
+    let kind = match error::kind(&e) {
+        Some(kind @ ErrorKind::DupSnippetName) | Some(kind @ ErrorKind::DeletedSnippetName) => kind,
+        _ => return Err(e),
+    };
+
+    match (duplicates, kind) {
+        (Duplicates::Rename, _) => {
+            for n in 2.. {
+                let name = format!("{} ({})", entry.name, n);
+
+                match save_entry(storage, name.clone(), &entry) {
+                    Ok(snippet_id) => return Ok(Imported::Renamed(snippet_id, name)),
+                    Err(ref e) if error::kind(e) == Some(ErrorKind::DupSnippetName) => continue,
+                    Err(ref e) if error::kind(e) == Some(ErrorKind::DeletedSnippetName) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            unreachable!()
+        },
+        (Duplicates::Overwrite, ErrorKind::DupSnippetName) => {
+            let snippet = storage.search_snippets(None, None)?
+                .into_iter()
+                .find(|x| x.name == entry.name)
+                .ok_or(ErrorKind::UnknownSnippetId)?;
+
+            if snippet.content != entry.content {
+                storage.change_snippet_content(snippet.id, entry.content.clone())
+                    .context("failed to change snippet content")?;
+            }
+
+            let added = entry.tags.iter()
+                .filter(|x| !snippet.tags.contains(x))
+                .map(|x| x.as_str())
+                .collect::<Vec<&str>>();
+            let removed = snippet.tags.iter()
+                .filter(|x| !entry.tags.contains(x))
+                .map(|x| x.as_str())
+                .collect::<Vec<&str>>();
+
+            if !added.is_empty() {
+                storage.save_tags(snippet.id, added)
+                    .context("failed to add tags to snippet")?;
+            }
+
+            if !removed.is_empty() {
+                storage.remove_tags_by_name(snippet.id, removed)
+                    .context("failed to remove tags from snippet")?;
+            }
+
+            if entry.language.is_some() && entry.language != snippet.language {
+                storage.set_snippet_language(snippet.id, entry.language.as_deref())
+                    .context("failed to set snippet language")?;
+            }
+
+            if entry.description.is_some() && entry.description != snippet.description {
+                storage.set_snippet_description(snippet.id, entry.description.as_deref())
+                    .context("failed to set snippet description")?;
+            }
+
+            if entry.source.is_some() && entry.source != snippet.source {
+                storage.set_snippet_source(snippet.id, entry.source.as_deref())
+                    .context("failed to set snippet source")?;
+            }
+
+            Ok(Imported::Replaced(snippet.id))
+        },
+        (_, kind) => Ok(Imported::Skipped(kind)),
+    }
+}
+
+fn save_entry<S: Storage>(storage: &S, name: String, entry: &Entry) -> Result<i64, Error> {
+    let tags = if entry.tags.is_empty() {
+        None
+    } else {
+        Some(entry.tags.iter().map(|x| x.as_str()).collect())
+    };
+
+    // entries of other tools rarely know the language
+    let language = match entry.language {
+        Some(ref language) => Some(language.as_str()),
+        None => language::detect(name.as_str(), entry.content.as_str()).map(|x| x.name),
+    };
+
+    let snippet_id = storage.save_snippet(name, entry.content.clone(), tags)?;
+
+    if language.is_some() {
+        storage.set_snippet_language(snippet_id, language)
+            .context("failed to set snippet language")?;
+    }
+
+    if entry.description.is_some() {
+        storage.set_snippet_description(snippet_id, entry.description.as_deref())
+            .context("failed to set snippet description")?;
+    }
+
+    if entry.source.is_some() {
+        storage.set_snippet_source(snippet_id, entry.source.as_deref())
+            .context("failed to set snippet source")?;
+    }
+
+    if let Some(created_at) = entry.created_at {
+        storage.set_snippet_created_at(snippet_id, created_at)
+            .context("failed to set snippet creation time")?;
+    }
+
+    Ok(snippet_id)
+}
+
+// most candidates listed when a name is ambiguous
+const MAX_CANDIDATES: usize = 10;
+
+// Resolve `query` to one of the `(id, name)` candidates. It is tried as an id,
+// an exact name, a name prefix and finally a fuzzy match of the name. Prefix
+// and fuzzy matches have to be unique, nobody wants to delete a snippet
+// that was merely the best guess.
+fn find_snippet_id(query: &str, candidates: Vec<(i64, &str)>) -> Result<i64, Error> {
+    if let Ok(snippet_id) = query.parse::<i64>() {
+        if candidates.iter().any(|x| x.0 == snippet_id) {
+            return Ok(snippet_id);
+        }
+    }
+
+    if let Some(x) = candidates.iter().find(|x| x.1 == query) {
+        return Ok(x.0);
+    }
+
+    let lower_query = query.to_lowercase();
+    let prefixed = candidates.iter()
+        .filter(|x| x.1.to_lowercase().starts_with(lower_query.as_str()))
+        .cloned()
+        .collect::<Vec<(i64, &str)>>();
+
+    if !prefixed.is_empty() {
+        return unique_candidate(query, prefixed);
+    }
+
+    let mut fuzzy_matches = candidates.iter()
+        .filter_map(|x| fuzzy::score(query, x.1).map(|score| (score, *x)))
+        .collect::<Vec<(i64, (i64, &str))>>();
+
+    // best matches first in the list of candidates
+    fuzzy_matches.sort_by_key(|x| cmp::Reverse(x.0));
+
+    if !fuzzy_matches.is_empty() {
+        return unique_candidate(query, fuzzy_matches.into_iter().map(|x| x.1).collect());
+    }
+
+    Err(ErrorKind::UnknownSnippetId.into())
+}
+
+fn unique_candidate(query: &str, candidates: Vec<(i64, &str)>) -> Result<i64, Error> {
+    if candidates.len() == 1 {
+        return Ok(candidates[0].0);
+    }
+
+    let mut listed = candidates.iter()
+        .take(MAX_CANDIDATES)
+        .map(|x| format!("{} '{}'", x.0, x.1))
+        .collect::<Vec<String>>();
+
+    if candidates.len() > MAX_CANDIDATES {
+        listed.push(format!("and {} more", candidates.len() - MAX_CANDIDATES));
+    }
+
+    Err(ErrorKind::AmbiguousSnippetName(query.to_string(), listed.as_slice().join(", ")).into())
+}
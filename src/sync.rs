@@ -1,42 +1,95 @@
 use std::time::Duration;
-use std::io::{BufRead, Write};
-use std::net::{TcpStream, ToSocketAddrs};
-use native_tls::TlsConnector;
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpStream;
+use std::fs;
+use native_tls::{TlsConnector, TlsStream, Certificate};
+use sha2::{Sha256, Digest};
 use failure::*;
 use bufstream::BufStream;
 use json;
+use config::SyncConfig;
+use error::ErrorKind;
 
+// changed snippets, tags, snippet tags and purged snippets
+pub type SyncData = (Vec<SnippetRow>, Vec<TagRow>, Vec<SnippetTagRow>, Vec<TombstoneRow>);
+
+// Rows are identified by the snippet UUID and the tag name across machines.
+// The local integer ids never leave the database.
 pub struct SnippetRow {
-    pub id: i64,
+    pub uuid: String,
     pub name: String,
     pub content: String,
+    pub language: Option<String>,
+    pub description: Option<String>,
+    pub source: Option<String>,
     pub deleted: i64,
+    pub created_at: i64,
+    // increased with every change, decides conflicts instead of the clocks
+    pub version: i64,
     pub last_updated: i64,
 }
 
+impl SnippetRow {
+    // servers from before languages, descriptions, creation times and
+    // versions send none
+    fn from_json(value: &json::JsonValue) -> Result<SnippetRow, Error> {
+        let last_updated = get_i64(value, "last_updated")?;
+
+        let row = SnippetRow {
+            uuid: get_string(value, "uuid")?,
+            name: get_string(value, "name")?,
+            content: get_string(value, "content")?,
+            language: value["language"].as_str().map(|x| x.to_string()),
+            description: value["description"].as_str().map(|x| x.to_string()),
+            source: value["source"].as_str().map(|x| x.to_string()),
+            deleted: get_i64(value, "deleted")?,
+            created_at: value["created_at"].as_i64().unwrap_or(last_updated),
+            version: value["version"].as_i64().unwrap_or(0),
+            last_updated: last_updated,
+        };
+
+        Ok(row)
+    }
+}
+
 impl Into<json::JsonValue> for SnippetRow {
     fn into(self) -> json::JsonValue {
         object! {
-            "id" => self.id,
+            "uuid" => self.uuid,
             "name" => self.name,
             "content" => self.content,
+            "language" => self.language,
+            "description" => self.description,
+            "source" => self.source,
             "deleted" => self.deleted,
+            "created_at" => self.created_at,
+            "version" => self.version,
             "last_updated" => self.last_updated,
         }
     }
 }
 
 pub struct TagRow {
-    pub id: i64,
     pub name: String,
     pub deleted: i64,
     pub last_updated: i64,
 }
 
+impl TagRow {
+    fn from_json(value: &json::JsonValue) -> Result<TagRow, Error> {
+        let row = TagRow {
+            name: get_string(value, "name")?,
+            deleted: get_i64(value, "deleted")?,
+            last_updated: get_i64(value, "last_updated")?,
+        };
+
+        Ok(row)
+    }
+}
+
 impl Into<json::JsonValue> for TagRow {
     fn into(self) -> json::JsonValue {
         object! {
-            "id" => self.id,
             "name" => self.name,
             "deleted" => self.deleted,
             "last_updated" => self.last_updated,
@@ -45,67 +98,296 @@ impl Into<json::JsonValue> for TagRow {
 }
 
 pub struct SnippetTagRow {
-    pub id: i64,
-    pub snippet_id: i64,
-    pub tag_id: i64,
+    pub snippet_uuid: String,
+    pub tag_name: String,
     pub deleted: i64,
     pub last_updated: i64,
 }
 
+impl SnippetTagRow {
+    fn from_json(value: &json::JsonValue) -> Result<SnippetTagRow, Error> {
+        let row = SnippetTagRow {
+            snippet_uuid: get_string(value, "snippet_uuid")?,
+            tag_name: get_string(value, "tag_name")?,
+            deleted: get_i64(value, "deleted")?,
+            last_updated: get_i64(value, "last_updated")?,
+        };
+
+        Ok(row)
+    }
+}
+
 impl Into<json::JsonValue> for SnippetTagRow {
     fn into(self) -> json::JsonValue {
         object! {
-            "id" => self.id,
-            "snippet_id" => self.snippet_id,
-            "tag_id" => self.tag_id,
+            "snippet_uuid" => self.snippet_uuid,
+            "tag_name" => self.tag_name,
             "deleted" => self.deleted,
             "last_updated" => self.last_updated,
         }
     }
 }
 
-pub fn sync_data<T: ToSocketAddrs>(dest: T, domain: &str, last_synced: i64, data: &str) -> Result<(), Error> {
-    let connector = TlsConnector::builder().danger_accept_invalid_certs(true).build()
+pub struct TombstoneRow {
+    pub snippet_uuid: String,
+    pub name: String,
+    pub last_updated: i64,
+}
+
+impl TombstoneRow {
+    fn from_json(value: &json::JsonValue) -> Result<TombstoneRow, Error> {
+        let row = TombstoneRow {
+            snippet_uuid: get_string(value, "snippet_uuid")?,
+            name: get_string(value, "name")?,
+            last_updated: get_i64(value, "last_updated")?,
+        };
+
+        Ok(row)
+    }
+}
+
+impl Into<json::JsonValue> for TombstoneRow {
+    fn into(self) -> json::JsonValue {
+        object! {
+            "snippet_uuid" => self.snippet_uuid,
+            "name" => self.name,
+            "last_updated" => self.last_updated,
+        }
+    }
+}
+
+fn get_i64(value: &json::JsonValue, key: &str) -> Result<i64, Error> {
+    value[key].as_i64()
+        .ok_or(format_err!("missing or invalid field: {}", key))
+}
+
+fn get_string(value: &json::JsonValue, key: &str) -> Result<String, Error> {
+    value[key].as_str()
+        .map(|x| x.to_string())
+        .ok_or(format_err!("missing or invalid field: {}", key))
+}
+
+// parse every row of a table in the server response. Missing tables
+// contain no changes
+fn parse_rows<T, F>(response: &json::JsonValue, table: &str, parse: F) -> Result<Vec<T>, Error>
+    where F: Fn(&json::JsonValue) -> Result<T, Error> {
+    let mut rows = Vec::new();
+
+    for value in response[table].members() {
+        let row = parse(value)
+            .context(format!("failed to parse {} row", table))?;
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+pub struct Credentials {
+    pub token: String,
+    pub refresh_token: String,
+    pub expires: i64,
+}
+
+fn connect(config: &SyncConfig) -> Result<TlsStream<TcpStream>, Error> {
+    let mut builder = TlsConnector::builder();
+
+    if config.insecure {
+        builder.danger_accept_invalid_certs(true);
+    }
+
+    // trust the CA of a self-hosted server
+    if let Some(ref path) = config.ca_file {
+        let mut pem = String::new();
+        fs::File::open(path)
+            .and_then(|mut x| x.read_to_string(&mut pem))
+            .context(format!("failed to read CA file: {:?}", path))?;
+
+        let blocks = pem_blocks(pem.as_str());
+
+        if blocks.is_empty() {
+            return Err(format_err!("no certificate in CA file: {:?}", path));
+        }
+
+        // only the first certificate of a PEM string is parsed
+        for block in blocks {
+            let cert = Certificate::from_pem(block.as_bytes())
+                .context("failed to parse CA certificate")?;
+
+            builder.add_root_certificate(cert);
+        }
+    }
+
+    let connector = builder.build()
         .context("failed to create TLS connector")?;
 
-    let stream = TcpStream::connect(dest)
+    let stream = TcpStream::connect(config.server.as_str())
+        .map_err(connection_failed)
         .context("failed to connect to remote host")?;
 
-    let duration = Duration::new(60, 0);
+    let duration = Duration::new(config.timeout, 0);
     stream.set_read_timeout(Some(duration))
         .context("failed to set read timeout")?;
     stream.set_write_timeout(Some(duration))
-        .context("failed to set read timeout")?;
+        .context("failed to set write timeout")?;
 
-    let mut stream = BufStream::new(connector.connect(domain, stream)
-        .context("failed to perform TLS handshake")?);
+    let stream = connector.connect(config.domain.as_str(), stream)
+        .context("failed to perform TLS handshake")?;
 
-    let token = "dummy";
-    let data_size = data.len();
+    if let Some(ref pin) = config.pin {
+        check_pin(&stream, pin.as_str())
+            .context("failed to verify certificate pin")?;
+    }
 
-    let upload_data = format!(
-        "{}\n{}\n{}\n{}\n",
-        token,
-        last_synced,
-        data_size,
-        data);
+    Ok(stream)
+}
+
+// the certificates of a PEM bundle, each with its BEGIN and END lines
+fn pem_blocks(pem: &str) -> Vec<&str> {
+    static BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    static END: &str = "-----END CERTIFICATE-----";
+
+    let mut blocks = Vec::new();
+    let mut rest = pem;
+
+    while let Some(start) = rest.find(BEGIN) {
+        let end = match rest[start..].find(END) {
+            Some(x) => start + x + END.len(),
+            None => break,
+        };
+
+        blocks.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+
+    blocks
+}
+
+fn check_pin(stream: &TlsStream<TcpStream>, pin: &str) -> Result<(), Error> {
+    let cert = stream.peer_certificate()
+        .context("failed to get server certificate")?
+        .ok_or(format_err!("server did not send a certificate"))?;
+
+    let der = cert.to_der()
+        .context("failed to encode server certificate")?;
 
-    stream.write_all(upload_data.as_bytes())
+    let fingerprint = Sha256::digest(der.as_slice()).iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<String>();
+
+    // allow the common colon separated notation
+    let pin = pin.replace(":", "").to_lowercase();
+
+    if fingerprint != pin {
+        return Err(ErrorKind::PinMismatch(fingerprint).into());
+    }
+
+    Ok(())
+}
+
+// network errors keep their cause but are all reported as connection failures
+fn connection_failed(e: io::Error) -> Context<ErrorKind> {
+    e.context(ErrorKind::SyncConnectionFailed)
+}
+
+// send a request to the sync server and return its parsed response
+fn request(config: &SyncConfig, data: &str) -> Result<json::JsonValue, Error> {
+    let mut stream = BufStream::new(connect(config)?);
+
+    stream.write_all(data.as_bytes())
+        .map_err(connection_failed)
         .context("failed to send data to upstream server")?;
 
     stream.flush()
+        .map_err(connection_failed)
         .context("failed to flush stream")?;
 
     let mut buf = String::new();
     stream.read_line(&mut buf)
+        .map_err(connection_failed)
         .context("server is not reponding")?;
 
     let response = json::parse(buf.as_str())
         .context("failed to parse server response")?;
 
+    // typed errors so callers can react to them
+    match response["code"].as_str() {
+        Some("token_expired") => return Err(ErrorKind::TokenExpired.into()),
+        Some("unauthorized") => return Err(ErrorKind::SyncAuthFailed.into()),
+        _ => (),
+    }
+
     if let Some(err) = response["error"].as_str() {
-        bail!(err.to_string())
+        return Err(ErrorKind::SyncServerError(err.to_string()).into());
     }
 
-    Ok(())
+    Ok(response)
+}
+
+fn parse_credentials(response: &json::JsonValue) -> Result<Credentials, Error> {
+    let token = response["token"].as_str()
+        .ok_or(format_err!("missing token"))?;
+    let refresh_token = response["refresh_token"].as_str()
+        .ok_or(format_err!("missing refresh token"))?;
+    let expires = response["expires"].as_i64()
+        .ok_or(format_err!("missing token expiry time"))?;
+
+    let credentials = Credentials {
+        token: token.to_string(),
+        refresh_token: refresh_token.to_string(),
+        expires: expires,
+    };
+
+    Ok(credentials)
+}
+
+pub fn login(config: &SyncConfig, user: &str, password: &str) -> Result<Credentials, Error> {
+    // the request fields are separated by newlines
+    if user.contains(['\n', '\r']) || password.contains(['\n', '\r']) {
+        return Err(ErrorKind::InvalidArgument("user name and password must not contain line breaks".to_string()).into());
+    }
+
+    let login_data = format!("LOGIN\n{}\n{}\n", user, password);
+
+    let response = request(config, login_data.as_str())
+        .context("failed to log in")?;
+
+    let credentials = parse_credentials(&response)
+        .context("invalid login response")?;
+
+    Ok(credentials)
+}
+
+pub fn refresh(config: &SyncConfig, refresh_token: &str) -> Result<Credentials, Error> {
+    let refresh_data = format!("REFRESH\n{}\n", refresh_token);
+
+    let response = request(config, refresh_data.as_str())
+        .context("failed to refresh token")?;
+
+    let credentials = parse_credentials(&response)
+        .context("invalid refresh response")?;
+
+    Ok(credentials)
+}
+
+// Upload the local changes and return the remote changes since the last sync
+// along with the sync time of the server to ask for the changes since next
+// time. Servers from before sync times send none.
+pub fn sync_data(config: &SyncConfig, token: &str, last_synced: i64, data: &str) -> Result<(SyncData, Option<i64>), Error> {
+    let data_size = data.len();
+
+    let upload_data = format!(
+        "{}\n{}\n{}\n{}\n",
+        token,
+        last_synced,
+        data_size,
+        data);
+
+    let response = request(config, upload_data.as_str())?;
+
+    let snippets = parse_rows(&response, "snippets", SnippetRow::from_json)?;
+    let tags = parse_rows(&response, "tags", TagRow::from_json)?;
+    let snippet_tags = parse_rows(&response, "snippet_tags", SnippetTagRow::from_json)?;
+    let tombstones = parse_rows(&response, "tombstones", TombstoneRow::from_json)?;
+
+    Ok(((snippets, tags, snippet_tags, tombstones), response["sync_time"].as_i64()))
 }
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use failure::*;
+use error::ErrorKind;
+
+// A `{{name}}` or `{{name:default}}` placeholder in the content of a snippet.
+// Names consist of letters, digits, `_` and `-`, anything else between
+// braces is left alone so `${{ github.sha }}` and friends keep working.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placeholder {
+    pub name: String,
+    pub default: Option<String>,
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(Placeholder),
+}
+
+// every placeholder once, in the order of their first appearance. The first
+// default given for a name is used for all of its occurrences
+pub fn placeholders(content: &str) -> Vec<Placeholder> {
+    let mut placeholders: Vec<Placeholder> = Vec::new();
+
+    for part in parse(content) {
+        if let Part::Placeholder(placeholder) = part {
+            match placeholders.iter_mut().find(|x| x.name == placeholder.name) {
+                Some(known) => {
+                    if known.default.is_none() {
+                        known.default = placeholder.default;
+                    }
+                },
+                None => placeholders.push(placeholder),
+            }
+        }
+    }
+
+    placeholders
+}
+
+// replace the placeholders with the given values or their defaults
+pub fn render(content: &str, values: &HashMap<String, String>) -> Result<String, Error> {
+    let defaults = placeholders(content);
+    let mut rendered = String::with_capacity(content.len());
+
+    for part in parse(content) {
+        match part {
+            Part::Text(text) => rendered.push_str(text),
+            Part::Placeholder(placeholder) => {
+                let default = defaults.iter()
+                    .find(|x| x.name == placeholder.name)
+                    .and_then(|x| x.default.as_ref());
+
+                let value = values.get(&placeholder.name)
+                    .or(default)
+                    .ok_or(ErrorKind::MissingVariable(placeholder.name.clone()))?;
+
+                rendered.push_str(value);
+            },
+        }
+    }
+
+    Ok(rendered)
+}
+
+// rebuild the content with the text and placeholders mapped, for turning
+// placeholders into the syntax of other tools
+pub fn transform<T, P>(content: &str, mut text: T, mut placeholder: P) -> String
+    where T: FnMut(&str) -> String, P: FnMut(&Placeholder) -> String {
+    let mut transformed = String::with_capacity(content.len());
+
+    for part in parse(content) {
+        match part {
+            Part::Text(x) => transformed.push_str(text(x).as_str()),
+            Part::Placeholder(x) => transformed.push_str(placeholder(&x).as_str()),
+        }
+    }
+
+    transformed
+}
+
+fn parse<'a>(content: &'a str) -> Vec<Part<'a>> {
+    let mut parts = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("{{") {
+        let inner = &rest[start + 2..];
+
+        let end = match inner.find("}}") {
+            Some(end) => end,
+            None => break,
+        };
+
+        match parse_placeholder(&inner[..end]) {
+            Some(placeholder) => {
+                parts.push(Part::Text(&rest[..start]));
+                parts.push(Part::Placeholder(placeholder));
+                rest = &inner[end + 2..];
+            },
+            // keep the braces as text and look for the next placeholder
+            None => {
+                parts.push(Part::Text(&rest[..start + 2]));
+                rest = inner;
+            },
+        }
+    }
+
+    parts.push(Part::Text(rest));
+
+    parts
+}
+
+fn parse_placeholder(inner: &str) -> Option<Placeholder> {
+    let mut split = inner.splitn(2, ':');
+    let name = split.next().unwrap_or("").trim();
+
+    if name.is_empty() || !name.chars().all(|x| x.is_alphanumeric() || x == '_' || x == '-') {
+        return None;
+    }
+
+    Some(Placeholder {
+        name: name.to_string(),
+        default: split.next().map(|x| x.to_string()),
+    })
+}
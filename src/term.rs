@@ -0,0 +1,114 @@
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::io::prelude::*;
+use std::{io, cmp, mem};
+use ansi_term::{Style, Colour};
+use rsm::snippet::Snippet;
+use rsm::config::DisplayConfig;
+use libc;
+use failure::*;
+
+pub fn is_a_tty() -> bool {
+    let reader = io::stdin();
+    unsafe { libc::isatty(reader.as_raw_fd()) == 1 }
+}
+
+pub fn stdout_is_a_tty() -> bool {
+    let writer = io::stdout();
+    unsafe { libc::isatty(writer.as_raw_fd()) == 1 }
+}
+
+// columns and rows of the terminal behind `fd`
+pub fn size(fd: RawFd) -> Result<(usize, usize), Error> {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } != 0 {
+        bail!("failed to get terminal size");
+    }
+
+    // some terminals do not report a size at all
+    if size.ws_col == 0 || size.ws_row == 0 {
+        return Ok((80, 24));
+    }
+
+    Ok((size.ws_col as usize, size.ws_row as usize))
+}
+
+#[cfg_attr(not(feature = "sync"), allow(dead_code))]
+pub fn read_line(prompt: &str) -> Result<String, Error> {
+    print!("{}", prompt);
+    io::stdout().flush()
+        .context("failed to flush stdout")?;
+
+    let mut buffer = String::new();
+    io::stdin().read_line(&mut buffer)
+        .context("failed to read user input")?;
+
+    Ok(buffer.trim_end_matches('\n').to_string())
+}
+
+// like `read_line` but with the prompt on stderr, for commands whose output
+// is meant to be piped
+pub fn ask(prompt: &str) -> Result<String, Error> {
+    eprint!("{}", prompt);
+    io::stderr().flush()
+        .context("failed to flush stderr")?;
+
+    let mut buffer = String::new();
+    io::stdin().read_line(&mut buffer)
+        .context("failed to read user input")?;
+
+    Ok(buffer.trim_end_matches('\n').to_string())
+}
+
+// read a line without echoing it back to the terminal
+#[cfg_attr(not(feature = "sync"), allow(dead_code))]
+pub fn read_password(prompt: &str) -> Result<String, Error> {
+    if !is_a_tty() {
+        return read_line(prompt);
+    }
+
+    let fd = io::stdin().as_raw_fd();
+    let mut term: libc::termios = unsafe { mem::zeroed() };
+
+    if unsafe { libc::tcgetattr(fd, &mut term) } != 0 {
+        bail!("failed to get terminal attributes");
+    }
+
+    let echo_term = term;
+    term.c_lflag &= !libc::ECHO;
+
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &term) } != 0 {
+        bail!("failed to disable terminal echo");
+    }
+
+    let password = read_line(prompt);
+
+    // restore echo even if reading failed
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &echo_term) };
+    println!();
+
+    password
+}
+
+pub fn header_style(display: &DisplayConfig) -> Style {
+    if display.color {
+        Style::new().underline()
+    } else {
+        Style::new()
+    }
+}
+
+// alternate the background of list rows for readability
+pub fn row_style(display: &DisplayConfig, row: usize) -> Style {
+    if display.color && row % 2 == 1 {
+        Style::new().on(Colour::Fixed(235))
+    } else {
+        Style::new()
+    }
+}
+
+pub fn get_list_col_widths(snippets: &Vec<Snippet>) -> (usize, usize, usize) {
+    snippets.iter().fold((2, 4, 4), |acc, x| {
+        (cmp::max(acc.0, x.id.to_string().len()), cmp::max(acc.1, x.tags.as_slice().join(", ").len()), cmp::max(acc.2, x.name.len()))
+    })
+}
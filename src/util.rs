@@ -1,20 +1,32 @@
-use std::os::unix::io::AsRawFd;
-use std::{io, cmp};
-use snippet::Snippet;
 use time;
-use libc;
 
-pub fn is_a_tty() -> bool {
-    let reader = io::stdin();
-    unsafe { libc::isatty(reader.as_raw_fd()) == 1 }
+pub fn get_utc_now() -> i64 {
+    time::now_utc().to_timespec().sec
 }
 
-pub fn get_list_col_widths(snippets: &Vec<Snippet>) -> (usize, usize, usize) {
-    snippets.iter().fold((2, 4, 4), |acc, x| {
-        (cmp::max(acc.0, x.id.to_string().len()), cmp::max(acc.1, x.tags.as_slice().join(", ").len()), cmp::max(acc.2, x.name.len()))
-    })
+// A yes/no answer typed by the user, prefixes like `y` count as well.
+// `None` if the answer is empty or neither.
+pub fn parse_answer(input: &str) -> Option<bool> {
+    let input = input.trim_end_matches(['\n', '\r']);
+
+    if input.is_empty() {
+        return None;
+    }
+
+    if "yes".starts_with(input) {
+        return Some(true);
+    }
+
+    if "no".starts_with(input) {
+        return Some(false);
+    }
+
+    None
 }
 
-pub fn get_utc_now() -> i64 {
-    time::now_utc().to_timespec().sec
+pub fn format_time(timestamp: i64) -> String {
+    let tm = time::at(time::Timespec::new(timestamp, 0));
+
+    // strftime only fails on invalid format strings
+    time::strftime("%Y-%m-%d %H:%M:%S", &tm).unwrap()
 }
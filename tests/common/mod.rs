@@ -0,0 +1,227 @@
+// Test harness for the sync client. A mock server accepts TLS connections on
+// a random local port with a freshly generated self-signed certificate,
+// records every request and answers with scripted replies.
+
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::fs;
+use mktemp::Temp;
+use native_tls::{Identity, TlsAcceptor, TlsStream};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::{X509, X509NameBuilder};
+use openssl::x509::extension::SubjectAlternativeName;
+use rsm::config::{SyncConfig, ConflictStrategy};
+use rsm::SnippetStore;
+
+// what the mock server does after reading a request
+pub enum Reply {
+    // answer with a line of JSON
+    Json(&'static str),
+    // send raw bytes and close the connection
+    Raw(&'static str),
+    // wait without answering
+    Stall(Duration),
+}
+
+// a request as received by the mock server
+#[derive(Debug)]
+pub enum Request {
+    Login(String, String),
+    Refresh(String),
+    Sync { token: String, last_synced: i64, data: String },
+}
+
+pub struct MockServer {
+    pub port: u16,
+    pub cert_pem: Vec<u8>,
+    requests: Arc<Mutex<Vec<Request>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    // answer one connection per reply, in order
+    pub fn start(replies: Vec<Reply>) -> MockServer {
+        let (identity, cert_pem) = self_signed_identity("localhost");
+        let acceptor = TlsAcceptor::new(identity).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        let handle = thread::spawn(move || {
+            for reply in replies {
+                let (stream, _) = listener.accept().unwrap();
+                let stream = acceptor.accept(stream).unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let request = read_request(&mut reader);
+                recorded.lock().unwrap().push(request);
+
+                let mut stream = reader.into_inner();
+                match reply {
+                    Reply::Json(json) => {
+                        stream.write_all(format!("{}\n", json).as_bytes()).unwrap();
+                    },
+                    Reply::Raw(data) => {
+                        stream.write_all(data.as_bytes()).unwrap();
+                    },
+                    Reply::Stall(duration) => thread::sleep(duration),
+                }
+
+                stream.shutdown().ok();
+            }
+        });
+
+        MockServer {
+            port: port,
+            cert_pem: cert_pem,
+            requests: requests,
+            handle: Some(handle),
+        }
+    }
+
+    // wait for all replies to be sent and return the received requests
+    pub fn finish(mut self) -> Vec<Request> {
+        self.handle.take().unwrap().join().unwrap();
+
+        let mut requests = self.requests.lock().unwrap();
+        requests.drain(..).collect()
+    }
+}
+
+fn read_line(reader: &mut BufReader<TlsStream<TcpStream>>) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+
+    line.trim_end_matches('\n').to_string()
+}
+
+fn read_request(reader: &mut BufReader<TlsStream<TcpStream>>) -> Request {
+    let first = read_line(reader);
+
+    match first.as_str() {
+        "LOGIN" => {
+            let user = read_line(reader);
+            let password = read_line(reader);
+
+            Request::Login(user, password)
+        },
+        "REFRESH" => Request::Refresh(read_line(reader)),
+        _ => {
+            let last_synced = read_line(reader).parse::<i64>().unwrap();
+            let size = read_line(reader).parse::<usize>().unwrap();
+
+            let mut data = vec![0u8; size + 1];
+            reader.read_exact(data.as_mut_slice()).unwrap();
+            data.pop();
+
+            Request::Sync {
+                token: first,
+                last_synced: last_synced,
+                data: String::from_utf8(data).unwrap(),
+            }
+        },
+    }
+}
+
+// certificate and key for `domain` as identity and PEM encoded certificate
+fn self_signed_identity(domain: &str) -> (Identity, Vec<u8>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", domain).unwrap();
+    let name = name.build();
+
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&serial.to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+
+    let san = SubjectAlternativeName::new()
+        .dns(domain)
+        .build(&builder.x509v3_context(None, None))
+        .unwrap();
+    builder.append_extension(san).unwrap();
+
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    let cert = builder.build();
+
+    let pkcs12 = Pkcs12::builder()
+        .name(domain)
+        .pkey(&key)
+        .cert(&cert)
+        .build2("test")
+        .unwrap();
+
+    let identity = Identity::from_pkcs12(pkcs12.to_der().unwrap().as_slice(), "test").unwrap();
+
+    (identity, cert.to_pem().unwrap())
+}
+
+// a PEM encoded certificate the mock server does not use
+pub fn unrelated_cert_pem() -> Vec<u8> {
+    self_signed_identity("example.com").1
+}
+
+// reply to the login every test client starts with
+pub static LOGIN_REPLY: &str = r#"{"token":"token","refresh_token":"refresh","expires":9999999999}"#;
+
+// A client with its own database. The temporary directory is removed when
+// the client is dropped.
+pub struct TestClient {
+    pub store: SnippetStore,
+    pub config: SyncConfig,
+    _dir: Temp,
+}
+
+impl TestClient {
+    pub fn new(server: &MockServer) -> TestClient {
+        let dir = Temp::new_dir().unwrap();
+        let dir_path = dir.to_path_buf();
+
+        let ca_file = dir_path.join("ca.pem");
+        fs::write(&ca_file, server.cert_pem.as_slice()).unwrap();
+
+        let config = SyncConfig {
+            server: format!("127.0.0.1:{}", server.port),
+            domain: "localhost".to_string(),
+            ca_file: Some(ca_file),
+            pin: None,
+            insecure: false,
+            conflict: ConflictStrategy::KeepBoth,
+            timeout: 1,
+        };
+
+        TestClient {
+            store: SnippetStore::open(&dir_path.join("data.db")).unwrap(),
+            config: config,
+            _dir: dir,
+        }
+    }
+
+    // a client logged in with the first reply of the server, see `LOGIN_REPLY`
+    pub fn logged_in(server: &MockServer) -> TestClient {
+        let client = TestClient::new(server);
+        client.store.login(&client.config, "test", "password").unwrap();
+
+        client
+    }
+}
@@ -0,0 +1,225 @@
+extern crate rsm;
+extern crate mktemp;
+
+use std::fs;
+use mktemp::Temp;
+use rsm::exchange::{self, Editor, Entry, Format, Source};
+use rsm::error::{self, ErrorKind};
+
+fn entries() -> Vec<Entry> {
+    vec![
+        Entry {
+            name: "git log".to_string(),
+            tags: vec!["git".to_string(), "vcs".to_string()],
+            content: "git log --oneline\n".to_string(),
+            language: Some("sh".to_string()),
+            description: Some("the history in one line per commit".to_string()),
+            source: Some("https://git-scm.com/docs/git-log".to_string()),
+            created_at: Some(1538300000),
+            updated_at: Some(1538400000),
+        },
+        Entry {
+            name: "fenced/markdown".to_string(),
+            tags: Vec::new(),
+            content: "```\nnested\n```\n".to_string(),
+            language: None,
+            description: None,
+            source: None,
+            created_at: None,
+            updated_at: Some(1538400001),
+        },
+    ]
+}
+
+#[test]
+fn json_round_trip() {
+    let data = Format::Json.serialize(&entries()).unwrap();
+    assert_eq!(Format::Json.parse(data.as_str()).unwrap(), entries());
+
+    // tags and timestamps are optional
+    let parsed = Format::Json.parse(r#"{"version": 1, "snippets": [{"name": "a", "content": "b"}]}"#).unwrap();
+    assert_eq!(parsed[0].tags.len(), 0);
+    assert_eq!(parsed[0].updated_at, None);
+
+    let e = Format::Json.parse(r#"{"version": 2, "snippets": []}"#).unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::InvalidImport("unsupported version 2".to_string())));
+}
+
+#[test]
+fn markdown_round_trip() {
+    let data = Format::Markdown.serialize(&entries()).unwrap();
+    assert!(data.contains("````\n```\nnested\n```\n````\n"));
+    assert!(data.contains("```sh\ngit log --oneline\n```\n"));
+    assert!(data.contains("Description: the history in one line per commit\n\nSource: https://git-scm.com/docs/git-log\n"));
+
+    // timestamps are not part of the bundle
+    let parsed = Format::Markdown.parse(data.as_str()).unwrap();
+    let expected = entries().into_iter()
+        .map(|x| Entry { created_at: None, updated_at: None, ..x })
+        .collect::<Vec<Entry>>();
+    assert_eq!(parsed, expected);
+
+    // sections without code are skipped
+    let parsed = Format::Markdown.parse("## notes\n\nnothing here\n\n## ls\n\n```sh\nls -la\n```\n").unwrap();
+    assert_eq!(parsed.len(), 1);
+    assert_eq!((parsed[0].name.as_str(), parsed[0].content.as_str()), ("ls", "ls -la\n"));
+    assert_eq!(parsed[0].language, Some("sh".to_string()));
+}
+
+#[test]
+fn directory_round_trip() {
+    let dir = Temp::new_dir().unwrap();
+    let path = dir.to_path_buf();
+
+    exchange::directory::write(&path, &entries()).unwrap();
+    assert!(path.join("fenced_markdown.txt").is_file());
+
+    fs::write(path.join("plain.txt"), "echo plain\n").unwrap();
+    fs::write(path.join(".hidden"), "ignored").unwrap();
+
+    let mut expected = entries();
+    expected.push(Entry {
+        name: "plain".to_string(),
+        tags: Vec::new(),
+        content: "echo plain\n".to_string(),
+        language: None,
+        description: None,
+        source: None,
+        created_at: None,
+        updated_at: None,
+    });
+
+    let mut parsed = exchange::directory::read(&path).unwrap();
+    parsed.sort_by(|a, b| a.name.cmp(&b.name));
+    expected.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(parsed, expected);
+    assert_eq!(Format::detect(&path), Format::Directory);
+}
+
+#[test]
+fn imports_pet_and_navi() {
+    let mut skipped = Vec::new();
+
+    let pet = r#"
+[[snippets]]
+  description = "ping a host"
+  command = "ping -c <count=3> <host> < /dev/null"
+  tag = ["network"]
+  output = ""
+
+[[snippets]]
+  description = "nothing"
+  command = ""
+"#;
+
+    let parsed = exchange::pet::from_str(pet, &mut skipped).unwrap();
+    assert_eq!(parsed, vec![Entry {
+        name: "ping a host".to_string(),
+        tags: vec!["network".to_string()],
+        content: "ping -c {{count:3}} {{host}} < /dev/null\n".to_string(),
+        language: None,
+        description: None,
+        source: None,
+        created_at: None,
+        updated_at: None,
+    }]);
+    assert_eq!(skipped, vec!["'nothing' has no command".to_string()]);
+
+    let navi = "% git, vcs\n\n# Change branch\ngit checkout <branch>\n\n$ branch: git branch\n\n; a comment\ngit status\n\n# Show log\ngit log \\\n  --oneline\n";
+
+    skipped.clear();
+    let parsed = exchange::navi::from_str(navi, &mut skipped).unwrap();
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0].content, "git checkout {{branch}}\n");
+    assert_eq!(parsed[0].tags, vec!["git".to_string(), "vcs".to_string()]);
+    assert_eq!((parsed[1].name.as_str(), parsed[1].content.as_str()), ("Show log", "git log \\\n  --oneline\n"));
+    assert_eq!(skipped, vec!["'git status' has no description".to_string()]);
+}
+
+#[test]
+fn imports_vscode_and_tldr() {
+    let dir = Temp::new_dir().unwrap();
+    let path = dir.to_path_buf();
+
+    fs::write(path.join("rust.json"), r#"{
+  // comments and trailing commas are fine
+  "Print": {
+    "prefix": "pr",
+    "body": ["println!(\"${1:value} {}\", $2);", "$0"],
+    "description": "print a value",
+  },
+  "Choice": {"body": "${1|a,b|} ${TM_FILENAME} \\$HOME", "scope": "rust, toml"},
+}"#).unwrap();
+
+    let (parsed, skipped) = Source::Vscode.read(&path).unwrap();
+    assert!(skipped.is_empty());
+    assert_eq!(parsed, vec![
+        Entry {
+            name: "Choice".to_string(),
+            tags: vec!["rust".to_string(), "toml".to_string()],
+            content: "{{1:a}} {{TM_FILENAME}} $HOME\n".to_string(),
+            language: None,
+            description: None,
+            source: None,
+            created_at: None,
+            updated_at: None,
+        },
+        Entry {
+            name: "Print".to_string(),
+            tags: vec!["rust".to_string()],
+            content: "println!(\"{{1:value}} {}\", {{2}});\n".to_string(),
+            language: Some("rust".to_string()),
+            description: Some("print a value".to_string()),
+            source: None,
+            created_at: None,
+            updated_at: None,
+        },
+    ]);
+
+    let page = "# tar\n\n> Archiving utility.\n> More information: <https://www.gnu.org/software/tar>.\n\n- Create an archive from files:\n\n`tar cf {{target.tar}} {{path/to/file}}`\n\n`tar --help`\n";
+
+    let mut skipped = Vec::new();
+    let parsed = exchange::tldr::from_str(page, &mut skipped).unwrap();
+    assert_eq!(parsed, vec![Entry {
+        name: "tar: Create an archive from files".to_string(),
+        tags: vec!["tar".to_string()],
+        content: "tar cf {{target_tar}} {{path_to_file}}\n".to_string(),
+        language: None,
+        description: Some("Archiving utility.".to_string()),
+        source: Some("https://www.gnu.org/software/tar".to_string()),
+        created_at: None,
+        updated_at: None,
+    }]);
+    assert_eq!(skipped, vec!["'tar --help' has no description".to_string()]);
+}
+
+#[test]
+fn exports_editor_snippets() {
+    let entries = vec![Entry {
+        name: "Git Log".to_string(),
+        tags: Vec::new(),
+        content: "git log {{rev:HEAD}} -- {{path}} {{rev}} # $`}\n".to_string(),
+        language: None,
+        description: Some("show the log".to_string()),
+        source: None,
+        created_at: None,
+        updated_at: None,
+    }];
+
+    let data = Editor::Vscode.serialize(&entries).unwrap();
+    assert!(data.contains(r#""prefix": "git-log""#));
+    assert!(data.contains(r#""description": "show the log""#));
+    assert!(data.contains(r#""git log ${1:HEAD} -- ${2:path} $1 # \\$`}""#));
+
+    let data = Editor::Ultisnips.serialize(&entries).unwrap();
+    assert_eq!(data, "snippet git-log \"Git Log\"\ngit log ${1:HEAD} -- ${2:path} $1 # \\$\\`}\nendsnippet\n\n");
+
+    let dir = Temp::new_dir().unwrap();
+    let path = dir.to_path_buf();
+
+    Editor::Sublime.write(&path, &entries).unwrap();
+    let data = fs::read_to_string(path.join("git-log.sublime-snippet")).unwrap();
+    assert!(data.contains("<content><![CDATA[git log ${1:HEAD} -- ${2:path} $1 # \\$`}]]></content>"));
+
+    assert!(Editor::Yasnippet.serialize(&entries).is_err());
+}
@@ -0,0 +1,24 @@
+extern crate rsm;
+
+use rsm::fuzzy;
+
+#[test]
+fn matches_characters_in_order() {
+    assert!(fuzzy::score("gst", "git status").is_some());
+    assert!(fuzzy::score("GIT ST", "git status").is_some());
+    assert!(fuzzy::score("", "anything").is_some());
+
+    assert_eq!(fuzzy::score("tsg", "git status"), None);
+    assert_eq!(fuzzy::score("git logs", "git log"), None);
+}
+
+#[test]
+fn prefers_tight_matches() {
+    let word_starts = fuzzy::score("gs", "git status").unwrap();
+    let scattered = fuzzy::score("gs", "progress").unwrap();
+    assert!(word_starts > scattered);
+
+    let consecutive = fuzzy::score("stat", "upstate").unwrap();
+    let gaps = fuzzy::score("stat", "upsetting data").unwrap();
+    assert!(consecutive > gaps);
+}
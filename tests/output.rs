@@ -0,0 +1,42 @@
+extern crate mktemp;
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use mktemp::Temp;
+
+// runs the rsm binary with its data and config in `home`
+fn rsm(home: &Temp, args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rsm"))
+        .args(args)
+        .env("HOME", home.to_path_buf())
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("XDG_DATA_HOME")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    output
+}
+
+#[test]
+fn csv_and_tsv_columns() {
+    let home = Temp::new_dir().unwrap();
+    rsm(&home, &["add", "-l", "bash", "-d", "list all", "-s", "man ls", "ls"], "ls -la");
+
+    let output = rsm(&home, &["list", "--format", "csv"], "");
+    let output = String::from_utf8(output.stdout).unwrap();
+    let lines = output.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "id,name,tags,content,language,description,source,created_at,updated_at");
+    assert!(lines[1].starts_with("1,ls,,ls -la,bash,list all,man ls,"));
+
+    let output = rsm(&home, &["list", "--format", "tsv"], "");
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.lines().next(), Some("id\tname\ttags\tcontent\tlanguage\tdescription\tsource\tcreated_at\tupdated_at"));
+}
@@ -0,0 +1,435 @@
+extern crate rsm;
+#[macro_use]
+extern crate failure;
+extern crate mktemp;
+
+use std::fs;
+use std::collections::HashMap;
+use mktemp::Temp;
+use rsm::{SnippetStore, Storage, SqliteStorage, MemoryStorage, Modification, Duplicates, Imported};
+use rsm::exchange::Entry;
+use rsm::snippet;
+use rsm::error::{self, ErrorKind};
+
+// Every check runs against both storages, which have to behave the same
+macro_rules! storage_tests {
+    ($($name:ident),*) => {
+        mod sqlite {
+            use super::*;
+
+            $(
+                #[test]
+                fn $name() {
+                    let dir = Temp::new_dir().unwrap();
+                    let storage = SqliteStorage::open(&dir.to_path_buf().join("rsm.db")).unwrap();
+
+                    super::$name(storage);
+                }
+            )*
+        }
+
+        mod memory {
+            use super::*;
+
+            $(
+                #[test]
+                fn $name() {
+                    super::$name(MemoryStorage::in_memory().unwrap());
+                }
+            )*
+        }
+    };
+}
+
+storage_tests!(
+    saves_snippets,
+    rejects_duplicate_names,
+    rejects_renames_to_taken_names,
+    lists_by_name_and_tags,
+    keeps_revisions,
+    trashes_and_restores,
+    holds_back_unsynced_deletions,
+    ranks_name_matches_first,
+    rejects_malformed_queries,
+    rolls_back_failed_transactions,
+    stores_metadata,
+    looks_up_ids_and_names,
+    remembers_variables,
+    imports_with_duplicates,
+    detects_languages,
+    describes_snippets
+);
+
+fn names(snippets: Vec<snippet::Snippet>) -> Vec<String> {
+    snippets.into_iter().map(|x| x.name).collect()
+}
+
+fn saves_snippets<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let snippet_id = store.add("hello".to_string(), "world".to_string(), Some(vec!["a", "b"]), None, None, None).unwrap();
+    let snippet = store.get(snippet_id).unwrap();
+
+    assert_eq!(snippet.name, "hello");
+    assert_eq!(snippet.content, "world");
+    assert_eq!(snippet.tags, vec!["a".to_string(), "b".to_string()]);
+    assert!(snippet.updated_at > 0);
+
+    let e = store.get(snippet_id + 1).unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::UnknownSnippetId));
+}
+
+fn rejects_duplicate_names<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let snippet_id = store.add("hello".to_string(), "world".to_string(), None, None, None, None).unwrap();
+    let e = store.add("hello".to_string(), "again".to_string(), None, None, None, None).unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::DupSnippetName));
+
+    store.delete(snippet_id).unwrap();
+    let e = store.add("hello".to_string(), "again".to_string(), None, None, None, None).unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::DeletedSnippetName));
+}
+
+fn rejects_renames_to_taken_names<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let hello = store.add("hello".to_string(), "world".to_string(), None, None, None, None).unwrap();
+    let other = store.add("other".to_string(), "thing".to_string(), None, None, None, None).unwrap();
+
+    let e = store.modify(other, Modification::Name("hello".to_string())).unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::DupSnippetName));
+
+    store.delete(hello).unwrap();
+    let e = store.modify(other, Modification::Name("hello".to_string())).unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::DeletedSnippetName));
+    assert_eq!(store.get(other).unwrap().name, "other");
+
+    // keeping the name is not a conflict
+    store.modify(other, Modification::Name("other".to_string())).unwrap();
+}
+
+fn lists_by_name_and_tags<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let first = store.add("git log".to_string(), "git log --oneline".to_string(), Some(vec!["git"]), None, None, None).unwrap();
+    store.add("git status".to_string(), "git status -s".to_string(), Some(vec!["git", "status"]), None, None, None).unwrap();
+    store.add("disk usage".to_string(), "du -sh".to_string(), None, None, None, None).unwrap();
+
+    assert_eq!(names(store.list(None, None).unwrap()), vec!["git log", "git status", "disk usage"]);
+    assert_eq!(names(store.list(Some("GIT%".to_string()), None).unwrap()), vec!["git log", "git status"]);
+    assert_eq!(names(store.list(Some("disk_usage".to_string()), None).unwrap()), vec!["disk usage"]);
+    assert_eq!(names(store.list(None, Some(vec!["stat%"])).unwrap()), vec!["git status"]);
+    assert_eq!(names(store.list(Some("disk%".to_string()), Some(vec!["status"])).unwrap()), vec!["git status", "disk usage"]);
+
+    // removed tags no longer match
+    store.modify(first, Modification::RemoveTags(vec!["git"])).unwrap();
+    assert_eq!(names(store.list(None, Some(vec!["git"])).unwrap()), vec!["git status"]);
+    assert!(store.get(first).unwrap().tags.is_empty());
+}
+
+fn keeps_revisions<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let snippet_id = store.add("hello".to_string(), "one".to_string(), None, None, None, None).unwrap();
+    store.modify(snippet_id, Modification::Content("two".to_string())).unwrap();
+    store.modify(snippet_id, Modification::Name("bye".to_string())).unwrap();
+
+    let history = store.history(snippet_id).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].revision, 1);
+    assert_eq!(history[0].old_value, "one");
+    assert_eq!(history[1].new_value, "bye");
+
+    let original = store.get_revision(snippet_id, 0).unwrap();
+    assert_eq!((original.name.as_str(), original.content.as_str()), ("hello", "one"));
+
+    let e = store.get_revision(snippet_id, 3).unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::UnknownRevision));
+
+    store.revert(snippet_id, 1).unwrap();
+    let snippet = store.get(snippet_id).unwrap();
+    assert_eq!((snippet.name.as_str(), snippet.content.as_str()), ("hello", "two"));
+    assert_eq!(store.history(snippet_id).unwrap().len(), 3);
+}
+
+fn trashes_and_restores<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let snippet_id = store.add("hello".to_string(), "world".to_string(), Some(vec!["a"]), None, None, None).unwrap();
+    store.delete(snippet_id).unwrap();
+
+    assert!(store.list(None, None).unwrap().is_empty());
+    assert!(store.get(snippet_id).is_err());
+
+    let trash = store.trash().unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].0.name, "hello");
+
+    // the tags come back with the snippet
+    store.restore(snippet_id).unwrap();
+    assert_eq!(store.get(snippet_id).unwrap().tags, vec!["a".to_string()]);
+    assert!(store.restore(snippet_id).is_err());
+
+    // nothing waits for a server that was never used
+    store.delete(snippet_id).unwrap();
+    assert_eq!(store.purge(None).unwrap(), (1, 0));
+    assert!(store.trash().unwrap().is_empty());
+}
+
+fn holds_back_unsynced_deletions<S: Storage>(storage: S) {
+    storage.set_metadata_value("last_uploaded", "1").unwrap();
+    let store = SnippetStore::new(storage);
+
+    // deletions after the last sync are kept until they reached the server
+    let snippet_id = store.add("hello".to_string(), "world".to_string(), None, None, None, None).unwrap();
+    store.delete(snippet_id).unwrap();
+
+    let outcome = store.purge(None).unwrap();
+    assert_eq!(outcome, if cfg!(feature = "sync") { (0, 1) } else { (1, 0) });
+    assert_eq!(store.trash().unwrap().len(), outcome.1);
+}
+
+fn ranks_name_matches_first<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    store.add("prune images".to_string(), "docker image prune".to_string(), None, None, None, None).unwrap();
+    store.add("docker cleanup".to_string(), "remove stopped containers".to_string(), None, None, None, None).unwrap();
+    store.add("disk usage".to_string(), "du -sh".to_string(), None, None, None, None).unwrap();
+
+    let matches = store.search("docker").unwrap();
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[0].snippet.name, "docker cleanup");
+    assert_eq!(matches[1].excerpt, format!("{}docker{} image prune", snippet::MATCH_START, snippet::MATCH_END));
+
+    let matches = store.search("contain*").unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].snippet.name, "docker cleanup");
+}
+
+fn rejects_malformed_queries<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+    store.add("echo".to_string(), "echo hello".to_string(), None, None, None, None).unwrap();
+
+    for query in &["echo AND", "\"unterminated", "nocolumn:echo"] {
+        let e = match store.search(query) { Err(e) => e, Ok(_) => panic!("query accepted: {}", query) };
+        match error::kind(&e) {
+            Some(ErrorKind::InvalidArgument(message)) => assert!(message.contains("invalid search query")),
+            x => panic!("unexpected error kind: {:?}", x),
+        }
+        assert_eq!(error::exit_code(&e), 2);
+    }
+}
+
+fn rolls_back_failed_transactions<S: Storage>(storage: S) {
+    let result: Result<(), failure::Error> = storage.transaction(|storage| {
+        storage.save_snippet("hello".to_string(), "world".to_string(), None)?;
+        bail!("failed on purpose")
+    });
+    assert!(result.is_err());
+
+    assert!(storage.search_snippets(None, None).unwrap().is_empty());
+}
+
+fn stores_metadata<S: Storage>(storage: S) {
+    assert_eq!(storage.get_metadata_value("last_synced").unwrap(), "0");
+    assert_eq!(storage.find_metadata_value("missing").unwrap(), None);
+    assert!(storage.get_metadata_value("missing").is_err());
+
+    storage.set_metadata_value("key", "one").unwrap();
+    storage.set_metadata_value("key", "two").unwrap();
+    assert_eq!(storage.get_metadata_value("key").unwrap(), "two");
+}
+
+fn looks_up_ids_and_names<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let git_log = store.add("git log".to_string(), "git log --oneline".to_string(), None, None, None, None).unwrap();
+    let git_status = store.add("git status".to_string(), "git status -s".to_string(), None, None, None, None).unwrap();
+    let disk_usage = store.add("disk usage".to_string(), "du -sh".to_string(), None, None, None, None).unwrap();
+    let numbered = store.add("99".to_string(), "".to_string(), None, None, None, None).unwrap();
+    store.add(git_log.to_string(), "".to_string(), None, None, None, None).unwrap();
+
+    assert_eq!(store.lookup(git_status.to_string().as_str()).unwrap(), git_status);
+    assert_eq!(store.lookup("git log").unwrap(), git_log);
+    assert_eq!(store.lookup("DISK").unwrap(), disk_usage);
+    assert_eq!(store.lookup("gstat").unwrap(), git_status);
+
+    // ids come before names
+    assert_eq!(store.lookup(git_log.to_string().as_str()).unwrap(), git_log);
+    assert_eq!(store.lookup("99").unwrap(), numbered);
+
+    let e = store.lookup("git").unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::AmbiguousSnippetName(
+        "git".to_string(),
+        format!("{} 'git log', {} 'git status'", git_log, git_status))));
+
+    let e = store.lookup("docker").unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::UnknownSnippetId));
+
+    // deleted snippets are only found in the trash
+    store.delete(disk_usage).unwrap();
+    assert!(store.lookup("disk usage").is_err());
+    assert_eq!(store.lookup_deleted("disk").unwrap(), disk_usage);
+}
+
+fn remembers_variables<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let snippet_id = store.add("ssh".to_string(), "ssh {{user}}@{{host}}".to_string(), None, None, None, None).unwrap();
+    assert!(store.variables(snippet_id).unwrap().is_empty());
+
+    let mut values = HashMap::new();
+    values.insert("user".to_string(), "root".to_string());
+    values.insert("host".to_string(), "example.com".to_string());
+    store.remember_variables(snippet_id, &values).unwrap();
+
+    // only the given values are replaced
+    let mut changed = HashMap::new();
+    changed.insert("host".to_string(), "example.org".to_string());
+    store.remember_variables(snippet_id, &changed).unwrap();
+
+    let remembered = store.variables(snippet_id).unwrap();
+    assert_eq!(remembered.len(), 2);
+    assert_eq!(remembered["user"], "root");
+    assert_eq!(remembered["host"], "example.org");
+
+    // and go away with the snippet
+    store.delete(snippet_id).unwrap();
+    store.purge(None).unwrap();
+}
+
+fn imports_with_duplicates<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let existing = store.add("ls".to_string(), "ls".to_string(), Some(vec!["old"]), None, None, None).unwrap();
+    let deleted = store.add("rm".to_string(), "rm".to_string(), None, None, None, None).unwrap();
+    store.delete(deleted).unwrap();
+
+    let entry = |name: &str, content: &str| Entry {
+        name: name.to_string(),
+        tags: vec!["new".to_string()],
+        content: content.to_string(),
+        language: None,
+        description: None,
+        source: None,
+        created_at: None,
+        updated_at: Some(1),
+    };
+    let entries = vec![entry("ls", "ls -la"), entry("rm", "rm -i"), entry("du", "du -sh")];
+
+    let outcomes = store.import(entries.clone(), Duplicates::Skip).unwrap();
+    assert_eq!(outcomes[0].1, Imported::Skipped(ErrorKind::DupSnippetName));
+    assert_eq!(outcomes[1].1, Imported::Skipped(ErrorKind::DeletedSnippetName));
+    assert!(matches!(outcomes[2].1, Imported::Added(_)));
+
+    let outcomes = store.import(entries.clone(), Duplicates::Rename).unwrap();
+    let names = outcomes.into_iter()
+        .map(|x| match x.1 { Imported::Renamed(_, name) => name, _ => panic!("not renamed") })
+        .collect::<Vec<String>>();
+    assert_eq!(names, vec!["ls (2)", "rm (2)", "du (2)"]);
+
+    // the trash is never overwritten
+    let outcomes = store.import(entries[..2].to_vec(), Duplicates::Overwrite).unwrap();
+    assert_eq!(outcomes[0].1, Imported::Replaced(existing));
+    assert_eq!(outcomes[1].1, Imported::Skipped(ErrorKind::DeletedSnippetName));
+
+    let snippet = store.get(existing).unwrap();
+    assert_eq!((snippet.content.as_str(), snippet.tags.clone()), ("ls -la", vec!["new".to_string()]));
+
+    // later entries with the same name count as duplicates as well
+    let before = store.export(None, None).unwrap().len();
+    let outcomes = store.import(vec![entry("df", "df"), entry("df", "df -h")], Duplicates::Overwrite).unwrap();
+    assert!(matches!(outcomes[1].1, Imported::Replaced(_)));
+    assert_eq!(store.export(None, None).unwrap().len(), before + 1);
+}
+
+fn detects_languages<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let script = store.add("clean".to_string(), "#!/usr/bin/env python3 -u\nprint(1)\n".to_string(), None, None, None, None).unwrap();
+    let named = store.add("backup.sh".to_string(), "tar cf - .".to_string(), None, None, None, None).unwrap();
+    let plain = store.add("notes".to_string(), "nothing to see".to_string(), None, None, None, None).unwrap();
+
+    assert_eq!(store.get(script).unwrap().language, Some("python".to_string()));
+    assert_eq!(store.get(named).unwrap().language, Some("sh".to_string()));
+    assert_eq!(store.get(plain).unwrap().language, None);
+
+    // setting the language counts as a change so it is synced
+    let updated_at = store.get(named).unwrap().updated_at;
+    store.modify(named, Modification::Language(Some("bash".to_string()))).unwrap();
+    assert_eq!(store.list(None, None).unwrap()[1].language, Some("bash".to_string()));
+    assert!(store.get(named).unwrap().updated_at >= updated_at);
+
+    store.modify(named, Modification::Language(None)).unwrap();
+    assert_eq!(store.get(named).unwrap().language, None);
+}
+
+fn describes_snippets<S: Storage>(storage: S) {
+    let store = SnippetStore::new(storage);
+
+    let snippet_id = store.add("free".to_string(), "free -h".to_string(), None, None, None, None).unwrap();
+    store.add("memory hog".to_string(), "ps aux --sort=-rss | head".to_string(), None, None, None, None).unwrap();
+
+    let snippet = store.get(snippet_id).unwrap();
+    assert_eq!((snippet.description, snippet.source), (None, None));
+    assert_eq!(snippet.created_at, snippet.updated_at);
+
+    store.modify(snippet_id, Modification::Description(Some("show the memory usage".to_string()))).unwrap();
+    store.modify(snippet_id, Modification::Source(Some("https://man7.org/linux/man-pages/man1/free.1.html".to_string()))).unwrap();
+    store.modify(snippet_id, Modification::Content("free -m".to_string())).unwrap();
+
+    let snippet = store.get(snippet_id).unwrap();
+    assert_eq!(snippet.description, Some("show the memory usage".to_string()));
+    assert_eq!(snippet.source, Some("https://man7.org/linux/man-pages/man1/free.1.html".to_string()));
+    assert!(snippet.created_at <= snippet.updated_at);
+
+    // descriptions rank below names but above contents
+    let matches = store.search("memory").unwrap();
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[0].snippet.name, "memory hog");
+    assert_eq!(matches[1].snippet.id, snippet_id);
+    assert_eq!(store.search("man7").unwrap().len(), 1);
+
+    store.modify(snippet_id, Modification::Description(None)).unwrap();
+    assert_eq!(store.get(snippet_id).unwrap().description, None);
+    assert_eq!(store.search("usage").unwrap().len(), 0);
+
+    // everything given is saved along with the snippet
+    let described = store.add("disk free".to_string(), "df -h".to_string(), None,
+                              Some("bash".to_string()), Some("free disk space".to_string()), Some("man df".to_string())).unwrap();
+    let snippet = store.get(described).unwrap();
+    assert_eq!(snippet.language, Some("bash".to_string()));
+    assert_eq!(snippet.description, Some("free disk space".to_string()));
+    assert_eq!(snippet.source, Some("man df".to_string()));
+
+    let entry = Entry {
+        name: "uptime".to_string(),
+        tags: Vec::new(),
+        content: "uptime".to_string(),
+        language: None,
+        description: Some("how long the system has been running".to_string()),
+        source: None,
+        created_at: Some(1),
+        updated_at: Some(1),
+    };
+
+    let outcomes = store.import(vec![entry], Duplicates::Skip).unwrap();
+    let imported = match outcomes[0].1 { Imported::Added(x) => store.get(x).unwrap(), _ => panic!("not added") };
+    assert_eq!(imported.description, Some("how long the system has been running".to_string()));
+
+    // the creation time is restored, the update time is left to the next sync
+    assert_eq!(imported.created_at, 1);
+    assert!(imported.updated_at > 1);
+}
+
+#[test]
+fn damaged_database_is_typed() {
+    let dir = Temp::new_dir().unwrap();
+    let db_path = dir.to_path_buf().join("rsm.db");
+    fs::write(&db_path, "not a database, just some text that is long enough to fill a header").unwrap();
+
+    let e = SnippetStore::open(&db_path).err().unwrap();
+    assert_eq!(error::kind(&e), Some(ErrorKind::DatabaseCorrupt));
+    assert_eq!(error::exit_code(&e), 20);
+}
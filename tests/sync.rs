@@ -0,0 +1,407 @@
+#![cfg(feature = "sync")]
+
+extern crate rsm;
+extern crate failure;
+extern crate mktemp;
+extern crate native_tls;
+extern crate openssl;
+extern crate json;
+
+mod common;
+
+use std::fs;
+use std::time::Duration;
+use mktemp::Temp;
+use rsm::{SnippetStore, Modification};
+use rsm::error::{self, ErrorKind};
+use rsm::config::ConflictStrategy;
+use common::{MockServer, Reply, Request, TestClient, LOGIN_REPLY};
+
+static EMPTY_RESPONSE: &str = r#"{"snippets":[],"tags":[],"snippet_tags":[],"tombstones":[]}"#;
+
+fn has_cause(e: &failure::Error, message: &str) -> bool {
+    e.iter_chain().any(|x| x.to_string().contains(message))
+}
+
+#[test]
+fn login_saves_credentials() {
+    let server = MockServer::start(vec![
+        Reply::Json(r#"{"token":"abc","refresh_token":"def","expires":9999999999}"#),
+        Reply::Json(EMPTY_RESPONSE),
+    ]);
+    let client = TestClient::new(&server);
+
+    client.store.login(&client.config, "alice", "secret").unwrap();
+    client.store.sync(&client.config).unwrap();
+
+    let requests = server.finish();
+    match requests[0] {
+        Request::Login(ref user, ref password) => {
+            assert_eq!(user, "alice");
+            assert_eq!(password, "secret");
+        },
+        ref x => panic!("unexpected request: {:?}", x),
+    }
+
+    match requests[1] {
+        Request::Sync { ref token, .. } => assert_eq!(token, "abc"),
+        ref x => panic!("unexpected request: {:?}", x),
+    }
+}
+
+#[test]
+fn login_rejects_line_breaks() {
+    let server = MockServer::start(vec![]);
+    let client = TestClient::new(&server);
+
+    let e = client.store.login(&client.config, "alice\nSYNC", "secret").unwrap_err();
+    server.finish();
+
+    match error::kind(&e) {
+        Some(ErrorKind::InvalidArgument(_)) => (),
+        x => panic!("unexpected error kind: {:?}", x),
+    }
+}
+
+#[test]
+fn trusts_every_certificate_of_a_ca_bundle() {
+    let server = MockServer::start(vec![Reply::Json(LOGIN_REPLY)]);
+    let mut client = TestClient::new(&server);
+
+    // the certificate of the server comes second
+    let mut bundle = common::unrelated_cert_pem();
+    bundle.extend_from_slice(server.cert_pem.as_slice());
+
+    let dir = Temp::new_dir().unwrap();
+    let ca_file = dir.to_path_buf().join("bundle.pem");
+    fs::write(&ca_file, bundle.as_slice()).unwrap();
+    client.config.ca_file = Some(ca_file);
+
+    client.store.login(&client.config, "test", "password").unwrap();
+    server.finish();
+}
+
+#[test]
+fn sync_requires_login() {
+    let server = MockServer::start(vec![]);
+    let client = TestClient::new(&server);
+
+    let e = client.store.sync(&client.config).unwrap_err();
+    server.finish();
+
+    assert_eq!(error::kind(&e), Some(ErrorKind::NotLoggedIn));
+}
+
+#[test]
+fn uploads_local_changes() {
+    let server = MockServer::start(vec![Reply::Json(LOGIN_REPLY), Reply::Json(EMPTY_RESPONSE)]);
+    let client = TestClient::logged_in(&server);
+
+    let snippet_id = client.store.add("hello".to_string(), "world".to_string(), Some(vec!["greeting"]), None, None, None).unwrap();
+    client.store.modify(snippet_id, Modification::Description(Some("say hello".to_string()))).unwrap();
+    client.store.modify(snippet_id, Modification::Language(Some("sh".to_string()))).unwrap();
+
+    let summary = client.store.sync(&client.config).unwrap();
+    assert_eq!(summary.uploaded, 1);
+    assert_eq!(summary.received, 0);
+    assert_eq!(summary.conflicts, 0);
+
+    let requests = server.finish();
+    assert_eq!(requests.len(), 2);
+
+    let (token, since, data) = match requests[1] {
+        Request::Sync { ref token, last_synced, ref data } => (token, last_synced, data),
+        ref x => panic!("unexpected request: {:?}", x),
+    };
+
+    assert_eq!(token, "token");
+    assert_eq!(since, 0);
+
+    let data = json::parse(data.as_str()).unwrap();
+    assert_eq!(data["snippets"].len(), 1);
+    assert_eq!(data["snippets"][0]["name"], "hello");
+    assert_eq!(data["snippets"][0]["content"], "world");
+    assert_eq!(data["snippets"][0]["deleted"], 0);
+    assert_eq!(data["snippets"][0]["language"], "sh");
+    assert_eq!(data["snippets"][0]["description"], "say hello");
+    assert!(data["snippets"][0]["source"].is_null());
+    assert!(data["snippets"][0]["created_at"].as_i64().unwrap() > 0);
+    // local ids never leave the database
+    assert!(data["snippets"][0]["id"].is_null());
+    assert_eq!(data["snippets"][0]["uuid"].as_str().unwrap().len(), 36);
+    assert_eq!(data["tags"][0]["name"], "greeting");
+    assert_eq!(data["snippet_tags"][0]["snippet_uuid"], data["snippets"][0]["uuid"]);
+    assert_eq!(data["snippet_tags"][0]["tag_name"], "greeting");
+    assert_eq!(data["tombstones"].len(), 0);
+
+    assert!(client.store.last_synced().unwrap() > 0);
+    assert_eq!(client.store.get(snippet_id).unwrap().name, "hello");
+}
+
+#[test]
+fn applies_remote_changes() {
+    // the response has to fit on a single line
+    let server = MockServer::start(vec![Reply::Json(LOGIN_REPLY), Reply::Json(concat!(
+        r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"remote","content":"from server","language":"python","deleted":0,"last_updated":10}],"#,
+        r#""tags":[{"name":"shared","deleted":0,"last_updated":10}],"#,
+        r#""snippet_tags":[{"snippet_uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","tag_name":"shared","deleted":0,"last_updated":10}],"#,
+        r#""tombstones":[]}"#))]);
+    let client = TestClient::logged_in(&server);
+
+    let summary = client.store.sync(&client.config).unwrap();
+    server.finish();
+
+    assert_eq!(summary.received, 1);
+
+    let snippets = client.store.list(None, None).unwrap();
+    assert_eq!(snippets.len(), 1);
+    assert_eq!(snippets[0].name, "remote");
+    assert_eq!(snippets[0].content, "from server");
+    assert_eq!(snippets[0].tags, vec!["shared".to_string()]);
+    assert_eq!(snippets[0].language, Some("python".to_string()));
+    // servers from before creation times send none
+    assert_eq!((snippets[0].description.clone(), snippets[0].created_at), (None, 10));
+}
+
+#[test]
+fn server_sync_time_is_used() {
+    // the server clock runs far ahead of the local one
+    let server = MockServer::start(vec![
+        Reply::Json(LOGIN_REPLY),
+        Reply::Json(r#"{"snippets":[],"tags":[],"snippet_tags":[],"tombstones":[],"sync_time":9999999999}"#),
+        Reply::Json(EMPTY_RESPONSE),
+    ]);
+    let client = TestClient::logged_in(&server);
+
+    client.store.sync(&client.config).unwrap();
+    assert_eq!(client.store.last_synced().unwrap(), 9999999999);
+
+    // local changes are still picked by the local clock
+    client.store.add("hello".to_string(), "world".to_string(), None, None, None, None).unwrap();
+    assert_eq!(client.store.sync(&client.config).unwrap().uploaded, 1);
+
+    let requests = server.finish();
+    match requests[2] {
+        Request::Sync { last_synced, .. } => assert_eq!(last_synced, 9999999999),
+        ref x => panic!("unexpected request: {:?}", x),
+    }
+}
+
+#[test]
+fn error_response_fails_sync() {
+    let server = MockServer::start(vec![Reply::Json(LOGIN_REPLY), Reply::Json(r#"{"error":"storage is full"}"#)]);
+    let client = TestClient::logged_in(&server);
+
+    let e = client.store.sync(&client.config).unwrap_err();
+    server.finish();
+
+    assert!(has_cause(&e, "storage is full"));
+    assert_eq!(error::kind(&e), Some(ErrorKind::SyncServerError("storage is full".to_string())));
+    assert_eq!(client.store.last_synced().unwrap(), 0);
+}
+
+#[test]
+fn unauthorized_response_is_typed() {
+    let server = MockServer::start(vec![
+        Reply::Json(LOGIN_REPLY),
+        Reply::Json(r#"{"error":"invalid credentials","code":"unauthorized"}"#),
+    ]);
+    let client = TestClient::logged_in(&server);
+
+    let e = client.store.sync(&client.config).unwrap_err();
+    server.finish();
+
+    assert_eq!(error::kind(&e), Some(ErrorKind::SyncAuthFailed));
+    assert_eq!(client.store.last_synced().unwrap(), 0);
+}
+
+#[test]
+fn expired_token_is_refreshed_once() {
+    let server = MockServer::start(vec![
+        Reply::Json(LOGIN_REPLY),
+        Reply::Json(r#"{"error":"token expired","code":"token_expired"}"#),
+        Reply::Json(r#"{"token":"fresh","refresh_token":"refresh2","expires":9999999999}"#),
+        Reply::Json(EMPTY_RESPONSE),
+    ]);
+    let client = TestClient::logged_in(&server);
+
+    client.store.sync(&client.config).unwrap();
+
+    let requests = server.finish();
+    assert_eq!(requests.len(), 4);
+
+    match requests[2] {
+        Request::Refresh(ref token) => assert_eq!(token, "refresh"),
+        ref x => panic!("unexpected request: {:?}", x),
+    }
+
+    match requests[3] {
+        Request::Sync { ref token, .. } => assert_eq!(token, "fresh"),
+        ref x => panic!("unexpected request: {:?}", x),
+    }
+}
+
+#[test]
+fn timeout_fails_sync() {
+    let server = MockServer::start(vec![Reply::Json(LOGIN_REPLY), Reply::Stall(Duration::from_secs(3))]);
+    let client = TestClient::logged_in(&server);
+
+    client.store.add("local".to_string(), "content".to_string(), None, None, None, None).unwrap();
+
+    let e = client.store.sync(&client.config).unwrap_err();
+    server.finish();
+
+    assert!(has_cause(&e, "server is not reponding"));
+    assert_eq!(error::kind(&e), Some(ErrorKind::SyncConnectionFailed));
+    assert_eq!(client.store.last_synced().unwrap(), 0);
+}
+
+#[test]
+fn truncated_reply_is_not_applied() {
+    let server = MockServer::start(vec![
+        Reply::Json(LOGIN_REPLY),
+        Reply::Raw(r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"remote","#),
+    ]);
+    let client = TestClient::logged_in(&server);
+
+    let e = client.store.sync(&client.config).unwrap_err();
+    server.finish();
+
+    assert!(has_cause(&e, "failed to parse server response"));
+    assert_eq!(client.store.last_synced().unwrap(), 0);
+    assert!(client.store.list(None, None).unwrap().is_empty());
+}
+
+#[test]
+fn invalid_rows_are_not_applied() {
+    let server = MockServer::start(vec![
+        Reply::Json(LOGIN_REPLY),
+        Reply::Json(r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"remote","deleted":0,"last_updated":10}]}"#),
+    ]);
+    let client = TestClient::logged_in(&server);
+
+    let e = client.store.sync(&client.config).unwrap_err();
+    server.finish();
+
+    assert!(has_cause(&e, "missing or invalid field: content"));
+    assert_eq!(client.store.last_synced().unwrap(), 0);
+}
+
+#[test]
+fn keep_both_uploads_remote_version_again() {
+    let server = MockServer::start(vec![
+        Reply::Json(LOGIN_REPLY),
+        Reply::Json(concat!(
+            r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"shared","content":"first","deleted":0,"last_updated":10}],"#,
+            r#""tags":[],"snippet_tags":[],"tombstones":[]}"#)),
+        Reply::Json(concat!(
+            r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"shared","content":"remote edit","deleted":0,"last_updated":20}],"#,
+            r#""tags":[],"snippet_tags":[],"tombstones":[]}"#)),
+        Reply::Json(EMPTY_RESPONSE),
+    ]);
+    let client = TestClient::logged_in(&server);
+
+    client.store.sync(&client.config).unwrap();
+    let snippet_id = client.store.list(None, None).unwrap()[0].id;
+
+    // both machines edit the same snippet before syncing
+    client.store.modify(snippet_id, Modification::Content("local edit".to_string())).unwrap();
+
+    let summary = client.store.sync(&client.config).unwrap();
+    assert_eq!(summary.conflicts, 1);
+    assert_eq!(client.store.get(snippet_id).unwrap().content, "remote edit");
+
+    client.store.sync(&client.config).unwrap();
+    let requests = server.finish();
+
+    let uploaded = |i: usize| match requests[i] {
+        Request::Sync { ref data, .. } => json::parse(data.as_str()).unwrap(),
+        ref x => panic!("unexpected request: {:?}", x),
+    };
+
+    let second = uploaded(2);
+    assert_eq!(second["snippets"].len(), 1);
+    assert_eq!(second["snippets"][0]["content"], "local edit");
+
+    // the server kept the local edit, the remote one has to replace it
+    let third = uploaded(3);
+    let contents: Vec<_> = third["snippets"].members()
+        .filter(|x| x["uuid"] == "7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11")
+        .map(|x| x["content"].to_string())
+        .collect();
+    assert_eq!(contents, vec!["remote edit".to_string()]);
+}
+
+#[test]
+fn last_writer_wins_by_version() {
+    let server = MockServer::start(vec![
+        Reply::Json(LOGIN_REPLY),
+        Reply::Json(concat!(
+            r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"shared","content":"first","deleted":0,"version":1,"last_updated":10}],"#,
+            r#""tags":[],"snippet_tags":[],"tombstones":[]}"#)),
+        // a clock running ahead does not make the remote edit win
+        Reply::Json(concat!(
+            r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"shared","content":"remote edit","deleted":0,"version":2,"last_updated":9999999999}],"#,
+            r#""tags":[],"snippet_tags":[],"tombstones":[]}"#)),
+        // the server keeps its row on equal versions
+        Reply::Json(concat!(
+            r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"shared","content":"second remote edit","deleted":0,"version":4,"last_updated":20}],"#,
+            r#""tags":[],"snippet_tags":[],"tombstones":[]}"#)),
+    ]);
+    let mut client = TestClient::logged_in(&server);
+    client.config.conflict = ConflictStrategy::LastWriterWins;
+
+    client.store.sync(&client.config).unwrap();
+    let snippet_id = client.store.list(None, None).unwrap()[0].id;
+
+    client.store.modify(snippet_id, Modification::Content("local edit".to_string())).unwrap();
+    client.store.modify(snippet_id, Modification::Description(Some("edited twice".to_string()))).unwrap();
+
+    assert_eq!(client.store.sync(&client.config).unwrap().conflicts, 1);
+    assert_eq!(client.store.get(snippet_id).unwrap().content, "local edit");
+
+    client.store.modify(snippet_id, Modification::Content("another local edit".to_string())).unwrap();
+
+    assert_eq!(client.store.sync(&client.config).unwrap().conflicts, 1);
+    assert_eq!(client.store.get(snippet_id).unwrap().content, "second remote edit");
+
+    let requests = server.finish();
+    let versions: Vec<_> = requests[2..].iter()
+        .map(|x| match *x {
+            Request::Sync { ref data, .. } => json::parse(data.as_str()).unwrap()["snippets"][0]["version"].as_i64().unwrap(),
+            ref x => panic!("unexpected request: {:?}", x),
+        })
+        .collect();
+    assert_eq!(versions, vec![3, 4]);
+}
+
+#[test]
+fn memory_store_syncs() {
+    let server = MockServer::start(vec![Reply::Json(LOGIN_REPLY), Reply::Json(concat!(
+        r#"{"snippets":[{"uuid":"7d2b4f3e-1c6a-4f7e-9a53-0e5f2a9c4b11","name":"local","content":"from server","deleted":0,"last_updated":10}],"#,
+        r#""tags":[],"snippet_tags":[],"tombstones":[]}"#))]);
+    let client = TestClient::new(&server);
+    let store = SnippetStore::in_memory().unwrap();
+
+    store.login(&client.config, "alice", "secret").unwrap();
+    store.add("local".to_string(), "content".to_string(), Some(vec!["tag"]), None, None, None).unwrap();
+
+    let summary = store.sync(&client.config).unwrap();
+    let requests = server.finish();
+
+    assert_eq!(summary.uploaded, 1);
+    assert_eq!(summary.received, 1);
+
+    let data = match requests[1] {
+        Request::Sync { ref data, .. } => json::parse(data.as_str()).unwrap(),
+        ref x => panic!("unexpected request: {:?}", x),
+    };
+    assert_eq!(data["snippets"][0]["uuid"].as_str().unwrap().len(), 36);
+    assert_eq!(data["snippet_tags"][0]["tag_name"], "tag");
+
+    // the new remote snippet collides with the local one
+    let snippets = store.list(None, None).unwrap();
+    assert_eq!(snippets.len(), 2);
+    assert_eq!(snippets[1].name, "local (2)");
+    assert_eq!(snippets[1].content, "from server");
+}
@@ -0,0 +1,42 @@
+extern crate rsm;
+
+use std::collections::HashMap;
+use rsm::template::{self, Placeholder};
+use rsm::error::{self, ErrorKind};
+
+fn placeholder(name: &str, default: Option<&str>) -> Placeholder {
+    Placeholder {
+        name: name.to_string(),
+        default: default.map(|x| x.to_string()),
+    }
+}
+
+#[test]
+fn finds_placeholders() {
+    let content = "ssh -p {{port:22}} {{ user }}@{{host}} && echo {{host:localhost}}";
+
+    assert_eq!(template::placeholders(content), vec![
+        placeholder("port", Some("22")),
+        placeholder("user", None),
+        placeholder("host", Some("localhost")),
+    ]);
+
+    // not a valid name, left as it is
+    assert!(template::placeholders("echo ${{ github.sha }} {{}} {{").is_empty());
+}
+
+#[test]
+fn renders_values_and_defaults() {
+    let content = "curl {{host}}:{{port:8080}}/${{ path }}";
+    let mut values = HashMap::new();
+    values.insert("host".to_string(), "example.com".to_string());
+
+    let e = template::render(content, &values).unwrap_err();
+    assert_eq!(error::kind(&e), Some(ErrorKind::MissingVariable("path".to_string())));
+
+    values.insert("path".to_string(), "index.html".to_string());
+    assert_eq!(template::render(content, &values).unwrap(), "curl example.com:8080/$index.html");
+
+    values.insert("port".to_string(), "80".to_string());
+    assert_eq!(template::render(content, &values).unwrap(), "curl example.com:80/$index.html");
+}
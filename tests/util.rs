@@ -0,0 +1,19 @@
+extern crate rsm;
+
+use rsm::util;
+
+#[test]
+fn parses_answers() {
+    assert_eq!(util::parse_answer("yes\n"), Some(true));
+    assert_eq!(util::parse_answer("y\r\n"), Some(true));
+    assert_eq!(util::parse_answer("no\n"), Some(false));
+    // the last line before the end of input has no newline
+    assert_eq!(util::parse_answer("n"), Some(false));
+}
+
+#[test]
+fn asks_again_on_empty_answers() {
+    assert_eq!(util::parse_answer("\n"), None);
+    assert_eq!(util::parse_answer(""), None);
+    assert_eq!(util::parse_answer("maybe\n"), None);
+}